@@ -0,0 +1,89 @@
+// Compares the two ways of flattening curves, CurveFlattening::ForwardDifferencing (the
+// Bezier32 and Bezier64 crackers) and CurveFlattening::Scanlines. For each path it prints
+// the number of triangle list vertices, trapezoids and complex scans that are output, and
+// the time it takes to rasterize the path.
+//
+// Run with `cargo bench --bench flatten`.
+
+use std::time::{Duration, Instant};
+
+use wpf_gpu_raster::{CoverageSpan, CurveFlattening, GeometrySink, PathBuilder, Trapezoid};
+
+const CLIP_SIZE: i32 = 1000;
+
+#[derive(Default)]
+struct CountingSink {
+    trapezoids: usize,
+    scans: usize,
+}
+
+impl GeometrySink for CountingSink {
+    fn add_trapezoid(&mut self, _trapezoid: &Trapezoid) {
+        self.trapezoids += 1;
+    }
+
+    fn add_complex_scan(&mut self, _y: i32, _spans: &[CoverageSpan]) {
+        self.scans += 1;
+    }
+}
+
+fn circles(radius: f32, count: usize) -> PathBuilder {
+    let mut path = PathBuilder::new();
+    for i in 0..count {
+        let x = (i % 10) as f32 * CLIP_SIZE as f32 / 10. + radius + 0.3;
+        let y = (i / 10) as f32 * CLIP_SIZE as f32 / 10. + radius + 0.7;
+        path.add_circle(x, y, radius);
+    }
+    path
+}
+
+fn waves() -> PathBuilder {
+    let mut path = PathBuilder::new();
+    path.move_to(0., 500.);
+    for i in 0..20 {
+        let x = i as f32 * 50.;
+        path.curve_to(x + 15., 100., x + 35., 900., x + 50., 500.);
+    }
+    path.line_to(1000., 1000.);
+    path.line_to(0., 1000.);
+    path.close();
+    path
+}
+
+fn time(path: &PathBuilder) -> Duration {
+    // Run for at least 200ms and report the time per rasterization
+    let start = Instant::now();
+    let mut iterations = 0;
+    while start.elapsed() < Duration::from_millis(200) {
+        std::hint::black_box(path.rasterize_to_tri_list(0, 0, CLIP_SIZE, CLIP_SIZE));
+        iterations += 1;
+    }
+    start.elapsed() / iterations
+}
+
+fn main() {
+    let paths = [
+        ("100 circles, r = 4", circles(4., 100)),
+        ("100 circles, r = 40", circles(40., 100)),
+        ("circle, r = 450", circles(450., 1)),
+        // big enough that Bezier64 does the forward differencing
+        ("clipped circle, r = 5000", {
+            let mut path = PathBuilder::new();
+            path.add_circle(500., 5400., 5000.);
+            path
+        }),
+        ("20 waves", waves()),
+    ];
+
+    println!("{:<26} {:<20} {:>9} {:>11} {:>9} {:>12}", "path", "flattening", "vertices", "trapezoids", "scans", "time");
+    for (name, mut path) in paths {
+        for flattening in [CurveFlattening::ForwardDifferencing, CurveFlattening::Scanlines] {
+            path.set_curve_flattening(flattening);
+            let vertices = path.rasterize_to_tri_list(0, 0, CLIP_SIZE, CLIP_SIZE).len();
+            let mut sink = CountingSink::default();
+            path.rasterize_to_sink(0, 0, CLIP_SIZE, CLIP_SIZE, &mut sink).unwrap();
+            println!("{:<26} {:<20} {:>9} {:>11} {:>9} {:>12?}",
+                     name, format!("{:?}", flattening), vertices, sink.trapezoids, sink.scans, time(&path));
+        }
+    }
+}
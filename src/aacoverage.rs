@@ -8,7 +8,7 @@
 
 use std::cell::Cell;
 
-use typed_arena_nomut::Arena;
+use crate::arena::Arena;
 
 //
 //  Description:
@@ -23,9 +23,10 @@ use crate::types::*;
 
 //-------------------------------------------------------------------------
 //
-// TrapezoidalAA only supports 8x8 mode, so the shifts/masks are all
-// constants.  Also, since we must be symmetrical, x and y shifts are
-// merged into one shift unlike the implementation in aarasterizer.
+// The c_* constants describe the default 8x8 subpixel grid. Other grids
+// are described by CSubpixelGrid at runtime.  Since we must be symmetrical,
+// x and y shifts are merged into one shift unlike the implementation in
+// aarasterizer.
 //
 //-------------------------------------------------------------------------
 
@@ -39,6 +40,56 @@ pub const c_nShiftMask: INT = 7;
 pub const c_rInvShiftSize: f32 = 1.0/8.0;
 pub const c_antiAliasMode: MilAntiAliasMode = MilAntiAliasMode::EightByEight;
 
+//-------------------------------------------------------------------------
+//
+//  Class: CSubpixelGrid
+//
+//  Description:
+//      The shifts/masks for the subpixel grid of an antialiasing mode.
+//      A grid of nShiftSize x nShiftSize samples is used for each pixel.
+//
+//-------------------------------------------------------------------------
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CSubpixelGrid
+{
+    pub nShift: INT,
+    pub nShiftSize: INT,
+    pub nShiftSizeSquared: INT,
+    pub nHalfShiftSize: INT,
+    pub nShiftMask: INT,
+    pub rInvShiftSize: f32,
+}
+
+impl CSubpixelGrid {
+    pub const fn new(nShift: INT) -> Self {
+        let nShiftSize = 1 << nShift;
+        CSubpixelGrid {
+            nShift,
+            nShiftSize,
+            nShiftSizeSquared: nShiftSize * nShiftSize,
+            nHalfShiftSize: nShiftSize / 2,
+            nShiftMask: nShiftSize - 1,
+            rInvShiftSize: 1.0 / nShiftSize as f32,
+        }
+    }
+
+    pub fn FromAntiAliasMode(antiAliasMode: MilAntiAliasMode) -> Self {
+        match antiAliasMode {
+            MilAntiAliasMode::FourByFour => Self::new(2),
+            MilAntiAliasMode::EightByEight => Self::new(c_nShift),
+            MilAntiAliasMode::SixteenBySixteen => Self::new(4),
+            // Aliased rendering takes a single sample at the pixel center
+            MilAntiAliasMode::None => Self::new(0),
+        }
+    }
+}
+
+impl Default for CSubpixelGrid {
+    fn default() -> Self {
+        Self::FromAntiAliasMode(c_antiAliasMode)
+    }
+}
+
 //
 // Interval coverage descriptor for our antialiased filler
 //
@@ -75,7 +126,7 @@ impl<'a> Default for CCoverageInterval<'a> {
 // Allocator structure for the antialiased fill interval data
 //
 
-struct CCoverageIntervalBuffer<'a>
+pub struct CCoverageIntervalBuffer<'a>
 {
     m_pNext: Cell<Option<& 'a CCoverageIntervalBuffer<'a>>>,
     m_interval: [CCoverageInterval<'a>; INTERVAL_BUFFER_NUMBER],
@@ -184,7 +235,10 @@ public:*/
     m_pIntervalBufferBuiltin: CCoverageIntervalBuffer<'a>,
     m_pIntervalBufferCurrent: Cell<Ref<'a, CCoverageIntervalBuffer<'a>>>,
 
-    arena: Arena<CCoverageIntervalBuffer<'a>>
+    arena: Arena<CCoverageIntervalBuffer<'a>>,
+
+    // The subpixel grid that intervals are added in
+    m_grid: Cell<CSubpixelGrid>,
        
     // Disable instrumentation checks within all methods of this class
     //SET_MILINSTRUMENTATION_FLAGS(MILINSTRUMENTATIONFLAGS_DONOTHING);
@@ -201,6 +255,7 @@ impl<'a> Default for CCoverageBuffer<'a> {
             m_pIntervalBufferCurrent: unsafe { Cell::new(Ref::null()) },
             arena: Arena::new(),
             interval_new_index: Cell::new(0),
+            m_grid: Cell::new(Default::default()),
         }
     }
 }
@@ -230,6 +285,7 @@ pub fn AddInterval(&'a self, nSubpixelXLeft: INT, nSubpixelXRight: INT) -> HRESU
     let mut pIntervalNew = self.m_pIntervalNew.get();
     let mut interval_new_index = self.interval_new_index.get();
     let mut pIntervalEndMinus4 = self.m_pIntervalEndMinus4.get();
+    let grid = self.m_grid.get();
 
     // Make sure we have enough room to add two intervals if
     // necessary:
@@ -243,8 +299,8 @@ pub fn AddInterval(&'a self, nSubpixelXLeft: INT, nSubpixelXRight: INT) -> HRESU
     // into the coverage buffer
 
     debug_assert!(nSubpixelXLeft < nSubpixelXRight);
-    nPixelXLeft = nSubpixelXLeft >> c_nShift;
-    nPixelXRight = nSubpixelXRight >> c_nShift; 
+    nPixelXLeft = nSubpixelXLeft >> grid.nShift;
+    nPixelXRight = nSubpixelXRight >> grid.nShift;
 
     // Try to resume searching from the last searched interval.
     if self.m_pIntervalLast.get().m_nPixelX.get() < nPixelXLeft {
@@ -294,7 +350,7 @@ pub fn AddInterval(&'a self, nSubpixelXLeft: INT, nSubpixelXRight: INT) -> HRESU
     //  |_____|_____|_____|
     //
 
-    nCoverageLeft = c_nShiftSize - (nSubpixelXLeft & c_nShiftMask);
+    nCoverageLeft = grid.nShiftSize - (nSubpixelXLeft & grid.nShiftMask);
 
     // If nCoverageLeft == 0, then the value of nPixelXLeft is wrong
     // and should have been equal to nPixelXLeft+1.
@@ -303,7 +359,7 @@ pub fn AddInterval(&'a self, nSubpixelXLeft: INT, nSubpixelXRight: INT) -> HRESU
     // If we have partial coverage, then ensure that we have a position
     // for the end of the pixel 
 
-    if ((nCoverageLeft < c_nShiftSize || (nPixelXLeft == nPixelXRight))
+    if ((nCoverageLeft < grid.nShiftSize || (nPixelXLeft == nPixelXRight))
         && nPixelXLeft + 1 != pInterval.m_pNext.get().m_nPixelX.get())
     {
         pIntervalNew.m_nPixelX.set(nPixelXLeft + 1);
@@ -324,7 +380,7 @@ pub fn AddInterval(&'a self, nSubpixelXLeft: INT, nSubpixelXRight: INT) -> HRESU
     if (nPixelXLeft == nPixelXRight)
     {
         pInterval.m_nCoverage.set(pInterval.m_nCoverage.get() + nSubpixelXRight - nSubpixelXLeft);
-        debug_assert!(pInterval.m_nCoverage.get() <= c_nShiftSize*c_nShiftSize);
+        debug_assert!(pInterval.m_nCoverage.get() <= grid.nShiftSizeSquared);
         //goto Cleanup;
 
         //Cleanup:
@@ -336,7 +392,7 @@ pub fn AddInterval(&'a self, nSubpixelXLeft: INT, nSubpixelXRight: INT) -> HRESU
 
     // Update coverage of current interval
     pInterval.m_nCoverage.set(pInterval.m_nCoverage.get() + nCoverageLeft);
-    debug_assert!(pInterval.m_nCoverage.get() <= c_nShiftSize*c_nShiftSize);
+    debug_assert!(pInterval.m_nCoverage.get() <= grid.nShiftSizeSquared);
 
     // Increase the coverage for any intervals between 'nPixelXLeft'
     // and 'nPixelXRight':
@@ -349,8 +405,8 @@ pub fn AddInterval(&'a self, nSubpixelXLeft: INT, nSubpixelXRight: INT) -> HRESU
             break;
         }
         pInterval = nextInterval;
-        pInterval.m_nCoverage.set(pInterval.m_nCoverage.get() + c_nShiftSize);
-        debug_assert!(pInterval.m_nCoverage.get() <= c_nShiftSize*c_nShiftSize);
+        pInterval.m_nCoverage.set(pInterval.m_nCoverage.get() + grid.nShiftSize);
+        debug_assert!(pInterval.m_nCoverage.get() <= grid.nShiftSizeSquared);
     }
 
     // Remember the found interval.
@@ -361,7 +417,7 @@ pub fn AddInterval(&'a self, nSubpixelXLeft: INT, nSubpixelXRight: INT) -> HRESU
     if (nPixelXNext != nPixelXRight)
     {
         pIntervalNew.m_nPixelX.set(nPixelXRight);
-        pIntervalNew.m_nCoverage.set(pInterval.m_nCoverage.get() - c_nShiftSize);
+        pIntervalNew.m_nCoverage.set(pInterval.m_nCoverage.get() - grid.nShiftSize);
 
         pIntervalNew.m_pNext.set(pInterval.m_pNext.get());
         pInterval.m_pNext.set(pIntervalNew);
@@ -385,7 +441,7 @@ pub fn AddInterval(&'a self, nSubpixelXLeft: INT, nSubpixelXRight: INT) -> HRESU
     //  |_____|_____|_____|
     //
 
-    nCoverageRight = nSubpixelXRight & c_nShiftMask;
+    nCoverageRight = nSubpixelXRight & grid.nShiftMask;
     if (nCoverageRight > 0)
     {
         if (nPixelXRight + 1 != (*(*pInterval).m_pNext.get()).m_nPixelX.get())
@@ -401,7 +457,7 @@ pub fn AddInterval(&'a self, nSubpixelXLeft: INT, nSubpixelXRight: INT) -> HRESU
         }
 
         pInterval.m_nCoverage.set((*pInterval).m_nCoverage.get() + nCoverageRight);
-        debug_assert!(pInterval.m_nCoverage.get() <= c_nShiftSize*c_nShiftSize);
+        debug_assert!(pInterval.m_nCoverage.get() <= grid.nShiftSizeSquared);
     }
 
 //Cleanup:
@@ -539,11 +595,14 @@ pub fn FillEdgesWinding(&'a self,
 //
 //  Function:   CCoverageBuffer::Initialize
 //
-//  Synopsis:   Set the coverage buffer to a valid initial state
+//  Synopsis:   Set the coverage buffer to a valid initial state for
+//              intervals in the given subpixel grid
 // 
 //-------------------------------------------------------------------------
-pub fn Initialize(&'a self) 
+pub fn Initialize(&'a self, grid: CSubpixelGrid) 
 {
+    self.m_grid.set(grid);
+
     self.m_pIntervalBufferBuiltin.m_interval[0].m_nPixelX.set(INT::MIN);
     self.m_pIntervalBufferBuiltin.m_interval[0].m_nCoverage.set(0);
     self.m_pIntervalBufferBuiltin.m_interval[0].m_pNext.set(Ref::new(&self.m_pIntervalBufferBuiltin.m_interval[1]));
@@ -562,6 +621,35 @@ pub fn Initialize(&'a self)
     self.m_pIntervalLast.set(Ref::new(&self.m_pIntervalBufferBuiltin.m_interval[1]));
 }
 
+//-------------------------------------------------------------------------
+//
+//  Function:   CCoverageBuffer::WithChunks
+//
+//  Synopsis:   Create a coverage buffer that grows into the given interval
+//              buffers before allocating new ones
+// 
+//-------------------------------------------------------------------------
+pub fn WithChunks(chunks: Vec<Vec<CCoverageIntervalBuffer<'a>>>) -> Self
+{
+    Self {
+        arena: Arena::with_chunks(chunks),
+        ..Default::default()
+    }
+}
+
+//-------------------------------------------------------------------------
+//
+//  Function:   CCoverageBuffer::TakeChunks
+//
+//  Synopsis:   Take the interval buffers for reuse by another coverage
+//              buffer.  The coverage buffer can't be used afterwards.
+// 
+//-------------------------------------------------------------------------
+pub unsafe fn TakeChunks(&self) -> Vec<Vec<CCoverageIntervalBuffer<'a>>>
+{
+    self.arena.take_chunks()
+}
+
 //-------------------------------------------------------------------------
 //
 //  Function:   CCoverageBuffer::Destroy
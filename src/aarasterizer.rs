@@ -6,7 +6,7 @@
 
 use std::cell::Cell;
 
-use crate::aacoverage::c_nShift;
+use crate::aacoverage::{c_nShift, CSubpixelGrid};
 use crate::bezier::CMILBezier;
 use crate::helpers::Int32x32To64;
 use crate::matrix::CMILMatrix;
@@ -14,7 +14,7 @@ use crate::nullable_ref::Ref;
 use crate::real::CFloatFPU;
 //use crate::types::PathPointType::*;
 use crate::types::*;
-use typed_arena_nomut::Arena;
+use crate::arena::Arena;
 
 const S_OK: HRESULT = 0;
 
@@ -58,7 +58,7 @@ pub struct CEdge<'a> {
     pub ErrorDown: INT,        // Error decrement when the error rolls over
     pub StartY: INT,           // Y-row start
     pub EndY: INT,             // Y-row end
-    pub WindingDirection: INT, // -1 or 1
+    pub WindingDirection: INT, // -1 or 1, times the context's WindingWeight
 }
 
 impl<'a> std::default::Default for CEdge<'a> {
@@ -81,7 +81,7 @@ impl<'a> std::default::Default for CEdge<'a> {
 // we can more easily do in-place sorts on it:
 #[derive(Clone)]
 pub struct CInactiveEdge<'a> {
-    Edge: Ref<'a, CEdge<'a>>, // Associated edge
+    pub Edge: Ref<'a, CEdge<'a>>, // Associated edge
     Yx: LONGLONG,     // Sorting key, StartY and X packed into an lword
 }
 
@@ -93,6 +93,16 @@ impl<'a> Default for CInactiveEdge<'a> {
         }
     }
 }
+
+impl<'a> CInactiveEdge<'a> {
+    // An entry for pEdge with its sorting key, for filling an inactive
+    // array that is already in order without InitializeInactiveArray
+    pub fn new(pEdge: Ref<'a, CEdge<'a>>) -> Self {
+        let mut inactive = Self { Edge: pEdge, Yx: 0 };
+        YX(pEdge.X.get(), pEdge.StartY, &mut inactive.Yx);
+        inactive
+    }
+}
 macro_rules! ASSERTACTIVELISTORDER {
     ($list: expr) => {
         #[cfg(debug_assertions)]
@@ -529,6 +539,25 @@ fn AssertActiveListOrder(mut list:  Ref<CEdge>) {
 *
 \**************************************************************************/
 fn ClipEdge(edgeBuffer: &mut CEdge, yClipTopInteger: INT, dMOriginal: INT) {
+    let (x, error) = ComputeEdgeDDA(edgeBuffer, yClipTopInteger, dMOriginal);
+
+    // Update the edge data structure with the results:
+
+    edgeBuffer.StartY = yClipTopInteger;
+    edgeBuffer.X.set(x);
+    edgeBuffer.Error.set(error);
+}
+
+/**************************************************************************\
+*
+* Function Description:
+*
+*   Compute the x and error of the edge's DDA at scan-line 'y' without
+*   stepping through the scan-lines in between.  The result is exactly
+*   what stepping the DDA would give.
+*
+\**************************************************************************/
+pub fn ComputeEdgeDDA(edgeBuffer: &CEdge, y: INT, dMOriginal: INT) -> (INT, INT) {
     let mut xDelta: INT;
     let mut error: INT;
 
@@ -537,7 +566,7 @@ fn ClipEdge(edgeBuffer: &mut CEdge, yClipTopInteger: INT, dMOriginal: INT) {
     // in those cases.
 
     let dN: INT = edgeBuffer.ErrorDown;
-    let mut bigNumerator: LONGLONG = Int32x32To64(dMOriginal, yClipTopInteger - edgeBuffer.StartY)
+    let mut bigNumerator: LONGLONG = Int32x32To64(dMOriginal, y - edgeBuffer.StartY)
         + (edgeBuffer.Error.get() + dN) as LONGLONG;
     if (bigNumerator >= 0) {
         QUOTIENT_REMAINDER_64_32!(bigNumerator, dN, xDelta, error);
@@ -552,14 +581,10 @@ fn ClipEdge(edgeBuffer: &mut CEdge, yClipTopInteger: INT, dMOriginal: INT) {
         }
     }
 
-    // Update the edge data structure with the results:
-
-    edgeBuffer.StartY = yClipTopInteger;
-    edgeBuffer.X.set(edgeBuffer.X.get() + xDelta);
-    edgeBuffer.Error.set(error - dN); // Renormalize error
+    (edgeBuffer.X.get() + xDelta, error - dN) // Renormalize error
 }
 
-pub fn CheckValidRange28_4(x: f32, y: f32) -> bool {
+pub fn CheckValidRange28_4(x: f32, y: f32, nShift: INT) -> bool {
     //
     // We want coordinates in the 28.4 range in the end.  The matrix we get
     // as input includes the scale by 16 to get to 28.4, so we want to
@@ -571,10 +596,10 @@ pub fn CheckValidRange28_4(x: f32, y: f32) -> bool {
     // implementation of InitializeEdges.
     // (See line with "error -= dN * (16 - (xStart & 15))")
     //
-    // Anti-aliasing uses another c_nShift bits, so we get a
-    // desired range of -2^(26-c_nShift) to 2^(26-c_nShift)
+    // Anti-aliasing uses another nShift bits, so we get a
+    // desired range of -2^(26-nShift) to 2^(26-nShift)
     //
-    let rPixelCoordinateMax = (1 << (26 - c_nShift)) as f32;
+    let rPixelCoordinateMax = (1 << (26 - nShift)) as f32;
     let rPixelCoordinateMin = -rPixelCoordinateMax;
     return x <= rPixelCoordinateMax && x >= rPixelCoordinateMin
             && y <= rPixelCoordinateMax && y >= rPixelCoordinateMin;
@@ -586,10 +611,12 @@ pub fn CheckValidRange28_4(x: f32, y: f32) -> bool {
 //
 //  Synopsis:
 //      Transform rasterizer points to 28.4.  If overflow occurs, return that
-//      information.
+//      information.  NaNs fail with WGXERR_BADNUMBER and out of range points
+//      with WGXERR_VALUEOVERFLOW.  The index of the failing point is returned
+//      in piInvalidPoint.
 //
 //------------------------------------------------------------------------------
-fn TransformRasterizerPointsTo28_4(
+pub fn TransformRasterizerPointsTo28_4(
     pmat: &CMILMatrix,
     // Transform to take us to 28.4
     mut pPtsSource: &[MilPoint2F],
@@ -597,8 +624,10 @@ fn TransformRasterizerPointsTo28_4(
     mut cPoints: UINT,
     // Count of points
     mut pPtsDest: &mut [POINT], // Destination points
+    piInvalidPoint: &mut UINT,  // Index of the first invalid point
 ) -> HRESULT {
     let hr = S_OK;
+    let mut iPoint: UINT = 0;
 
     debug_assert!(cPoints > 0);
 
@@ -616,10 +645,16 @@ fn TransformRasterizerPointsTo28_4(
         // Check for NaNs or overflow
         //
 
-        if !CheckValidRange28_4(rPixelX, rPixelY) {
+        if rPixelX.is_nan() || rPixelY.is_nan() {
+            *piInvalidPoint = iPoint;
             return WGXERR_BADNUMBER;
         }
 
+        if !CheckValidRange28_4(rPixelX, rPixelY, c_nShift) {
+            *piInvalidPoint = iPoint;
+            return WGXERR_VALUEOVERFLOW;
+        }
+
         //
         // Assign coordinates
         //
@@ -629,6 +664,7 @@ fn TransformRasterizerPointsTo28_4(
 
         pPtsDest = &mut pPtsDest[1..];
         pPtsSource = &pPtsSource[1..];
+        iPoint += 1;
         cPoints -= 1;
         cPoints != 0
     } {}
@@ -663,11 +699,13 @@ pub struct CInitializeEdgesContext<'a> {
     pub ClipRect: Option<&'a RECT>, // Bounding clip rectangle in 28.4 format
     pub Store: &'a Arena<CEdge<'a>>,  // Where to stick the edges
     pub AntiAliasMode: MilAntiAliasMode,
+    pub WindingWeight: INT, // Scales the winding direction of the edges, lets
+    //   callers that sweep several paths at once tell their edges apart
 }
 
 impl<'a> CInitializeEdgesContext<'a> {
     pub fn new(store: &'a Arena<CEdge<'a>>) -> Self {
-        CInitializeEdgesContext { MaxY: Default::default(), ClipRect: Default::default(), Store: store, AntiAliasMode: MilAntiAliasMode::None }
+        CInitializeEdgesContext { MaxY: Default::default(), ClipRect: Default::default(), Store: store, AntiAliasMode: MilAntiAliasMode::None, WindingWeight: 1 }
     }
 }
 
@@ -711,6 +749,10 @@ fn InitializeEdges(
     let mut edgeCount = vertexCount - 1;
     assert!(edgeCount >= 1);
 
+    // The supersampling shift for the antialiasing mode.  Aliased rendering
+    // samples once per pixel so its shift is 0.
+    let nShift = CSubpixelGrid::FromAntiAliasMode(pEdgeContext.AntiAliasMode).nShift;
+
     if let Some(clipRect) = clipRect {
         yClipTopInteger = clipRect.top >> 4;
         yClipTop = clipRect.top;
@@ -722,7 +764,7 @@ fn InitializeEdges(
         assert!(yClipTop <= yClipBottom);
     } else {
         yClipBottom = 0;
-        yClipTopInteger = INT::MIN >> c_nShift;
+        yClipTopInteger = INT::MIN >> nShift;
 
         // These 3 values are only used when clipRect is non-NULL
         yClipTop = 0;
@@ -747,18 +789,18 @@ fn InitializeEdges(
         let mut i = vertexCount;
 
         while {
-            point[0].x = (point[0].x + 8) << c_nShift;
-            point[0].y = (point[0].y + 8) << c_nShift;
+            point[0].x = (point[0].x + 8) << nShift;
+            point[0].y = (point[0].y + 8) << nShift;
             point = &mut point[1..];
             i -= 1;
             i != 0
         } {}
 
-        yClipTopInteger <<= c_nShift;
-        yClipTop <<= c_nShift;
-        yClipBottom <<= c_nShift;
-        xClipLeft <<= c_nShift;
-        xClipRight <<= c_nShift;
+        yClipTopInteger <<= nShift;
+        yClipTop <<= nShift;
+        yClipBottom <<= nShift;
+        xClipLeft <<= nShift;
+        xClipRight <<= nShift;
     }
 
     // Make 'yClipBottom' inclusive by subtracting off one pixel
@@ -807,8 +849,8 @@ fn InitializeEdges(
                     yRectTop = clipRect.top >> 4;
                     yRectBottom = clipRect.bottom >> 4;
                     if (pEdgeContext.AntiAliasMode != MilAntiAliasMode::None) {
-                        yRectTop <<= c_nShift;
-                        yRectBottom <<= c_nShift;
+                        yRectTop <<= nShift;
+                        yRectBottom <<= nShift;
                     }
                     y0 = ((pointArray[0]).y + 15) >> 4;
                     y1 = ((pointArray[1]).y + 15) >> 4;
@@ -959,7 +1001,7 @@ fn InitializeEdges(
                 Error: Cell::new(error),
                 ErrorUp: errorUp,
                 ErrorDown: dN,
-                WindingDirection: windingDirection,
+                WindingDirection: windingDirection * pEdgeContext.WindingWeight,
                 StartY: yStartInteger,
                 EndY: yEndInteger,// Exclusive of end
             };
@@ -1014,7 +1056,7 @@ fn InitializeEdges(
 *   03/25/2000 andrewgo
 *
 \**************************************************************************/
-fn ValidatePathTypes(typesArray: &[BYTE], mut count: INT) -> bool {
+pub(crate) fn ValidatePathTypes(typesArray: &[BYTE], mut count: INT) -> bool {
     let mut types = typesArray;
 
     if (count == 0) {
@@ -1054,6 +1096,22 @@ fn ValidatePathTypes(typesArray: &[BYTE], mut count: INT) -> bool {
                     }
                 }
 
+                PathPointTypeQuadratic => {
+                    if (count < 2) {
+                        TraceTag!((
+                            tagMILWarning,
+                            "Path ended before multiple of 2 quadratic Bezier points"
+                        ));
+                        return (false);
+                    }
+
+                    types = &types[1..];
+                    count -= 2;
+                    if (count == 0) {
+                        return (true);
+                    }
+                }
+
                 PathPointTypeBezier => {
                     if (count < 3) {
                         TraceTag!((
@@ -1154,11 +1212,14 @@ pub fn FixedPointPathEnumerate(
     cPoints: UINT,
     _matrix: &CMILMatrix,
     clipRect: Option<&RECT>, // In scaled 28.4 format
+    rBezierTolerance: REAL,  // In pixels, see CMILBezier::new
+    fAlignBeziersToScanlines: bool, // See CMILBezier::newAlignedToScanlines
     enumerateContext: &mut CInitializeEdgesContext,
 ) -> HRESULT {
     let hr = S_OK;
     let mut bufferStart: [POINT; ENUMERATE_BUFFER_NUMBER!()] = [(); ENUMERATE_BUFFER_NUMBER!()].map(|_| Default::default());
     let mut bezierBuffer: [POINT; 4] = Default::default();
+    let mut quadraticBuffer: [POINT; 3] = Default::default();
     let mut buffer: &mut [POINT];
     let mut bufferSize: usize;
     let mut startFigure: [POINT; 1] = Default::default();
@@ -1256,19 +1317,41 @@ pub fn FixedPointPathEnumerate(
                     }
                 }
             } else {
-                assert!(iPoint + 3 <= cPoints as usize);
-                assert!((rgTypes[iType] & PathPointTypePathTypeMask) == PathPointTypeBezier);
+                let mut bezier = if ((rgTypes[iType] & PathPointTypePathTypeMask) == PathPointTypeQuadratic) {
+                    assert!(iPoint + 2 <= cPoints as usize);
+
+                    quadraticBuffer.copy_from_slice(&rgpt[(iPoint - 1) .. iPoint + 2]);
+
+                    // Prepare for the next iteration:
+
+                    iPoint += 2;
+                    iType += 1;
 
-                bezierBuffer.copy_from_slice(&rgpt[(iPoint - 1) .. iPoint + 3]);
+                    if (fAlignBeziersToScanlines) {
+                        CMILBezier::newQuadraticAlignedToScanlines(&quadraticBuffer, clipRect, rBezierTolerance)
+                    } else {
+                        CMILBezier::newQuadratic(&quadraticBuffer, clipRect, rBezierTolerance)
+                    }
+                } else {
+                    assert!(iPoint + 3 <= cPoints as usize);
+                    assert!((rgTypes[iType] & PathPointTypePathTypeMask) == PathPointTypeBezier);
+
+                    bezierBuffer.copy_from_slice(&rgpt[(iPoint - 1) .. iPoint + 3]);
 
-                // Prepare for the next iteration:
+                    // Prepare for the next iteration:
 
-                iPoint += 3;
-                iType += 1;
+                    iPoint += 3;
+                    iType += 1;
+
+                    if (fAlignBeziersToScanlines) {
+                        CMILBezier::newAlignedToScanlines(&bezierBuffer, clipRect, rBezierTolerance)
+                    } else {
+                        CMILBezier::new(&bezierBuffer, clipRect, rBezierTolerance)
+                    }
+                };
 
                 // Process the Bezier:
 
-                let mut bezier = CMILBezier::new(&bezierBuffer, clipRect);
                 loop {
                     thisCount = bezier.Flatten(buffer, &mut isMore) as usize;
 
@@ -1670,13 +1753,13 @@ pub fn InitializeInactiveArray<'a>(
 *
 \**************************************************************************/
 
-pub fn InsertNewEdges<'a>(
+pub fn InsertNewEdges<'a, 'b>(
     mut pActiveList: Ref<'a, CEdge<'a>>,
     iCurrentY: INT,
     /*__deref_inout_xcount(array terminated by an edge with StartY != iCurrentY)*/
-    ppInactiveEdge: &'a mut [CInactiveEdge<'a>],
+    ppInactiveEdge: &'b mut [CInactiveEdge<'a>],
     pYNextInactive: &mut INT, // will be INT_MAX when no more
-) -> &'a mut [CInactiveEdge<'a>] {
+) -> &'b mut [CInactiveEdge<'a>] {
 
     let mut inactive: &mut [CInactiveEdge] = ppInactiveEdge;
 
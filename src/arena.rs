@@ -0,0 +1,98 @@
+// A typed arena like typed_arena_nomut::Arena, except that its chunks can be taken back
+// once the path is rasterized so that the next path can reuse them. Values are never
+// moved once they are allocated, so they can point at each other.
+
+use std::cell::{Cell, RefCell};
+
+// The first chunk holds about this many bytes, later chunks double in size
+const INITIAL_SIZE: usize = 1024;
+
+pub struct Arena<T> {
+    chunks: RefCell<Vec<Vec<T>>>,
+    // The chunk that is being allocated from
+    current: Cell<usize>,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Self::with_chunks(Vec::new())
+    }
+
+    // Allocates into the given chunks before allocating new ones. The chunks have to be
+    // empty, like the ones returned by take_chunks.
+    pub fn with_chunks(chunks: Vec<Vec<T>>) -> Self {
+        debug_assert!(chunks.iter().all(|chunk| chunk.is_empty()));
+        Arena { chunks: RefCell::new(chunks), current: Cell::new(0) }
+    }
+
+    pub fn alloc(&self, value: T) -> &T {
+        let mut chunks = self.chunks.borrow_mut();
+        let mut current = self.current.get();
+        if chunks.len() <= current {
+            let size = std::mem::size_of::<T>().max(1);
+            chunks.push(Vec::with_capacity((INITIAL_SIZE / size).max(1)));
+        } else if chunks[current].len() == chunks[current].capacity() {
+            // Never grow a chunk, that would move the values in it
+            current += 1;
+            if chunks.len() <= current {
+                let capacity = chunks[current - 1].capacity() * 2;
+                chunks.push(Vec::with_capacity(capacity));
+            }
+            self.current.set(current);
+        }
+        let chunk = &mut chunks[current];
+        chunk.push(value);
+        let value: *const T = chunk.last().unwrap();
+        // The value stays in place until the chunks are taken or the arena is dropped,
+        // which both can't happen while it is borrowed
+        unsafe { &*value }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.borrow().iter().map(|chunk| chunk.len()).sum()
+    }
+
+    // The values in the order in which they were allocated
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        let chunks = self.chunks.borrow().len();
+        (0..chunks).flat_map(move |i| {
+            let chunk: *const [T] = self.chunks.borrow()[i].as_slice();
+            // Chunks that are in use never move or shrink
+            unsafe { &*chunk }.iter()
+        })
+    }
+
+    // Drops the values and returns the chunks for reuse by another arena.
+    //
+    // Safety: nothing that was allocated from the arena may be used afterwards.
+    pub unsafe fn take_chunks(&self) -> Vec<Vec<T>> {
+        self.current.set(0);
+        let mut chunks = std::mem::take(&mut *self.chunks.borrow_mut());
+        for chunk in &mut chunks {
+            chunk.clear();
+        }
+        chunks
+    }
+}
+
+// Turns an empty Vec into one of a type that only differs in its lifetimes, keeping the
+// allocation. This lets storage for types that borrow from each other outlive the borrows.
+pub fn recycle_vec<T, U>(mut vec: Vec<T>) -> Vec<U> {
+    // Types that only differ in their lifetimes have the same layout, so the allocation
+    // is one that a Vec<U> of the same capacity would have made
+    assert!(std::mem::size_of::<T>() == std::mem::size_of::<U>() && std::mem::align_of::<T>() == std::mem::align_of::<U>());
+    vec.clear();
+    let mut vec = std::mem::ManuallyDrop::new(vec);
+    // The Vec is empty, so no T is ever read as a U
+    unsafe { Vec::from_raw_parts(vec.as_mut_ptr() as *mut U, 0, vec.capacity()) }
+}
+
+pub fn recycle_chunks<T, U>(chunks: Vec<Vec<T>>) -> Vec<Vec<U>> {
+    chunks.into_iter().map(recycle_vec).collect()
+}
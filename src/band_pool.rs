@@ -0,0 +1,37 @@
+// The thread pool that the bands of `PathBuilder::set_parallel_bands` are rasterized on.
+// The rasterizer doesn't start threads of its own, so the bands run on a pool that the
+// caller provides, or on rayon's global pool when the `rayon` feature is enabled.
+
+/// Runs the bands of a path that is rasterized in parallel, see
+/// `PathBuilder::set_band_pool`.
+pub trait BandPool: Send + Sync {
+    /// Calls `band` once with every index in `0..count` and returns when all of the calls
+    /// have returned. The calls can be made on any threads and in any order.
+    fn run_bands(&self, count: usize, band: &(dyn Fn(usize) + Sync));
+}
+
+#[cfg(feature = "rayon")]
+impl BandPool for rayon::ThreadPool {
+    fn run_bands(&self, count: usize, band: &(dyn Fn(usize) + Sync)) {
+        use rayon::prelude::*;
+        self.install(|| (0..count).into_par_iter().for_each(band));
+    }
+}
+
+// rayon's global pool
+#[cfg(feature = "rayon")]
+struct GlobalPool;
+
+#[cfg(feature = "rayon")]
+impl BandPool for GlobalPool {
+    fn run_bands(&self, count: usize, band: &(dyn Fn(usize) + Sync)) {
+        use rayon::prelude::*;
+        (0..count).into_par_iter().for_each(band);
+    }
+}
+
+// The pool that is used when none is set. Without one, paths are rasterized in one band.
+#[cfg(feature = "rayon")]
+pub(crate) const DEFAULT_BAND_POOL: Option<&dyn BandPool> = Some(&GlobalPool);
+#[cfg(not(feature = "rayon"))]
+pub(crate) const DEFAULT_BAND_POOL: Option<&dyn BandPool> = None;
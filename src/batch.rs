@@ -0,0 +1,74 @@
+// Rasterizes many paths into one shared vertex buffer so that a whole scene can be drawn
+// with a single draw call. Every vertex carries the index of the path it belongs to, which
+// can be used to look up per path data like a color or a transform, and a range table maps
+// each path to its vertices.
+
+use crate::hwrasterizer::CRasterizerStorage;
+use crate::{OutputVertex, PathBuilder};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct BatchVertex {
+    pub x: f32,
+    pub y: f32,
+    pub coverage: f32,
+    pub path_index: u32,
+}
+
+/// The vertices of one path in `Batch::vertices`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct VertexRange {
+    pub start: usize,
+    pub count: usize,
+}
+
+#[derive(Default)]
+pub struct Batch {
+    vertices: Vec<BatchVertex>,
+    ranges: Vec<VertexRange>,
+    // The triangle list of the path that is being added. It is kept around so that adding
+    // a path doesn't allocate once the batch has been used for a while.
+    scratch: Vec<OutputVertex>,
+    storage: CRasterizerStorage,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rasterizes `path` like `PathBuilder::rasterize_to_tri_list` and appends its triangles.
+    /// Returns the index of the path, which is stored in each of its vertices. Paths that
+    /// can't be rasterized still get an index, with an empty range, so that the indices
+    /// match the order in which paths were added.
+    pub fn add_path(&mut self, path: &PathBuilder, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> u32 {
+        let path_index = self.ranges.len() as u32;
+        let start = self.vertices.len();
+        // Failures leave the scratch buffer empty
+        let _ = path.build_vertices(&mut self.storage, clip_x, clip_y, clip_width, clip_height, &mut self.scratch);
+        self.vertices.extend(self.scratch.iter().map(|vertex| BatchVertex {
+            x: vertex.x,
+            y: vertex.y,
+            coverage: vertex.coverage,
+            path_index,
+        }));
+        self.ranges.push(VertexRange { start, count: self.vertices.len() - start });
+        path_index
+    }
+
+    pub fn vertices(&self) -> &[BatchVertex] {
+        &self.vertices
+    }
+
+    /// The vertex range of each path, indexed by path index.
+    pub fn ranges(&self) -> &[VertexRange] {
+        &self.ranges
+    }
+
+    /// Removes all of the paths but keeps the allocations for the next frame.
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.ranges.clear();
+    }
+}
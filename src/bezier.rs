@@ -102,16 +102,31 @@ const HFD32_SHIFT: LONG = HFD32_INITIAL_SHIFT + HFD32_ADDITIONAL_SHIFT;
 const HFD32_ROUND: LONG = 1 << (HFD32_SHIFT - 1);
 
 // The error is tested on max(|e2|, |e3|), which represent 6 times the actual error.
-// The flattening tolerance is hard coded to 1/4 in the original geometry space,
-// which translates to 4 in 28.4 format.  So 6 times that is:
-
-const HFD32_TOLERANCE: LONGLONG = 24;
-
-// During the initial phase, while working in 18.14 format
-const HFD32_INITIAL_TEST_MAGNITUDE: LONGLONG = HFD32_TOLERANCE << HFD32_INITIAL_SHIFT; 
+// The flattening tolerance defaults to 1/4 in the original geometry space,
+// which translates to 4 in 28.4 format.  So 6 times that is 24.  The tolerance
+// is given to CMILBezier::new in pixels, and HFD32_TOLERANCE below stands for
+// 6 times the tolerance in 28.4 format, which is lTolerance in Bezier32.
+
+// The range of tolerances in pixels that CMILBezier::new accepts.  With the
+// smallest one lTolerance is still 1, and the largest one keeps the 32 bit
+// hfd's numbers below 2^31, see HFD32_MAX_ERROR.
+pub const BEZIER_DEFAULT_TOLERANCE: REAL = 0.25;
+pub const BEZIER_MIN_TOLERANCE: REAL = 1. / 64.;
+pub const BEZIER_MAX_TOLERANCE: REAL = 64.;
+
+// During the initial phase, while working in 18.14 format:
+//      HFD32_INITIAL_TEST_MAGNITUDE = HFD32_TOLERANCE << HFD32_INITIAL_SHIFT
+fn Hfd32InitialTestMagnitude(lTolerance: LONGLONG) -> LONGLONG
+{
+    lTolerance << HFD32_INITIAL_SHIFT
+}
 
-// During the steady state, while working in 15.17 format
-const HFD32_TEST_MAGNITUDE: LONGLONG = HFD32_INITIAL_TEST_MAGNITUDE << HFD32_ADDITIONAL_SHIFT; 
+// During the steady state, while working in 15.17 format:
+//      HFD32_TEST_MAGNITUDE = HFD32_INITIAL_TEST_MAGNITUDE << HFD32_ADDITIONAL_SHIFT
+fn Hfd32TestMagnitude(lTolerance: LONGLONG) -> LONG
+{
+    (Hfd32InitialTestMagnitude(lTolerance) << HFD32_ADDITIONAL_SHIFT) as LONG
+}
 
 // We will stop halving the segment with basis e1, e2, e3, e4 when max(|e2|, |e3|)
 // is less than HFD32_TOLERANCE.  The operation e2 = (e2 + e3) >> 3 in vHalveStepSize() may
@@ -121,8 +136,17 @@ const HFD32_TEST_MAGNITUDE: LONGLONG = HFD32_INITIAL_TEST_MAGNITUDE << HFD32_ADD
 // initial max(|e2|, |e3|) is less than than HFD32_TOLERANCE << 2n.  But if the initial 
 // max(|e2|, |e3|) is greater than HFD32_TOLERANCE >> (HFD32_SHIFT / 3) then we may not be
 // able to flatten with the 32 bit hfd, so we need to resort to the 64 bit hfd. 
+//
+//      HFD32_MAX_ERROR = HFD32_TOLERANCE << ((2 * HFD32_INITIAL_SHIFT) / 3)
+//
+// bInit shifts errors below HFD32_MAX_ERROR left by HFD32_INITIAL_SHIFT and
+// vLazyHalveStepSize adds two of them, so HFD32_TOLERANCE << 17 has to be
+// less than 2^31.  BEZIER_MAX_TOLERANCE gives 6 * 64 * 16 << 17 < 2^29.
 
-const HFD32_MAX_ERROR: INT = (HFD32_TOLERANCE as i32) << ((2 * HFD32_INITIAL_SHIFT) / 3);
+fn Hfd32MaxError(lTolerance: LONGLONG) -> INT
+{
+    (lTolerance as INT) << ((2 * HFD32_INITIAL_SHIFT) / 3)
+}
 
 // The maximum size of coefficients that can be handled by HfdBasis32.
 const HFD32_MAX_SIZE: LONGLONG = 0xffffc000;
@@ -164,7 +188,7 @@ const HFD32_MAX_SIZE: LONGLONG = 0xffffc000;
 // or Bounds < 2^18, less stringent than the above.
 //
 // In vLazyHalveStepSize we cut the error down by subdivision, making abs(e2) and abs(e3) 
-// less than HFD32_TEST_MAGNITUDE = 24*2^13,  well below 2^31.
+// less than HFD32_TEST_MAGNITUDE = 24*2^13 by default, and at most 6144*2^13,  well below 2^31.
 //
 // During all the steady-state operations - vTakeStep, vHalveStepSize and vDoubleStepSize, 
 // e0 is on the curve and e1 is a difference of 2 points on the curve, so
@@ -204,7 +228,7 @@ impl HfdBasis32 {
         return((self.e0 + HFD32_ROUND) >> HFD32_SHIFT); 
     }
 
-    fn bInit(&mut self, p1: INT, p2: INT, p3: INT, p4: INT) -> bool
+    fn bInit(&mut self, p1: INT, p2: INT, p3: INT, p4: INT, lMaxError: INT) -> bool
     {
     // Change basis and convert from 28.4 to 18.14 format:
     
@@ -214,7 +238,7 @@ impl HfdBasis32 {
         self.e2 = 6 * (p2 - p3 - p3 + p4);
         self.e3 = 6 * (p1 - p2 - p2 + p3);
 
-        if (self.lError() >= HFD32_MAX_ERROR)
+        if (self.lError() >= lMaxError)
         {
             // Large error, will require too many subdivision for this 32 bit hfd
             return false;
@@ -328,20 +352,25 @@ pub struct Bezier32
     cSteps: LONG,
     x: HfdBasis32,
     y: HfdBasis32,
-    rcfxBound: RECT
+    rcfxBound: RECT,
+    lTestMagnitude: LONG
 }
 impl Bezier32 {
     
 fn bInit(&mut self,
     aptfxBez: &[POINT; 4],
         // Pointer to 4 control points
-    prcfxClip: Option<&RECT>) -> bool
+    prcfxClip: Option<&RECT>,
         // Bound box of visible region (optional)
+    lTolerance: LONGLONG) -> bool
+        // 6 times the maximum error in 28.4 format
 {
     let mut aptfx;
     let mut cShift = 0;    // Keeps track of 'lazy' shifts
 
     self.cSteps = 1;         // Number of steps to do before reach end of curve
+    self.lTestMagnitude = Hfd32TestMagnitude(lTolerance);
+    let lMaxError = Hfd32MaxError(lTolerance);
 
     self.rcfxBound = vBoundBox(aptfxBez);
 
@@ -372,11 +401,11 @@ fn bInit(&mut self,
         }
     }
 
-    if (!self.x.bInit(aptfx[0].x, aptfx[1].x, aptfx[2].x, aptfx[3].x))
+    if (!self.x.bInit(aptfx[0].x, aptfx[1].x, aptfx[2].x, aptfx[3].x, lMaxError))
     {
         return false;
     }
-    if (!self.y.bInit(aptfx[0].y, aptfx[1].y, aptfx[2].y, aptfx[3].y))
+    if (!self.y.bInit(aptfx[0].y, aptfx[1].y, aptfx[2].y, aptfx[3].y, lMaxError))
     {
         return false;
     }
@@ -386,7 +415,7 @@ fn bInit(&mut self,
     {
         
         loop {
-            let lTestMagnitude = (HFD32_INITIAL_TEST_MAGNITUDE << cShift) as LONG;
+            let lTestMagnitude = (Hfd32InitialTestMagnitude(lTolerance) << cShift) as LONG;
 
             if (self.x.lError() <= lTestMagnitude && self.y.lError() <= lTestMagnitude) {
                 break;
@@ -442,7 +471,7 @@ fn cFlatten(&mut self,
     
     // Okay, we have to step:
     
-        if (self.x.lError().max(self.y.lError()) > HFD32_TEST_MAGNITUDE as LONG)
+        if (self.x.lError().max(self.y.lError()) > self.lTestMagnitude)
         {
             self.x.vHalveStepSize();
             self.y.vHalveStepSize();
@@ -454,11 +483,11 @@ fn cFlatten(&mut self,
         // |2e2-e3| < max(|e2|,|e3|) << 2 and vHalveStepSize is guaranteed to reduce 
         // max(|e2|,|e3|) by >> 2, no more than one subdivision should be required to 
         // bring the new max(|e2|,|e3|) back to within HFD32_TEST_MAGNITUDE, so:
-        assert!(self.x.lError().max(self.y.lError()) <= HFD32_TEST_MAGNITUDE as LONG);
+        assert!(self.x.lError().max(self.y.lError()) <= self.lTestMagnitude);
     
         while (!(self.cSteps & 1 != 0) &&
-               self.x.lParentErrorDividedBy4() <= (HFD32_TEST_MAGNITUDE as LONG >> 2) &&
-               self.y.lParentErrorDividedBy4() <= (HFD32_TEST_MAGNITUDE as LONG >> 2))
+               self.x.lParentErrorDividedBy4() <= (self.lTestMagnitude >> 2) &&
+               self.y.lParentErrorDividedBy4() <= (self.lTestMagnitude >> 2))
         {
             self.x.vDoubleStepSize();
             self.y.vDoubleStepSize();
@@ -665,11 +694,13 @@ const LONGLONG geqErrorLow = (LONGLONG)(4) << 32;
 
 // The following is the default 1/4 error encoded as a 36.28 number,
 // multiplied by 6, and leaving 4 bits for fraction:
+//
+//      geqErrorLow = (3) << 31
+//
+// Other tolerances are encoded the same way by CMILBezier::new.
 
 use crate::types::POINT;
 
-const geqErrorLow: LONGLONG = (3) << 31;
-
 //#endif
 #[derive(Default)]
 pub struct Bezier64
@@ -823,6 +854,463 @@ fn cFlatten(
 }
 }
 
+// Whether the bound box of the control points doesn't intersect the bound box
+// of the visible region
+fn bOutsideClip(aptfxBez: &[POINT], prcfxClip: Option<&RECT>) -> bool
+{
+    let Some(rcfxClip) = prcfxClip else {
+        return false;
+    };
+    let left = aptfxBez.iter().map(|ptfx| ptfx.x).min().unwrap();
+    let top = aptfxBez.iter().map(|ptfx| ptfx.y).min().unwrap();
+    let right = aptfxBez.iter().map(|ptfx| ptfx.x).max().unwrap();
+    let bottom = aptfxBez.iter().map(|ptfx| ptfx.y).max().unwrap();
+    left >= rcfxClip.right || top >= rcfxClip.bottom ||
+        right <= rcfxClip.left || bottom <= rcfxClip.top
+}
+
+//+-----------------------------------------------------------------------------
+//
+//  class BezierQuadratic
+//
+//  Flattens a quadratic Bezier by forward differencing.  The second difference
+//  of a quadratic is constant, so unlike the cubic crackers there's no need to
+//  adapt the step size: a step of size h in t strays
+//
+//      |p0 - 2 p1 + p2| h^2 / 4
+//
+//  from its chord wherever it is on the curve, and the number of steps can be
+//  picked up front.  The differences are kept in doubles; with at most
+//  QUADRATIC_MAX_STEPS steps the rounding error they build up stays well below
+//  a 28.4 unit.
+//
+//------------------------------------------------------------------------------
+
+const QUADRATIC_MAX_STEPS: f64 = (1 << 17) as f64;
+
+#[derive(Default)]
+pub struct BezierQuadratic
+{
+    cSteps: INT,
+        // Steps left until the end point
+    ptCurrent: (f64, f64),
+    ptFirstDifference: (f64, f64),
+    ptSecondDifference: (f64, f64),
+    ptfxEnd: POINT
+}
+
+impl BezierQuadratic {
+
+fn vInit(&mut self,
+    aptfxBez: &[POINT; 3],
+        // Pointer to 3 control points
+    prcfxClip: Option<&RECT>,
+        // Bound box of visible region (optional)
+    rTolerance: REAL)
+        // Maximum error in pixels
+{
+    let [p0, p1, p2] = aptfxBez.map(|ptfx| (ptfx.x as f64, ptfx.y as f64));
+
+    // p(t) = a t^2 + b t + p0
+    let a = (p0.0 - 2. * p1.0 + p2.0, p0.1 - 2. * p1.1 + p2.1);
+    let b = (2. * (p1.0 - p0.0), 2. * (p1.1 - p0.1));
+
+    let rError = (a.0 * a.0 + a.1 * a.1).sqrt() / 4.;
+    let rTolerance = rTolerance as f64 * 16.;
+    let cSteps = if (bOutsideClip(aptfxBez, prcfxClip))
+    {
+        // Render the entire curve as a single line, like Bezier64
+        1.
+    }
+    else
+    {
+        (rError / rTolerance).sqrt().ceil().clamp(1., QUADRATIC_MAX_STEPS)
+    };
+
+    let h = 1. / cSteps;
+    self.cSteps = cSteps as INT;
+    self.ptCurrent = p0;
+    self.ptFirstDifference = (a.0 * h * h + b.0 * h, a.1 * h * h + b.1 * h);
+    self.ptSecondDifference = (2. * a.0 * h * h, 2. * a.1 * h * h);
+    self.ptfxEnd = aptfxBez[2];
+}
+
+// Returns the number of points filled in, the same way as Bezier32::cFlatten
+fn cFlatten(&mut self,
+    pptfx: &mut [POINT],
+    pbMore: &mut bool) -> INT
+{
+    assert!(!pptfx.is_empty());
+
+    for (cptfx, ptfx) in pptfx.iter_mut().enumerate()
+    {
+        self.cSteps -= 1;
+        if (self.cSteps == 0)
+        {
+            // Finish exactly on the end point
+            *ptfx = self.ptfxEnd;
+            *pbMore = false;
+            return (cptfx + 1) as INT;
+        }
+
+        self.ptCurrent.0 += self.ptFirstDifference.0;
+        self.ptCurrent.1 += self.ptFirstDifference.1;
+        self.ptFirstDifference.0 += self.ptSecondDifference.0;
+        self.ptFirstDifference.1 += self.ptSecondDifference.1;
+        *ptfx = POINT { x: self.ptCurrent.0.round() as INT, y: self.ptCurrent.1.round() as INT };
+    }
+
+    *pbMore = true;
+    pptfx.len() as INT
+}
+}
+
+//+-----------------------------------------------------------------------------
+//
+//  class BezierScanline
+//
+//  Flattens a Bezier so that the points land on pixel row boundaries wherever
+//  the curve allows it.  The rasterizer can only output a trapezoid for rows
+//  where no edge starts or ends, so the points of Bezier32 and Bezier64 send
+//  almost every row of a curve down the complex scan path.
+//
+//  The curve is split into pieces that are monotonic in y.  Within a piece the
+//  next point is the furthest row boundary for which the curve between the
+//  points is within the tolerance of the line joining them.  When even the next
+//  row boundary is too far, which happens close to where the curve turns
+//  horizontal, the step is halved in t until it is flat enough instead.
+//
+//  The math is done in doubles on the 28.4 coordinates, and the points are
+//  rounded as they're returned.
+//
+//------------------------------------------------------------------------------
+
+// Pixel centers are at integer pixel coordinates in the 28.4 device space, so
+// the row boundaries are half a pixel away from multiples of 16.
+const SCANLINE_BOUNDARY_OFFSET: f64 = 8.;
+
+// Limits the halving of the step when no row boundary is flat enough
+const SCANLINE_MAX_SUBDIVISIONS: INT = 32;
+
+// Limits the iterations used to find the t at which a piece crosses a row,
+// and how close to the row, in 28.4 units, the crossing has to be
+const SCANLINE_SOLVE_ITERATIONS: INT = 64;
+const SCANLINE_SOLVE_PRECISION: f64 = 1e-6;
+
+#[derive(Default)]
+pub struct BezierScanline
+{
+    aptBez: [(f64, f64); 4],
+        // Control points
+    rTolerance: f64,
+        // Maximum error in 28.4 format
+    rgtPieces: [f64; 4],
+        // Values of t that split the curve into pieces monotonic in y
+    cPieces: usize,
+    iPiece: usize,
+        // The piece that tCurrent is in
+    tCurrent: f64,
+    rYCurrent: f64,
+        // y of the last point returned, exactly on the row boundary if it was
+        // snapped to one
+    ptfxEnd: POINT
+}
+
+impl BezierScanline {
+
+fn ptEval(&self, t: f64) -> (f64, f64)
+{
+    let [p0, p1, p2, p3] = self.aptBez;
+    let u = 1. - t;
+    let a = u * u * u;
+    let b = 3. * u * u * t;
+    let c = 3. * u * t * t;
+    let d = t * t * t;
+    (a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+     a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1)
+}
+
+//+-----------------------------------------------------------------------------
+//
+//  Member:
+//      BezierScanline::vInit
+//
+//  Synopsis:
+//      Find the pieces of the curve that are monotonic in y.  If the control
+//      points don't intersect prcfxClip the curve is output as a single line,
+//      like Bezier64 does.  Quadratics are elevated to cubics first.
+//
+//------------------------------------------------------------------------------
+fn vInit(&mut self,
+    aptfxBez: &[POINT],
+        // Pointer to 3 or 4 control points
+    prcfxClip: Option<&RECT>,
+        // Bound box of visible region (optional)
+    rTolerance: REAL)
+        // Maximum error in pixels
+{
+    let pt = |i: usize| (aptfxBez[i].x as f64, aptfxBez[i].y as f64);
+    self.aptBez = if (aptfxBez.len() == 3)
+    {
+        let (p0, p1, p2) = (pt(0), pt(1), pt(2));
+        [p0,
+         (p0.0 + (p1.0 - p0.0) * (2. / 3.), p0.1 + (p1.1 - p0.1) * (2. / 3.)),
+         (p2.0 + (p1.0 - p2.0) * (2. / 3.), p2.1 + (p1.1 - p2.1) * (2. / 3.)),
+         p2]
+    }
+    else
+    {
+        [pt(0), pt(1), pt(2), pt(3)]
+    };
+    self.rTolerance = rTolerance as f64 * 16.;
+    self.ptfxEnd = aptfxBez[aptfxBez.len() - 1];
+    self.tCurrent = 0.;
+    self.rYCurrent = self.aptBez[0].1;
+    self.iPiece = 0;
+    self.rgtPieces[0] = 0.;
+    self.cPieces = 0;
+
+    if (bOutsideClip(aptfxBez, prcfxClip))
+    {
+        // Every line is flat enough
+        self.rTolerance = f64::INFINITY;
+    }
+
+    // y'(t)/3 = a t^2 + b t + c
+    let d0 = self.aptBez[1].1 - self.aptBez[0].1;
+    let d1 = self.aptBez[2].1 - self.aptBez[1].1;
+    let d2 = self.aptBez[3].1 - self.aptBez[2].1;
+    let a = d0 - 2. * d1 + d2;
+    let b = 2. * (d1 - d0);
+    let c = d0;
+
+    let mut rgtRoots = [f64::NAN; 2];
+    if (a.abs() < 1e-9)
+    {
+        if (b != 0.)
+        {
+            rgtRoots[0] = -c / b;
+        }
+    }
+    else
+    {
+        let discriminant = b * b - 4. * a * c;
+        if (discriminant >= 0.)
+        {
+            let root = discriminant.sqrt();
+            rgtRoots[0] = (-b - root) / (2. * a);
+            rgtRoots[1] = (-b + root) / (2. * a);
+            if (rgtRoots[0] > rgtRoots[1])
+            {
+                rgtRoots.swap(0, 1);
+            }
+        }
+    }
+
+    for t in rgtRoots
+    {
+        // NaN fails the test
+        if (t > self.rgtPieces[self.cPieces] && t < 1.)
+        {
+            self.cPieces += 1;
+            self.rgtPieces[self.cPieces] = t;
+        }
+    }
+    self.cPieces += 1;
+    self.rgtPieces[self.cPieces] = 1.;
+}
+
+//+-----------------------------------------------------------------------------
+//
+//  Member:
+//      BezierScanline::bIsFlat
+//
+//  Synopsis:
+//      Whether the curve between t0 and t1 is within the tolerance of the line
+//      between its end points.  The distance of the curve from that line is
+//      at most 3/4 of the largest distance of the inner control points.
+//
+//------------------------------------------------------------------------------
+fn bIsFlat(&self, t0: f64, t1: f64) -> bool
+{
+    if (self.rTolerance == f64::INFINITY)
+    {
+        return true;
+    }
+
+    // The control points of the curve between t0 and t1 are the blossom
+    // B(t0, t0, t0), B(t0, t0, t1), B(t0, t1, t1) and B(t1, t1, t1).
+    let blossom = |a: f64, b: f64, c: f64| -> (f64, f64) {
+        let lerp = |p: (f64, f64), q: (f64, f64), t: f64| (p.0 + (q.0 - p.0) * t, p.1 + (q.1 - p.1) * t);
+        let [p0, p1, p2, p3] = self.aptBez;
+        let q0 = lerp(p0, p1, a);
+        let q1 = lerp(p1, p2, a);
+        let q2 = lerp(p2, p3, a);
+        let r0 = lerp(q0, q1, b);
+        let r1 = lerp(q1, q2, b);
+        lerp(r0, r1, c)
+    };
+    let q0 = blossom(t0, t0, t0);
+    let q1 = blossom(t0, t0, t1);
+    let q2 = blossom(t0, t1, t1);
+    let q3 = blossom(t1, t1, t1);
+
+    let dx = q3.0 - q0.0;
+    let dy = q3.1 - q0.1;
+    let length = (dx * dx + dy * dy).sqrt();
+    let distance = |q: (f64, f64)| {
+        if (length < 1e-9)
+        {
+            ((q.0 - q0.0) * (q.0 - q0.0) + (q.1 - q0.1) * (q.1 - q0.1)).sqrt()
+        }
+        else
+        {
+            (dx * (q.1 - q0.1) - dy * (q.0 - q0.0)).abs() / length
+        }
+    };
+    0.75 * distance(q1).max(distance(q2)) <= self.rTolerance
+}
+
+// Returns the t in [t0, t1] at which the curve crosses y, which has to be
+// between the y of t0 and t1.  The curve has to be monotonic in y there, so
+// Newton's method is used and falls back to bisection when it leaves the
+// bracket.
+fn tSolveY(&self, mut t0: f64, mut t1: f64, y: f64) -> f64
+{
+    let [p0, p1, p2, p3] = self.aptBez;
+    let y0 = self.ptEval(t0).1 - y;
+    let y1 = self.ptEval(t1).1 - y;
+    if (y0 == y1)
+    {
+        return t0;
+    }
+    let fIncreasing = y1 > y0;
+    let mut t = t0 + (t1 - t0) * (-y0 / (y1 - y0)).clamp(0., 1.);
+    for _ in 0..SCANLINE_SOLVE_ITERATIONS
+    {
+        let f = self.ptEval(t).1 - y;
+        if (f.abs() < SCANLINE_SOLVE_PRECISION)
+        {
+            break;
+        }
+        if ((f < 0.) == fIncreasing)
+        {
+            t0 = t;
+        }
+        else
+        {
+            t1 = t;
+        }
+        let u = 1. - t;
+        let dy = 3. * (u * u * (p1.1 - p0.1) + 2. * u * t * (p2.1 - p1.1) + t * t * (p3.1 - p2.1));
+        let tNewton = t - f / dy;
+        t = if (tNewton > t0 && tNewton < t1) { tNewton } else { (t0 + t1) / 2. };
+    }
+    t
+}
+
+// Returns the next point of the approximation and whether it's the end point
+fn ptfxNext(&mut self) -> (POINT, bool)
+{
+    let tEnd = self.rgtPieces[self.iPiece + 1];
+    if (self.bIsFlat(self.tCurrent, tEnd))
+    {
+        self.iPiece += 1;
+        self.tCurrent = tEnd;
+        if (self.iPiece == self.cPieces)
+        {
+            return (self.ptfxEnd, true);
+        }
+        let pt = self.ptEval(tEnd);
+        self.rYCurrent = pt.1;
+        return (POINT { x: pt.0.round() as INT, y: pt.1.round() as INT }, false);
+    }
+
+    // Find the row boundaries strictly between the current point and the end
+    // of the piece, in the order that the curve crosses them
+    let yEnd = self.ptEval(tEnd).1;
+    let (yFirst, yStep, cBoundaries) = if (yEnd > self.rYCurrent)
+    {
+        let yFirst = ((self.rYCurrent - SCANLINE_BOUNDARY_OFFSET) / 16.).floor() * 16. + 16. + SCANLINE_BOUNDARY_OFFSET;
+        let yLast = ((yEnd - SCANLINE_BOUNDARY_OFFSET) / 16.).ceil() * 16. - 16. + SCANLINE_BOUNDARY_OFFSET;
+        (yFirst, 16., ((yLast - yFirst) / 16.) as i64 + 1)
+    }
+    else
+    {
+        let yFirst = ((self.rYCurrent - SCANLINE_BOUNDARY_OFFSET) / 16.).ceil() * 16. - 16. + SCANLINE_BOUNDARY_OFFSET;
+        let yLast = ((yEnd - SCANLINE_BOUNDARY_OFFSET) / 16.).floor() * 16. + 16. + SCANLINE_BOUNDARY_OFFSET;
+        (yFirst, -16., ((yFirst - yLast) / 16.) as i64 + 1)
+    };
+
+    // Binary search for the furthest flat boundary.  iFlat is flat, or -1
+    // for the current point, and iNotFlat isn't, or cBoundaries for the end
+    // of the piece.
+    let mut iFlat: i64 = -1;
+    let mut tFlat = self.tCurrent;
+    let mut iNotFlat = cBoundaries.max(0);
+    let mut tNotFlat = tEnd;
+    while (iNotFlat - iFlat > 1)
+    {
+        let iMid = (iFlat + iNotFlat) / 2;
+        let tMid = self.tSolveY(tFlat, tNotFlat, yFirst + yStep * iMid as f64);
+        if (self.bIsFlat(self.tCurrent, tMid))
+        {
+            iFlat = iMid;
+            tFlat = tMid;
+        }
+        else
+        {
+            iNotFlat = iMid;
+            tNotFlat = tMid;
+        }
+    }
+
+    if (iFlat >= 0)
+    {
+        let y = yFirst + yStep * iFlat as f64;
+        self.tCurrent = tFlat;
+        self.rYCurrent = y;
+        return (POINT { x: self.ptEval(tFlat).0.round() as INT, y: y as INT }, false);
+    }
+
+    // Not even the next row boundary is flat enough, so take a smaller step
+    // that ends between rows
+    let mut t = tNotFlat;
+    let mut cSubdivisions = 0;
+    while (!self.bIsFlat(self.tCurrent, t) && cSubdivisions < SCANLINE_MAX_SUBDIVISIONS)
+    {
+        t = (self.tCurrent + t) / 2.;
+        cSubdivisions += 1;
+    }
+    let pt = self.ptEval(t);
+    self.tCurrent = t;
+    self.rYCurrent = pt.1;
+    (POINT { x: pt.0.round() as INT, y: pt.1.round() as INT }, false)
+}
+
+// Returns the number of points filled in, the same way as Bezier32::cFlatten
+fn cFlatten(&mut self,
+    pptfx: &mut [POINT],
+    pbMore: &mut bool) -> INT
+{
+    assert!(!pptfx.is_empty());
+
+    for (cptfx, ptfx) in pptfx.iter_mut().enumerate()
+    {
+        let (ptfxNext, fEnd) = self.ptfxNext();
+        *ptfx = ptfxNext;
+        if (fEnd)
+        {
+            *pbMore = false;
+            return (cptfx + 1) as INT;
+        }
+    }
+
+    *pbMore = true;
+    pptfx.len() as INT
+}
+}
+
 //+-----------------------------------------------------------------------------
 //
 //  class CMILBezier
@@ -832,6 +1320,20 @@ fn cFlatten(
 //  curves and slower 64 bit cracker for big curves.
 //
 //  Public Interface:
+//      new(aptfx, prcfxClip, rTolerance)
+//          - rTolerance is the maximum distance between the curve and its
+//            approximation in pixels.  It is clamped to
+//            [BEZIER_MIN_TOLERANCE, BEZIER_MAX_TOLERANCE].
+//
+//      newAlignedToScanlines(aptfx, prcfxClip, rTolerance)
+//          - Same as new, but uses BezierScanline so that the points land on
+//            pixel row boundaries where possible.
+//
+//      newQuadratic(aptfx, prcfxClip, rTolerance)
+//      newQuadraticAlignedToScanlines(aptfx, prcfxClip, rTolerance)
+//          - Same as new and newAlignedToScanlines for the 3 control points
+//            of a quadratic Bezier.
+//
 //      vInit(aptfx, prcfxClip, peqError)
 //          - pptfx points to 4 control points of Bezier.  The first point
 //            retrieved by bNext() is the the first point in the approximation
@@ -853,23 +1355,62 @@ fn cFlatten(
 pub (crate) enum CMILBezier
 {
     Bezier64(Bezier64),
-    Bezier32(Bezier32)
+    Bezier32(Bezier32),
+    Quadratic(BezierQuadratic),
+    Scanline(BezierScanline)
+}
+
+fn ClampTolerance(rTolerance: REAL) -> REAL
+{
+    if rTolerance.is_nan() {
+        BEZIER_DEFAULT_TOLERANCE
+    } else {
+        rTolerance.clamp(BEZIER_MIN_TOLERANCE, BEZIER_MAX_TOLERANCE)
+    }
 }
 
 impl CMILBezier {
     // All coordinates must be in 28.4 format:
-    pub fn new(aptfxBez: &[POINT; 4], prcfxClip: Option<&RECT>) -> Self {
+    pub fn new(aptfxBez: &[POINT; 4], prcfxClip: Option<&RECT>, rTolerance: REAL) -> Self {
+        let rTolerance = ClampTolerance(rTolerance);
+
+        // 6 times the tolerance in 28.4 format, see HFD32_TOLERANCE
+        let rTolerance = rTolerance * 6. * 16.;
+
         let mut bez32 = Bezier32::default();
-        let bBez32 = bez32.bInit(aptfxBez, prcfxClip);
+        let bBez32 = bez32.bInit(aptfxBez, prcfxClip, (rTolerance.round() as LONGLONG).max(1));
         if bBez32 {
             CMILBezier::Bezier32(bez32)
         } else {
+            // In 36.28 format, see geqErrorLow
+            let eqError = (rTolerance as f64 * (1u64 << BEZIER64_FRACTION) as f64) as LONGLONG;
             let mut bez64 = Bezier64::default();
-            bez64.vInit(aptfxBez, prcfxClip, geqErrorLow);
+            bez64.vInit(aptfxBez, prcfxClip, eqError);
             CMILBezier::Bezier64(bez64)
         }
     }
 
+    // All coordinates must be in 28.4 format, with pixel centers at integers:
+    pub fn newAlignedToScanlines(aptfxBez: &[POINT; 4], prcfxClip: Option<&RECT>, rTolerance: REAL) -> Self {
+        let mut bez = BezierScanline::default();
+        bez.vInit(aptfxBez, prcfxClip, ClampTolerance(rTolerance));
+        CMILBezier::Scanline(bez)
+    }
+
+    // All coordinates must be in 28.4 format:
+    pub fn newQuadratic(aptfxBez: &[POINT; 3], prcfxClip: Option<&RECT>, rTolerance: REAL) -> Self {
+        let mut bez = BezierQuadratic::default();
+        bez.vInit(aptfxBez, prcfxClip, ClampTolerance(rTolerance));
+        CMILBezier::Quadratic(bez)
+    }
+
+    // All coordinates must be in 28.4 format, with pixel centers at integers:
+    pub fn newQuadraticAlignedToScanlines(aptfxBez: &[POINT; 3], prcfxClip: Option<&RECT>, rTolerance: REAL) -> Self {
+        let mut bez = BezierScanline::default();
+        bez.vInit(aptfxBez, prcfxClip, ClampTolerance(rTolerance));
+        CMILBezier::Scanline(bez)
+    }
+
     // Returns the number of points filled in. This will never be zero.
     //
     // The last point returned may not be exactly the last control
@@ -880,7 +1421,9 @@ impl CMILBezier {
         pbMore: &mut bool) -> INT {
             match self {
                 CMILBezier::Bezier32(bez) => bez.cFlatten(pptfx, pbMore),
-                CMILBezier::Bezier64(bez) => bez.cFlatten(pptfx, pbMore)
+                CMILBezier::Bezier64(bez) => bez.cFlatten(pptfx, pbMore),
+                CMILBezier::Quadratic(bez) => bez.cFlatten(pptfx, pbMore),
+                CMILBezier::Scanline(bez) => bez.cFlatten(pptfx, pbMore)
             }
         }
 }
@@ -892,7 +1435,7 @@ fn flatten() {
     POINT{x: 1692, y: 6506},
     POINT{x: 1227, y: 5148},
     POINT{x: 647, y: 5211}];
-    let mut bez = CMILBezier::new(&curve, None);
+    let mut bez = CMILBezier::new(&curve, None, BEZIER_DEFAULT_TOLERANCE);
     let mut result: [POINT; 32] = Default::default();
     let mut more: bool = false;
     let count = bez.Flatten(&mut result, &mut more);
@@ -910,7 +1453,7 @@ fn split_flatten32() {
     POINT{x: 1908, y: 8683},
     POINT{x: 2043, y: 8705}];
 
-    let mut bez = CMILBezier::new(&curve, None);
+    let mut bez = CMILBezier::new(&curve, None, BEZIER_DEFAULT_TOLERANCE);
     let mut result: [POINT; 8] = Default::default();
     let mut more: bool = false;
     let count = bez.Flatten(&mut result[..5], &mut more);
@@ -920,7 +1463,7 @@ fn split_flatten32() {
     assert_eq!(count, 3);
     assert_eq!(more, false);
 
-    let mut bez = CMILBezier::new(&curve, None);
+    let mut bez = CMILBezier::new(&curve, None, BEZIER_DEFAULT_TOLERANCE);
     let mut full_result: [POINT; 8] = Default::default();
     let mut more: bool = false;
     let count = bez.Flatten(&mut full_result, &mut more);
@@ -936,7 +1479,7 @@ fn flatten32() {
     POINT{x: 110, y: 100},
     POINT{x: 110, y: 110},
     POINT{x: 110, y: 100}];
-    let mut bez = CMILBezier::new(&curve, None);
+    let mut bez = CMILBezier::new(&curve, None, BEZIER_DEFAULT_TOLERANCE);
     let mut result: [POINT; 32] = Default::default();
     let mut more: bool = false;
     let count = bez.Flatten(&mut result, &mut more);
@@ -951,7 +1494,7 @@ fn flatten32_double_step_size() {
     POINT{x: 1761, y: 8152},
     POINT{x: 1750, y: 8355},
     POINT{x: 1795, y: 8445}];
-    let mut bez = CMILBezier::new(&curve, None);
+    let mut bez = CMILBezier::new(&curve, None, BEZIER_DEFAULT_TOLERANCE);
     let mut result: [POINT; 32] = Default::default();
     let mut more: bool = false;
     let count = bez.Flatten(&mut result, &mut more);
@@ -966,7 +1509,7 @@ fn bezier64_init_high_num_steps() {
     POINT{x: -1, y: -1},
     POINT{x: -1, y: -16385},
     POINT{x: -226, y: 10}];
-    let mut bez = CMILBezier::new(&curve, None);
+    let mut bez = CMILBezier::new(&curve, None, BEZIER_DEFAULT_TOLERANCE);
     let mut result: [POINT; 32] = Default::default();
     let mut more: bool = false;
     let count = bez.Flatten(&mut result, &mut more);
@@ -981,10 +1524,155 @@ fn bezier64_high_error() {
     POINT{x: -4097, y: -1},
     POINT{x: 65471, y: -256},
     POINT{x: -1, y: 0}];
-    let mut bez = CMILBezier::new(&curve, None);
+    let mut bez = CMILBezier::new(&curve, None, BEZIER_DEFAULT_TOLERANCE);
     let mut result: [POINT; 32] = Default::default();
     let mut more: bool = false;
     let count = bez.Flatten(&mut result, &mut more);
     assert_eq!(count, 32);
     assert_eq!(more, true);
-}
\ No newline at end of file
+}
+#[test]
+fn flatten_tolerance() {
+    fn count_points(curve: &[POINT; 4], rTolerance: REAL) -> usize {
+        let mut bez = CMILBezier::new(curve, None, rTolerance);
+        let mut result: [POINT; 32] = Default::default();
+        let mut more: bool = true;
+        let mut count = 0;
+        while more {
+            count += bez.Flatten(&mut result, &mut more) as usize;
+        }
+        count
+    }
+    // one curve for each of the crackers
+    let curve32: [POINT; 4] = [
+    POINT{x: 1761, y: 8152},
+    POINT{x: 1761, y: 8152},
+    POINT{x: 1750, y: 8355},
+    POINT{x: 1795, y: 8445}];
+    let curve64: [POINT; 4] = [
+    POINT{x: 0, y: 0},
+    POINT{x: 0, y: 160000},
+    POINT{x: 160000, y: 160000},
+    POINT{x: 160000, y: 0}];
+    assert!(matches!(CMILBezier::new(&curve32, None, BEZIER_DEFAULT_TOLERANCE), CMILBezier::Bezier32(_)));
+    assert!(matches!(CMILBezier::new(&curve64, None, BEZIER_DEFAULT_TOLERANCE), CMILBezier::Bezier64(_)));
+    for curve in [&curve32, &curve64] {
+        let default = count_points(curve, BEZIER_DEFAULT_TOLERANCE);
+        assert!(count_points(curve, 4.) < default);
+        assert!(count_points(curve, 1. / 16.) > default);
+        assert_eq!(count_points(curve, REAL::NAN), default);
+        assert_eq!(count_points(curve, 1000.), count_points(curve, BEZIER_MAX_TOLERANCE));
+        assert_eq!(count_points(curve, 0.), count_points(curve, BEZIER_MIN_TOLERANCE));
+    }
+}
+
+#[test]
+fn flatten_to_scanlines() {
+    fn eval(curve: &[POINT; 4], t: f64) -> (f64, f64) {
+        let u = 1. - t;
+        let w = [u * u * u, 3. * u * u * t, 3. * u * t * t, t * t * t];
+        (curve.iter().zip(w).map(|(p, w)| p.x as f64 * w).sum(),
+         curve.iter().zip(w).map(|(p, w)| p.y as f64 * w).sum())
+    }
+    fn distance_to_segment(p: (f64, f64), a: &POINT, b: &POINT) -> f64 {
+        let (ax, ay, bx, by) = (a.x as f64, a.y as f64, b.x as f64, b.y as f64);
+        let (dx, dy) = (bx - ax, by - ay);
+        let length2 = dx * dx + dy * dy;
+        let t = if length2 == 0. { 0. } else { (((p.0 - ax) * dx + (p.1 - ay) * dy) / length2).clamp(0., 1.) };
+        ((ax + dx * t - p.0).powi(2) + (ay + dy * t - p.1).powi(2)).sqrt()
+    }
+
+    let curves: [[POINT; 4]; 4] = [
+        // a quarter circle
+        [POINT{x: 1600, y: 0}, POINT{x: 1600, y: 884}, POINT{x: 884, y: 1600}, POINT{x: 0, y: 1600}],
+        // turns horizontal twice
+        [POINT{x: 0, y: 800}, POINT{x: 400, y: -700}, POINT{x: 1200, y: 2300}, POINT{x: 1600, y: 800}],
+        // big enough for Bezier64
+        [POINT{x: 0, y: 0}, POINT{x: 0, y: 160000}, POINT{x: 160000, y: 160000}, POINT{x: 160000, y: 0}],
+        // a point
+        [POINT{x: 5, y: 5}, POINT{x: 5, y: 5}, POINT{x: 5, y: 5}, POINT{x: 5, y: 5}],
+    ];
+    for curve in &curves {
+        for rTolerance in [BEZIER_DEFAULT_TOLERANCE, 2.] {
+            let mut bez = CMILBezier::newAlignedToScanlines(curve, None, rTolerance);
+            let mut points = vec![curve[0]];
+            let mut result: [POINT; 5] = Default::default();
+            let mut more = true;
+            while more {
+                let count = bez.Flatten(&mut result, &mut more) as usize;
+                assert!(count > 0);
+                points.extend_from_slice(&result[..count]);
+            }
+            assert!(points.last() == Some(&curve[3]));
+
+            // all but the end points and the points where the curve turns are
+            // on row boundaries
+            let aligned = points.iter().filter(|p| p.y.rem_euclid(16) == 8).count();
+            assert!(aligned + 4 >= points.len());
+
+            // within the tolerance, give or take the rounding of the points
+            for i in 0..=256 {
+                let pt = eval(curve, i as f64 / 256.);
+                let distance = points.windows(2).map(|s| distance_to_segment(pt, &s[0], &s[1])).fold(f64::INFINITY, f64::min);
+                assert!(distance <= rTolerance as f64 * 16. + 1., "{} away from the flattened curve", distance);
+            }
+        }
+    }
+}
+
+#[test]
+fn flatten_quadratic() {
+    fn flatten(mut bez: CMILBezier) -> Vec<POINT> {
+        let mut points = Vec::new();
+        let mut result: [POINT; 7] = Default::default();
+        let mut more = true;
+        while more {
+            let count = bez.Flatten(&mut result, &mut more) as usize;
+            assert!(count > 0);
+            points.extend_from_slice(&result[..count]);
+        }
+        points
+    }
+    let curves: [[POINT; 3]; 3] = [
+        [POINT{x: 0, y: 0}, POINT{x: 1600, y: 0}, POINT{x: 1600, y: 1600}],
+        [POINT{x: 100, y: 100}, POINT{x: -40000, y: 90000}, POINT{x: 160000, y: 300}],
+        // a point
+        [POINT{x: 5, y: 5}, POINT{x: 5, y: 5}, POINT{x: 5, y: 5}],
+    ];
+    for curve in &curves {
+        // the elevated cubic
+        let cubic = [curve[0],
+                     POINT { x: curve[0].x + (curve[1].x - curve[0].x) * 2 / 3, y: curve[0].y + (curve[1].y - curve[0].y) * 2 / 3 },
+                     POINT { x: curve[2].x + (curve[1].x - curve[2].x) * 2 / 3, y: curve[2].y + (curve[1].y - curve[2].y) * 2 / 3 },
+                     curve[2]];
+        for rTolerance in [BEZIER_DEFAULT_TOLERANCE, 3.] {
+            let points = flatten(CMILBezier::newQuadratic(curve, None, rTolerance));
+            assert!(points.last() == Some(&curve[2]));
+            assert!(points.len() <= flatten(CMILBezier::new(&cubic, None, rTolerance)).len());
+
+            // the points are on the curve, and the middle of each segment is within the
+            // tolerance of it
+            let eval = |t: f64| {
+                let u = 1. - t;
+                (u * u * curve[0].x as f64 + 2. * u * t * curve[1].x as f64 + t * t * curve[2].x as f64,
+                 u * u * curve[0].y as f64 + 2. * u * t * curve[1].y as f64 + t * t * curve[2].y as f64)
+            };
+            let n = points.len() as f64;
+            let mut previous = curve[0];
+            for (i, point) in points.iter().enumerate() {
+                let expected = eval((i + 1) as f64 / n);
+                assert!((point.x as f64 - expected.0).abs() <= 1. && (point.y as f64 - expected.1).abs() <= 1.);
+                let middle = eval((i as f64 + 0.5) / n);
+                let chord = ((previous.x + point.x) as f64 / 2., (previous.y + point.y) as f64 / 2.);
+                let distance = ((middle.0 - chord.0).powi(2) + (middle.1 - chord.1).powi(2)).sqrt();
+                assert!(distance <= rTolerance as f64 * 16. + 1.);
+                previous = *point;
+            }
+        }
+    }
+
+    // scanline alignment works on quadratics too
+    let points = flatten(CMILBezier::newQuadraticAlignedToScanlines(&curves[0], None, BEZIER_DEFAULT_TOLERANCE));
+    assert!(points.last() == Some(&curves[0][2]));
+    assert!(points[..points.len() - 1].iter().all(|p| p.y.rem_euclid(16) == 8));
+}
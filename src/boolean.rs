@@ -0,0 +1,448 @@
+// Boolean operations on paths.
+//
+// The edges of both paths are built with FixedPointPathEnumerate and swept from top to
+// bottom with the rasterizer's active edge list, like RasterizeEdges does: edges are inserted
+// with InsertNewEdges once the sweep reaches them, their DDAs are advanced one row at a time
+// and the list is kept sorted by SortActiveEdges. The edges are set up on the 16x16 subpixel
+// grid, which has a row for every 28.4 unit, so the sweep yields the x of every edge at every
+// 28.4 row, rounded up to a 28.4 unit. Vertices are on rows, so their x is exact.
+//
+// Consecutive rows that have the same active edges in the same order form a band. Within a
+// band no edges cross, so the gaps between neighboring edges have constant winding numbers
+// for both paths and each run of gaps that is inside of the result becomes a trapezoid whose
+// sides lie on the edges. Edges that cross swap places between two rows, so crossings are
+// rounded to a row.
+//
+// The outline of the result is made of the sides of those trapezoids, plus horizontal pieces
+// wherever the covered spans above and below a band boundary differ. These pieces are
+// chained into closed contours that run clockwise around filled areas and counterclockwise
+// around holes, so the result can be filled with either fill rule.
+
+use std::collections::HashMap;
+
+use crate::aacoverage::{c_nShift, CSubpixelGrid};
+use crate::aarasterizer::*;
+use crate::arena::Arena;
+use crate::error::validate_path;
+use crate::matrix::CMILMatrix;
+use crate::nullable_ref::Ref;
+use crate::types::*;
+use crate::{FillMode, OutputPath};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BooleanOp {
+    Union = 0,
+    Intersection = 1,
+    /// The area of the first path that isn't covered by the second one.
+    Difference = 2,
+    Xor = 3,
+}
+
+impl BooleanOp {
+    fn apply(self, a: bool, b: bool) -> bool {
+        match self {
+            BooleanOp::Union => a || b,
+            BooleanOp::Intersection => a && b,
+            BooleanOp::Difference => a && !b,
+            BooleanOp::Xor => a != b,
+        }
+    }
+}
+
+fn is_inside(fill_mode: FillMode, winding: i32) -> bool {
+    match fill_mode {
+        FillMode::EvenOdd => winding & 1 != 0,
+        FillMode::Winding => winding != 0,
+    }
+}
+
+// Identifies the line that a piece of the outline lies on, so that consecutive pieces on the
+// same line can be merged
+#[derive(Copy, Clone, PartialEq)]
+enum Line {
+    // The address of the edge
+    Edge(usize),
+    // A horizontal piece that goes left (-1) or right (+1)
+    Horizontal(i32),
+}
+
+// A piece of the outline in subpixel coordinates
+#[derive(Copy, Clone)]
+struct Piece {
+    start: (INT, INT),
+    end: (INT, INT),
+    line: Line,
+}
+
+// A span of a band that is inside of the result. The x values are at the top and the bottom
+// of the band.
+struct Span {
+    left: Line,
+    right: Line,
+    left_top: INT,
+    left_bottom: INT,
+    right_top: INT,
+    right_bottom: INT,
+}
+
+fn address(pEdge: Ref<CEdge>) -> usize {
+    pEdge.get_ref() as *const CEdge as usize
+}
+
+// The rows from `top` on that have `edges` active, in this order. `tops` are the x values of
+// the edges at the top row.
+struct Band<'a> {
+    top: INT,
+    edges: Vec<Ref<'a, CEdge<'a>>>,
+    tops: Vec<INT>,
+}
+
+impl<'a> Band<'a> {
+    // Starts a band at row y. Edges that continue from the previous band start where it
+    // left them, see Sweep::add_band.
+    fn start(&mut self, y: INT, pEdgeActiveList: Ref<'a, CEdge<'a>>, sweep: &mut Sweep) {
+        self.top = y;
+        self.edges.clear();
+        self.tops.clear();
+        let mut pEdge = pEdgeActiveList.Next.get();
+        while pEdge.EndY != INT::MIN {
+            self.edges.push(pEdge);
+            self.tops.push(sweep.bottoms_by_edge.get(&address(pEdge)).copied().unwrap_or(pEdge.X.get()));
+            pEdge = pEdge.Next.get();
+        }
+        make_ascending(&mut self.tops, &mut sweep.runs);
+    }
+
+    // Whether the active list still holds the band's edges in the same order
+    fn continues(&self, pEdgeActiveList: Ref<'a, CEdge<'a>>) -> bool {
+        let mut pEdge = pEdgeActiveList.Next.get();
+        for &edge in &self.edges {
+            if pEdge != edge {
+                return false;
+            }
+            pEdge = pEdge.Next.get();
+        }
+        pEdge.EndY == INT::MIN
+    }
+}
+
+// Returns the x of the edge at row y. Edges that end at y have been taken off of the active
+// list without advancing their DDA, so it is advanced here.
+fn x_at(pEdge: &CEdge, y: INT) -> INT {
+    if pEdge.EndY > y {
+        return pEdge.X.get();
+    }
+    let mut x = pEdge.X.get() + pEdge.Dx;
+    if pEdge.Error.get() + pEdge.ErrorUp >= 0 {
+        x += 1;
+    }
+    x
+}
+
+struct Sweep {
+    op: BooleanOp,
+    fill_modes: [FillMode; 2],
+    pieces: Vec<Piece>,
+    // The spans at the bottom of the previous band
+    above: Vec<(INT, INT)>,
+    below: Vec<(INT, INT)>,
+    xs: Vec<(INT, INT)>,
+    bottoms: Vec<INT>,
+    // The x values at the bottom of the previous band
+    bottoms_by_edge: HashMap<usize, INT>,
+    runs: Vec<(i64, i64)>,
+    spans: Vec<Span>,
+}
+
+impl Sweep {
+    // Adds the outline of the band, which ends at row bottom
+    fn add_band(&mut self, band: &Band, bottom: INT) {
+        // The x values of the edges at the top and bottom of the band. The active list is
+        // sorted at the top, but edges that cross right below the band are out of order at
+        // the bottom, which would give spans with negative widths. Those edges are moved to
+        // where they cross instead.
+        self.bottoms.clear();
+        self.bottoms.extend(band.edges.iter().map(|edge| x_at(edge, bottom)));
+        make_ascending(&mut self.bottoms, &mut self.runs);
+        self.bottoms_by_edge.clear();
+        self.bottoms_by_edge.extend(band.edges.iter().map(|&edge| address(edge)).zip(self.bottoms.iter().copied()));
+        self.xs.clear();
+        self.xs.extend(band.tops.iter().copied().zip(self.bottoms.iter().copied()));
+
+        // Find the spans that are inside of the result. The weight of an edge's winding
+        // direction tells which path it belongs to.
+        self.spans.clear();
+        let mut winding = [0, 0];
+        let mut left = None;
+        for (position, edge) in band.edges.iter().enumerate() {
+            winding[(edge.WindingDirection.abs() - 1) as usize] += edge.WindingDirection.signum();
+            // Gaps without any area don't start or end a span
+            if self.xs.get(position + 1) == Some(&self.xs[position]) {
+                continue;
+            }
+            let inside = self.op.apply(is_inside(self.fill_modes[0], winding[0]), is_inside(self.fill_modes[1], winding[1]));
+            match left {
+                None if inside => left = Some(position),
+                Some(l) if !inside => {
+                    self.spans.push(Span {
+                        left: Line::Edge(address(band.edges[l])),
+                        right: Line::Edge(address(*edge)),
+                        left_top: self.xs[l].0,
+                        left_bottom: self.xs[l].1,
+                        right_top: self.xs[position].0,
+                        right_bottom: self.xs[position].1,
+                    });
+                    left = None;
+                }
+                _ => {}
+            }
+        }
+
+        let top = band.top;
+        self.below.clear();
+        self.below.extend(self.spans.iter().map(|span| (span.left_top, span.right_top)));
+        add_horizontal_pieces(top, &self.above, &self.below, &mut self.pieces);
+        for span in &self.spans {
+            self.pieces.push(Piece { start: (span.left_bottom, bottom), end: (span.left_top, top), line: span.left });
+            self.pieces.push(Piece { start: (span.right_top, top), end: (span.right_bottom, bottom), line: span.right });
+        }
+        self.above.clear();
+        self.above.extend(self.spans.iter().map(|span| (span.left_bottom, span.right_bottom)));
+    }
+}
+
+// Replaces each run of values that are out of order with the run's average, which makes the
+// values ascending while moving them as little as possible. runs is scratch space.
+fn make_ascending(xs: &mut [INT], runs: &mut Vec<(i64, i64)>) {
+    // The sum and length of each run
+    runs.clear();
+    for &x in xs.iter() {
+        let (mut sum, mut count) = (x as i64, 1);
+        while let Some(&(previous_sum, previous_count)) = runs.last() {
+            // Merge with the previous run while its average is larger
+            if previous_sum * count <= sum * previous_count {
+                break;
+            }
+            sum += previous_sum;
+            count += previous_count;
+            runs.pop();
+        }
+        runs.push((sum, count));
+    }
+    let mut i = 0;
+    for &(sum, count) in runs.iter() {
+        for x in &mut xs[i..i + count as usize] {
+            *x = sum.div_euclid(count) as INT;
+        }
+        i += count as usize;
+    }
+}
+
+// Adds the horizontal pieces at y where the spans that end there (above) differ from the ones
+// that start there (below). Both lists are sorted and don't overlap, so they're merged in one
+// pass over their end points.
+fn add_horizontal_pieces(y: INT, above: &[(INT, INT)], below: &[(INT, INT)], pieces: &mut Vec<Piece>) {
+    // The first end point after x of spans[i], which mustn't end at or before x
+    fn next_end_point(spans: &[(INT, INT)], i: usize, x: INT) -> INT {
+        match spans.get(i) {
+            Some(&(left, _)) if left > x => left,
+            Some(&(_, right)) => right,
+            None => INT::MAX,
+        }
+    }
+    fn covers(spans: &[(INT, INT)], i: usize, x: INT) -> bool {
+        matches!(spans.get(i), Some(&(left, right)) if left <= x && x < right)
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut left = above.first().map_or(INT::MAX, |span| span.0).min(below.first().map_or(INT::MAX, |span| span.0));
+    while left != INT::MAX {
+        while matches!(above.get(i), Some(&(_, right)) if right <= left) {
+            i += 1;
+        }
+        while matches!(below.get(j), Some(&(_, right)) if right <= left) {
+            j += 1;
+        }
+        let right = next_end_point(above, i, left).min(next_end_point(below, j, left));
+        if right == INT::MAX {
+            break;
+        }
+        match (covers(above, i, left), covers(below, j, left)) {
+            // The top of a filled area goes right
+            (false, true) => pieces.push(Piece { start: (left, y), end: (right, y), line: Line::Horizontal(1) }),
+            // The bottom of a filled area goes left
+            (true, false) => pieces.push(Piece { start: (right, y), end: (left, y), line: Line::Horizontal(-1) }),
+            _ => {}
+        }
+        left = right;
+    }
+}
+
+// Chains the pieces into closed contours and appends them to types and points. Subpixel
+// coordinates are converted back to 28.4 with to_28_4.
+fn build_contours(pieces: &[Piece], to_28_4: impl Fn(INT) -> INT, types: &mut Vec<BYTE>, points: &mut Vec<POINT>) {
+    let mut outgoing: HashMap<(INT, INT), Vec<usize>> = HashMap::new();
+    for (i, piece) in pieces.iter().enumerate().rev() {
+        outgoing.entry(piece.start).or_default().push(i);
+    }
+
+    let mut contour: Vec<Piece> = Vec::new();
+    let mut vertices: Vec<POINT> = Vec::new();
+    for first in 0..pieces.len() {
+        let Some(starts) = outgoing.get_mut(&pieces[first].start) else {
+            continue;
+        };
+        let Some(position) = starts.iter().position(|&i| i == first) else {
+            // Already used by another contour
+            continue;
+        };
+        starts.remove(position);
+
+        contour.clear();
+        let mut piece = pieces[first];
+        loop {
+            // Merge pieces that lie on the same line
+            match contour.last_mut() {
+                Some(last) if last.line == piece.line => last.end = piece.end,
+                _ => contour.push(piece),
+            }
+            if piece.end == pieces[first].start {
+                break;
+            }
+            // Every point where a piece ends is the start of another piece
+            let Some(next) = outgoing.get_mut(&piece.end).and_then(|starts| starts.pop()) else {
+                debug_assert!(false, "the outline isn't closed");
+                break;
+            };
+            piece = pieces[next];
+        }
+        if contour.len() > 1 && contour[0].line == contour[contour.len() - 1].line {
+            let last = contour.pop().unwrap();
+            contour[0].start = last.start;
+        }
+
+        vertices.clear();
+        for piece in &contour {
+            let point = POINT { x: to_28_4(piece.start.0), y: to_28_4(piece.start.1) };
+            if vertices.last() != Some(&point) {
+                vertices.push(point);
+            }
+        }
+        while vertices.len() > 1 && vertices.first() == vertices.last() {
+            vertices.pop();
+        }
+        if vertices.len() < 3 {
+            continue;
+        }
+        types.push(PathPointTypeStart);
+        types.resize(types.len() + vertices.len() - 1, PathPointTypeLine);
+        *types.last_mut().unwrap() |= PathPointTypeCloseSubpath;
+        points.extend_from_slice(&vertices);
+    }
+}
+
+// Operands that aren't valid paths are treated as empty, and so is the result when the
+// points don't fit into the rasterizer's range.
+pub(crate) fn boolean(a: &OutputPath, op: BooleanOp, b: &OutputPath) -> OutputPath {
+    let empty = OutputPath { fill_mode: FillMode::Winding, ..Default::default() };
+    let operands = [a, b].map(|path| {
+        let valid = path.points.len() > 1 && validate_path(&path.types, path.points.len()).is_ok();
+        valid.then_some(path)
+    });
+
+    // Use a subpixel row for every 28.4 unit unless the points are too large for that
+    let in_range = |nShift| operands.iter().flatten().all(|path| {
+        path.points.iter().all(|pt| CheckValidRange28_4(pt.x as f32, pt.y as f32, nShift))
+    });
+    let antiAliasMode = if in_range(4) {
+        MilAntiAliasMode::SixteenBySixteen
+    } else if in_range(c_nShift) {
+        MilAntiAliasMode::EightByEight
+    } else {
+        return empty;
+    };
+    let nShift = CSubpixelGrid::FromAntiAliasMode(antiAliasMode).nShift;
+
+    let edgeHead: CEdge = Default::default();
+    let mut edgeTail: CEdge = Default::default();
+    let edgeStore = Arena::new();
+    let mut edgeContext = CInitializeEdgesContext::new(&edgeStore);
+    edgeContext.MaxY = INT::MIN;
+    edgeContext.AntiAliasMode = antiAliasMode;
+    let matrix = CMILMatrix::Identity();
+    for (path, weight) in operands.iter().zip([1, 2]) {
+        let Some(path) = path else {
+            continue;
+        };
+        edgeContext.WindingWeight = weight;
+        let hr = FixedPointPathEnumerate(&path.points, &path.types, path.points.len() as UINT, &matrix, None,
+                                         path.flattening.tolerance, path.flattening.aligns_to_scanlines(), &mut edgeContext);
+        if FAILED(hr) {
+            return empty;
+        }
+    }
+    let nTotalCount = edgeStore.len();
+    if nTotalCount == 0 {
+        return empty;
+    }
+
+    edgeTail.X.set(INT::MAX);
+    edgeTail.StartY = INT::MAX;
+    edgeTail.EndY = INT::MIN;
+    edgeHead.X.set(INT::MIN);
+    edgeHead.Next.set(Ref::new(&edgeTail));
+    let pEdgeActiveList = Ref::new(&edgeHead);
+
+    let mut inactiveArray: Vec<CInactiveEdge> = vec![Default::default(); nTotalCount + 2];
+    let mut nSubpixelYCurrent = InitializeInactiveArray(&edgeStore, &mut inactiveArray, nTotalCount as UINT, Ref::new(&edgeTail));
+    let mut pInactiveEdgeArray = &mut inactiveArray[1..];
+    let mut nSubpixelYNextInactive = nSubpixelYCurrent;
+
+    let mut sweep = Sweep {
+        op,
+        fill_modes: [a.fill_mode, b.fill_mode],
+        pieces: Vec::new(),
+        above: Vec::new(),
+        below: Vec::new(),
+        xs: Vec::new(),
+        bottoms: Vec::new(),
+        bottoms_by_edge: HashMap::new(),
+        runs: Vec::new(),
+        spans: Vec::new(),
+    };
+    let mut band = Band { top: nSubpixelYCurrent, edges: Vec::new(), tops: Vec::new() };
+    loop {
+        if nSubpixelYCurrent == nSubpixelYNextInactive {
+            pInactiveEdgeArray = InsertNewEdges(pEdgeActiveList, nSubpixelYCurrent, pInactiveEdgeArray, &mut nSubpixelYNextInactive);
+        }
+        if !band.continues(pEdgeActiveList) {
+            sweep.add_band(&band, nSubpixelYCurrent);
+            band.start(nSubpixelYCurrent, pEdgeActiveList, &mut sweep);
+        }
+        if pEdgeActiveList.Next.get().EndY == INT::MIN {
+            // Skip the rows without edges
+            if nSubpixelYNextInactive == INT::MAX {
+                break;
+            }
+            nSubpixelYCurrent = nSubpixelYNextInactive;
+        } else {
+            nSubpixelYCurrent += 1;
+            AdvanceDDAAndUpdateActiveEdgeList(nSubpixelYCurrent, pEdgeActiveList);
+        }
+    }
+    // Close the bottom of the last band
+    sweep.add_band(&band, nSubpixelYCurrent);
+
+    // Subpixels are offset by half a pixel from 28.4, see InitializeEdges
+    let to_28_4 = |subpixel: INT| (subpixel << (4 - nShift)) - 8;
+    let mut types = Vec::new();
+    let mut points = Vec::new();
+    build_contours(&sweep.pieces, to_28_4, &mut types, &mut points);
+    OutputPath {
+        fill_mode: FillMode::Winding,
+        points: points.into_boxed_slice(),
+        types: types.into_boxed_slice(),
+        flattening: Default::default(),
+    }
+}
@@ -1,4 +1,5 @@
-use crate::{PathBuilder, OutputPath, OutputVertex, FillMode, rasterize_to_tri_list};
+use crate::{Flattening, PathBuilder, OutputPath, OutputVertex, FillMode, AntialiasMode, CurveFlattening, Batch, BatchVertex, BooleanOp, Mesh, MeshIndices, Rasterizer, VertexRange, RasterError, RasterizeOptions, rasterize_to_tri_list_with_options, try_rasterize_to_indexed_vertices, try_rasterize_to_tri_list};
+use crate::hwvertexbuffer::CHwVertexBuffer;
 use crate::types::{BYTE, POINT};
 
 #[no_mangle]
@@ -42,6 +43,21 @@ pub extern "C" fn wgr_builder_set_fill_mode(pb: &mut PathBuilder, fill_mode: Fil
     pb.set_fill_mode(fill_mode)
 }
 
+#[no_mangle]
+pub extern "C" fn wgr_builder_set_antialias_mode(pb: &mut PathBuilder, antialias_mode: AntialiasMode) {
+    pb.set_antialias_mode(antialias_mode)
+}
+
+#[no_mangle]
+pub extern "C" fn wgr_builder_set_flattening_tolerance(pb: &mut PathBuilder, tolerance: f32) {
+    pb.set_flattening_tolerance(tolerance)
+}
+
+#[no_mangle]
+pub extern "C" fn wgr_builder_set_curve_flattening(pb: &mut PathBuilder, curve_flattening: CurveFlattening) {
+    pb.set_curve_flattening(curve_flattening)
+}
+
 #[repr(C)]
 pub struct Path {
     fill_mode: FillMode,
@@ -49,6 +65,24 @@ pub struct Path {
     num_points: usize,
     types: *const BYTE,
     num_types: usize,
+    flattening_tolerance: f32,
+    curve_flattening: CurveFlattening,
+}
+
+impl Path {
+    fn flattening(&self) -> Flattening {
+        Flattening { tolerance: self.flattening_tolerance, curve_flattening: self.curve_flattening }
+    }
+
+    // The settings of a path that is rasterized into the clip rect
+    fn rasterize_options(&self, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> RasterizeOptions {
+        RasterizeOptions {
+            fill_mode: self.fill_mode,
+            flattening_tolerance: self.flattening_tolerance,
+            curve_flattening: self.curve_flattening,
+            ..RasterizeOptions::new(clip_x, clip_y, clip_width, clip_height)
+        }
+    }
 }
 
 impl From<OutputPath> for Path {
@@ -59,6 +93,8 @@ impl From<OutputPath> for Path {
             num_points: output_path.points.len(),
             types: output_path.types.as_ptr(),
             num_types: output_path.types.len(),
+            flattening_tolerance: output_path.flattening.tolerance,
+            curve_flattening: output_path.flattening.curve_flattening,
         };
         std::mem::forget(output_path);
         path
@@ -83,6 +119,7 @@ impl Into<OutputPath> for Path {
                     Box::from_raw(std::slice::from_raw_parts_mut(self.types as *mut BYTE, self.num_types))
                 }
             },
+            flattening: self.flattening(),
         }
     }
 }
@@ -98,6 +135,33 @@ pub struct VertexBuffer {
     len: usize
 }
 
+impl From<CHwVertexBuffer<'_>> for VertexBuffer {
+    fn from(mut vertex_buffer: CHwVertexBuffer) -> Self {
+        if let Some(output_buffer_size) = vertex_buffer.get_output_buffer_size() {
+            VertexBuffer {
+                data: std::ptr::null(),
+                len: output_buffer_size,
+            }
+        } else {
+            let slice = vertex_buffer.flush_output();
+            let vb = VertexBuffer {
+                data: slice.as_ptr(),
+                len: slice.len(),
+            };
+            std::mem::forget(slice);
+            vb
+        }
+    }
+}
+
+unsafe fn output_buffer_from_raw_parts<'a>(output_ptr: *mut OutputVertex, output_capacity: usize) -> Option<&'a mut [OutputVertex]> {
+    if output_ptr != std::ptr::null_mut() {
+        Some(std::slice::from_raw_parts_mut(output_ptr, output_capacity))
+    } else {
+        None
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn wgr_path_rasterize_to_tri_list(
     path: &Path,
@@ -111,36 +175,331 @@ pub extern "C" fn wgr_path_rasterize_to_tri_list(
     output_ptr: *mut OutputVertex,
     output_capacity: usize,
 ) -> VertexBuffer {
-    let output_buffer = if output_ptr != std::ptr::null_mut() {
-        unsafe { Some(std::slice::from_raw_parts_mut(output_ptr, output_capacity)) }
-    } else {
-        None
+    unsafe {
+        wgr_path_rasterize_to_tri_list_with_aa(path, clip_x, clip_y, clip_width, clip_height, need_inside, need_outside,
+                                               rasterization_truncates, AntialiasMode::default(), output_ptr, output_capacity)
+    }
+}
+
+/// Like `wgr_path_rasterize_to_tri_list`, but coverage is computed on the subpixel grid
+/// given by `antialias_mode`.
+///
+/// # Safety
+///
+/// `path` has to point to valid points and types, e.g. from `wgr_builder_get_path`.
+/// `output_ptr` has to be null or point to `output_capacity` writable vertices.
+#[no_mangle]
+pub unsafe extern "C" fn wgr_path_rasterize_to_tri_list_with_aa(
+    path: &Path,
+    clip_x: i32,
+    clip_y: i32,
+    clip_width: i32,
+    clip_height: i32,
+    need_inside: bool,
+    need_outside: bool,
+    rasterization_truncates: bool,
+    antialias_mode: AntialiasMode,
+    output_ptr: *mut OutputVertex,
+    output_capacity: usize,
+) -> VertexBuffer {
+    let output_buffer = output_buffer_from_raw_parts(output_ptr, output_capacity);
+    let options = RasterizeOptions {
+        antialias_mode,
+        need_inside,
+        need_outside,
+        rasterization_truncates,
+        ..path.rasterize_options(clip_x, clip_y, clip_width, clip_height)
     };
-    let mut result = rasterize_to_tri_list(
-        path.fill_mode,
+    let result = rasterize_to_tri_list_with_options(
+        std::slice::from_raw_parts(path.types, path.num_types),
+        std::slice::from_raw_parts(path.points, path.num_points),
+        &options,
+        output_buffer
+    );
+    VertexBuffer::from(result)
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RasterErrorCode {
+    Ok = 0,
+    OutOfRange = 1,
+    NaN = 2,
+    EmptyFill = 3,
+    ClippedToEmpty = 4,
+    InvalidPathTypes = 5,
+    OutputBufferTooSmall = 6,
+    Internal = 7,
+}
+
+/// `point_index` is set for `OutOfRange` and `NaN`, `required` is the number of vertices
+/// that are needed for `OutputBufferTooSmall` and `hresult` is the HRESULT the rasterizer
+/// failed with for `Internal`.
+#[repr(C)]
+pub struct RasterStatus {
+    code: RasterErrorCode,
+    point_index: usize,
+    required: usize,
+    hresult: i32,
+}
+
+impl From<Result<(), RasterError>> for RasterStatus {
+    fn from(result: Result<(), RasterError>) -> Self {
+        let mut status = RasterStatus { code: RasterErrorCode::Ok, point_index: 0, required: 0, hresult: 0 };
+        match result {
+            Ok(()) => {}
+            Err(RasterError::OutOfRange { point_index }) => {
+                status.code = RasterErrorCode::OutOfRange;
+                status.point_index = point_index;
+            }
+            Err(RasterError::NaN { point_index }) => {
+                status.code = RasterErrorCode::NaN;
+                status.point_index = point_index;
+            }
+            Err(RasterError::EmptyFill) => status.code = RasterErrorCode::EmptyFill,
+            Err(RasterError::ClippedToEmpty) => status.code = RasterErrorCode::ClippedToEmpty,
+            Err(RasterError::InvalidPathTypes) => status.code = RasterErrorCode::InvalidPathTypes,
+            Err(RasterError::OutputBufferTooSmall { required }) => {
+                status.code = RasterErrorCode::OutputBufferTooSmall;
+                status.required = required;
+            }
+            Err(RasterError::Internal { hresult }) => {
+                status.code = RasterErrorCode::Internal;
+                status.hresult = hresult;
+            }
+        }
+        status
+    }
+}
+
+/// Like `wgr_path_rasterize_to_tri_list_with_aa`, but `status` is set to the reason why the path
+/// couldn't be rasterized. On failure the returned vertex buffer is empty.
+///
+/// # Safety
+///
+/// `path` has to point to valid points and types, e.g. from `wgr_builder_get_path`.
+/// `output_ptr` has to be null or point to `output_capacity` writable vertices.
+#[no_mangle]
+pub unsafe extern "C" fn wgr_path_try_rasterize_to_tri_list(
+    path: &Path,
+    clip_x: i32,
+    clip_y: i32,
+    clip_width: i32,
+    clip_height: i32,
+    need_inside: bool,
+    need_outside: bool,
+    rasterization_truncates: bool,
+    antialias_mode: AntialiasMode,
+    output_ptr: *mut OutputVertex,
+    output_capacity: usize,
+    status: &mut RasterStatus,
+) -> VertexBuffer {
+    let output_buffer = unsafe { output_buffer_from_raw_parts(output_ptr, output_capacity) };
+    let options = RasterizeOptions {
+        antialias_mode,
+        need_inside,
+        need_outside,
+        rasterization_truncates,
+        ..path.rasterize_options(clip_x, clip_y, clip_width, clip_height)
+    };
+    let result = try_rasterize_to_tri_list(
         unsafe { std::slice::from_raw_parts(path.types, path.num_types) },
         unsafe { std::slice::from_raw_parts(path.points, path.num_points) },
-        clip_x, clip_y, clip_width, clip_height,
-        need_inside, need_outside,
-        rasterization_truncates,
+        &options,
         output_buffer
     );
-    if let Some(output_buffer_size) = result.get_output_buffer_size() {
-        VertexBuffer {
-            data: std::ptr::null(),
-            len: output_buffer_size,
+    let result = match result {
+        Ok(result) => result,
+        Err(error) => {
+            *status = Err(error).into();
+            return VertexBuffer { data: std::ptr::null(), len: 0 };
         }
-    } else {
-        let slice = result.flush_output();
-        let vb = VertexBuffer {
-            data: slice.as_ptr(),
-            len: slice.len(),
+    };
+    *status = Ok(()).into();
+    VertexBuffer::from(result)
+}
+
+// An indexed triangle list. index_size is 2 for u16 indices and 4 for u32 indices.
+#[repr(C)]
+pub struct IndexedVertexBuffer {
+    vertices: *const OutputVertex,
+    num_vertices: usize,
+    indices: *const std::ffi::c_void,
+    index_size: usize,
+    num_indices: usize,
+}
+
+impl From<Mesh> for IndexedVertexBuffer {
+    fn from(mesh: Mesh) -> Self {
+        let Mesh { vertices, indices } = mesh;
+        let (num_indices, index_size, indices) = match indices {
+            MeshIndices::U16(indices) => (indices.len(), 2, Box::into_raw(indices) as *const u16 as *const _),
+            MeshIndices::U32(indices) => (indices.len(), 4, Box::into_raw(indices) as *const u32 as *const _),
         };
-        std::mem::forget(slice);
-        vb
+        IndexedVertexBuffer {
+            num_vertices: vertices.len(),
+            vertices: Box::into_raw(vertices) as *const OutputVertex,
+            indices,
+            index_size,
+            num_indices,
+        }
+    }
+}
+
+/// Like `wgr_path_rasterize_to_tri_list_with_aa`, but the triangles share their vertices through
+/// an index buffer.
+#[no_mangle]
+pub extern "C" fn wgr_path_rasterize_to_mesh(
+    path: &Path,
+    clip_x: i32,
+    clip_y: i32,
+    clip_width: i32,
+    clip_height: i32,
+    need_inside: bool,
+    need_outside: bool,
+    rasterization_truncates: bool,
+    antialias_mode: AntialiasMode,
+) -> IndexedVertexBuffer {
+    let options = RasterizeOptions {
+        antialias_mode,
+        need_inside,
+        need_outside,
+        rasterization_truncates,
+        ..path.rasterize_options(clip_x, clip_y, clip_width, clip_height)
+    };
+    let result = try_rasterize_to_indexed_vertices(
+        unsafe { std::slice::from_raw_parts(path.types, path.num_types) },
+        unsafe { std::slice::from_raw_parts(path.points, path.num_points) },
+        &options
+    );
+    // Failures just leave the mesh empty
+    IndexedVertexBuffer::from(result.unwrap_or_default())
+}
+
+// Copies a path that is still owned by the caller
+fn copy_output_path(path: &Path) -> OutputPath {
+    OutputPath {
+        fill_mode: path.fill_mode,
+        points: if path.points.is_null() {
+            Default::default()
+        } else {
+            Box::from(unsafe { std::slice::from_raw_parts(path.points, path.num_points) })
+        },
+        types: if path.types.is_null() {
+            Default::default()
+        } else {
+            Box::from(unsafe { std::slice::from_raw_parts(path.types, path.num_types) })
+        },
+        flattening: path.flattening(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wgr_path_boolean(path: &Path, op: BooleanOp, other: &Path) -> Path {
+    Path::from(copy_output_path(path).boolean(op, &copy_output_path(other)))
+}
+
+#[no_mangle]
+pub extern "C" fn wgr_new_batch() -> *mut Batch {
+    let batch = Batch::new();
+    Box::into_raw(Box::new(batch))
+}
+
+#[no_mangle]
+pub extern "C" fn wgr_batch_add_path(
+    batch: &mut Batch,
+    pb: &PathBuilder,
+    clip_x: i32,
+    clip_y: i32,
+    clip_width: i32,
+    clip_height: i32,
+) -> u32 {
+    batch.add_path(pb, clip_x, clip_y, clip_width, clip_height)
+}
+
+// The contents of a batch. The pointers stay valid until the batch is changed or released.
+#[repr(C)]
+pub struct BatchData {
+    vertices: *const BatchVertex,
+    num_vertices: usize,
+    ranges: *const VertexRange,
+    num_ranges: usize,
+}
+
+#[no_mangle]
+pub extern "C" fn wgr_batch_get_data(batch: &Batch) -> BatchData {
+    BatchData {
+        vertices: batch.vertices().as_ptr(),
+        num_vertices: batch.vertices().len(),
+        ranges: batch.ranges().as_ptr(),
+        num_ranges: batch.ranges().len(),
     }
 }
 
+#[no_mangle]
+pub extern "C" fn wgr_batch_clear(batch: &mut Batch) {
+    batch.clear()
+}
+
+/// Frees a batch along with its vertices and ranges.
+///
+/// # Safety
+///
+/// `batch` has to come from `wgr_new_batch` and can only be released once. Pointers from
+/// `wgr_batch_get_data` are invalid once the batch is released.
+#[no_mangle]
+pub unsafe extern "C" fn wgr_batch_release(batch: *mut Batch) {
+    drop(Box::from_raw(batch));
+}
+
+#[no_mangle]
+pub extern "C" fn wgr_new_rasterizer() -> *mut Rasterizer {
+    let rasterizer = Rasterizer::new();
+    Box::into_raw(Box::new(rasterizer))
+}
+
+// The triangle list of the last path rasterized by a rasterizer. The pointer stays valid
+// until the rasterizer is used again or released.
+#[repr(C)]
+pub struct RasterizerData {
+    vertices: *const OutputVertex,
+    num_vertices: usize,
+}
+
+#[no_mangle]
+pub extern "C" fn wgr_rasterizer_rasterize_builder(
+    rasterizer: &mut Rasterizer,
+    pb: &PathBuilder,
+    clip_x: i32,
+    clip_y: i32,
+    clip_width: i32,
+    clip_height: i32,
+    status: &mut RasterStatus,
+) -> RasterizerData {
+    let result = rasterizer.try_rasterize_to_tri_list(pb, clip_x, clip_y, clip_width, clip_height);
+    let vertices = match result {
+        Ok(vertices) => vertices,
+        Err(error) => {
+            *status = Err(error).into();
+            return RasterizerData { vertices: std::ptr::null(), num_vertices: 0 };
+        }
+    };
+    *status = Ok(()).into();
+    RasterizerData { vertices: vertices.as_ptr(), num_vertices: vertices.len() }
+}
+
+/// Frees a rasterizer along with the buffers it keeps between paths.
+///
+/// # Safety
+///
+/// `rasterizer` has to come from `wgr_new_rasterizer` and can only be released once. The
+/// vertices from `wgr_rasterizer_rasterize_builder` are invalid once the rasterizer is
+/// released.
+#[no_mangle]
+pub unsafe extern "C" fn wgr_rasterizer_release(rasterizer: *mut Rasterizer) {
+    drop(Box::from_raw(rasterizer));
+}
+
 #[no_mangle]
 pub extern "C" fn wgr_path_release(path: Path) {
     let output_path: OutputPath = path.into();
@@ -157,6 +516,22 @@ pub extern "C" fn wgr_vertex_buffer_release(vb: VertexBuffer)
     }
 }
 
+/// Frees the vertices and indices of a mesh.
+///
+/// # Safety
+///
+/// `vb` has to come from `wgr_path_rasterize_to_mesh` unchanged and can only be released
+/// once.
+#[no_mangle]
+pub unsafe extern "C" fn wgr_indexed_vertex_buffer_release(vb: IndexedVertexBuffer) {
+    drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(vb.vertices as *mut OutputVertex, vb.num_vertices)));
+    if vb.index_size == 2 {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(vb.indices as *mut u16, vb.num_indices)));
+    } else {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(vb.indices as *mut u32, vb.num_indices)));
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn wgr_builder_release(pb: *mut PathBuilder) {
     drop(Box::from_raw(pb));
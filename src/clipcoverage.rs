@@ -0,0 +1,175 @@
+//------------------------------------------------------------------------------
+//
+//  Description:
+//      Coverage of a clip path.  The clip path is rasterized into complex
+//      scans first, which are kept by CClipCoverage.  While the clipped path
+//      is rasterized, each of its complex scans is then multiplied with the
+//      clip's scan for the same row.
+//
+
+use crate::aacoverage::{CCoverageInterval, CSubpixelGrid};
+use crate::arena::recycle_vec;
+use crate::geometry_sink::IGeometrySink;
+use crate::nullable_ref::Ref;
+use crate::types::*;
+
+#[derive(Default)]
+pub struct CClipCoverage {
+    // Scans sorted by pixel y.  Each scan is a list of (pixel x, coverage)
+    // intervals that starts at INT::MIN and ends at INT::MAX.
+    m_rgScans: Vec<(INT, Vec<(INT, INT)>)>,
+    m_nFullCoverage: INT,
+}
+
+// Buffers that clipped scans are built in, so that they can be reused from one row to the
+// next.  They are kept by each rasterizer because the CClipCoverage is shared by the
+// rasterizers of all bands.
+#[derive(Default)]
+pub struct CClipScanBuffers {
+    m_rgClipped: Vec<(INT, INT)>,
+    m_rgIntervals: Vec<CCoverageInterval<'static>>,
+}
+
+impl CClipCoverage {
+    pub fn new() -> Self {
+        Self {
+            m_rgScans: Vec::new(),
+            m_nFullCoverage: CSubpixelGrid::default().nShiftSizeSquared,
+        }
+    }
+
+    // Returns the intervals of row nPixelY, which are empty when the row
+    // isn't covered by the clip at all.
+    fn GetScan(&self, nPixelY: INT) -> &[(INT, INT)] {
+        match self.m_rgScans.binary_search_by_key(&nPixelY, |scan| scan.0) {
+            Ok(i) => &self.m_rgScans[i].1,
+            Err(_) => &[],
+        }
+    }
+
+    //-------------------------------------------------------------------------
+    //
+    //  Function:   CClipCoverage::ClipScan
+    //
+    //  Synopsis:
+    //      Multiplies the coverage of the intervals that start at
+    //      pIntervalSpanStart with the clip's coverage of row nPixelY.  The
+    //      resulting intervals, including the sentinel, are written to
+    //      rgIntervals.
+    //
+    //-------------------------------------------------------------------------
+    pub fn ClipScan(&self,
+        nPixelY: INT,
+        pIntervalSpanStart: Ref<CCoverageInterval>,
+        rgIntervals: &mut Vec<(INT, INT)>
+        )
+    {
+        rgIntervals.clear();
+        let rgClip = self.GetScan(nPixelY);
+
+        let mut pInterval = pIntervalSpanStart;
+        let mut iClip = 0;
+        let mut nPixelX = pInterval.m_nPixelX.get();
+        while nPixelX != INT::MAX
+        {
+            // Advance to the clip interval that contains nPixelX
+            while iClip + 1 < rgClip.len() && rgClip[iClip + 1].0 <= nPixelX
+            {
+                iClip += 1;
+            }
+            let nClipCoverage = if rgClip.is_empty() { 0 } else { rgClip[iClip].1 };
+
+            let nCoverage = ((pInterval.m_nCoverage.get() as i64 * nClipCoverage as i64
+                + (self.m_nFullCoverage / 2) as i64) / self.m_nFullCoverage as i64) as INT;
+            if rgIntervals.last().map(|interval| interval.1) != Some(nCoverage)
+            {
+                rgIntervals.push((nPixelX, nCoverage));
+            }
+
+            // The next interval starts at the next fill or clip boundary
+            let nNextFillX = pInterval.m_pNext.get().m_nPixelX.get();
+            let nNextClipX = rgClip.get(iClip + 1).map_or(INT::MAX, |interval| interval.0);
+            nPixelX = nNextFillX.min(nNextClipX);
+            if nNextFillX == nPixelX
+            {
+                pInterval = pInterval.m_pNext.get();
+            }
+        }
+        rgIntervals.push((INT::MAX, 0));
+    }
+
+    //-------------------------------------------------------------------------
+    //
+    //  Function:   CClipCoverage::AddClippedScan
+    //
+    //  Synopsis:
+    //      Clips the intervals that start at pIntervalSpanStart, see ClipScan,
+    //      and outputs them to pIGeometrySink as the complex scan of row
+    //      nPixelY.
+    //
+    //-------------------------------------------------------------------------
+    pub fn AddClippedScan(&self,
+        nPixelY: INT,
+        pIntervalSpanStart: Ref<CCoverageInterval>,
+        buffers: &mut CClipScanBuffers,
+        pIGeometrySink: &mut dyn IGeometrySink
+        ) -> HRESULT
+    {
+        self.ClipScan(nPixelY, pIntervalSpanStart, &mut buffers.m_rgClipped);
+
+        let mut rgIntervals: Vec<CCoverageInterval> = recycle_vec(std::mem::take(&mut buffers.m_rgIntervals));
+        rgIntervals.extend(buffers.m_rgClipped.iter().map(|&(nPixelX, nCoverage)| {
+            let interval: CCoverageInterval = Default::default();
+            interval.m_nPixelX.set(nPixelX);
+            interval.m_nCoverage.set(nCoverage);
+            interval
+        }));
+        let pIntervals: *const CCoverageInterval = rgIntervals.as_ptr();
+        for i in 1..rgIntervals.len()
+        {
+            // The intervals point at each other. rgIntervals isn't modified until the
+            // scan has been output, so they stay in place for as long as they are used.
+            rgIntervals[i - 1].m_pNext.set(Ref::new(unsafe { &*pIntervals.add(i) }));
+        }
+        let hr = pIGeometrySink.AddComplexScan(nPixelY, Ref::new(&rgIntervals[0]));
+        buffers.m_rgIntervals = recycle_vec(rgIntervals);
+        hr
+    }
+}
+
+impl IGeometrySink for CClipCoverage {
+    fn AddComplexScan(&mut self, nPixelY: INT, pIntervalSpanStart: Ref<CCoverageInterval>) -> HRESULT {
+        let mut rgIntervals = Vec::new();
+        let mut pInterval = pIntervalSpanStart;
+        while pInterval.m_nPixelX.get() != INT::MAX
+        {
+            rgIntervals.push((pInterval.m_nPixelX.get(), pInterval.m_nCoverage.get()));
+            pInterval = pInterval.m_pNext.get();
+        }
+        debug_assert!(self.m_rgScans.last().map(|scan| scan.0 < nPixelY).unwrap_or(true));
+        self.m_rgScans.push((nPixelY, rgIntervals));
+        S_OK
+    }
+
+    fn AddTrapezoid(
+        &mut self,
+        _rYMin: f32,
+        _rXLeftYMin: f32,
+        _rXRightYMin: f32,
+        _rYMax: f32,
+        _rXLeftYMax: f32,
+        _rXRightYMax: f32,
+        _rXDeltaLeft: f32,
+        _rXDeltaRight: f32,
+    ) -> HRESULT {
+        unreachable!("clip paths are rasterized without trapezoids");
+    }
+
+    fn IsEmpty(&self) -> bool {
+        self.m_rgScans.is_empty()
+    }
+
+    fn SetSubpixelGrid(&mut self, grid: CSubpixelGrid) {
+        self.m_nFullCoverage = grid.nShiftSizeSquared;
+    }
+}
@@ -0,0 +1,75 @@
+// The errors reported by the Result returning rasterization functions. Internally the
+// rasterizer reports failures as HRESULTs, which are mapped to a RasterError here.
+
+use std::fmt;
+
+use crate::aarasterizer::ValidatePathTypes;
+use crate::types::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum RasterError {
+    /// A point is outside of the range the rasterizer can handle, either as given or
+    /// after the transform is applied. Finer antialiasing modes have smaller ranges.
+    OutOfRange { point_index: usize },
+    /// A point is NaN, either as given or after the transform is applied.
+    NaN { point_index: usize },
+    /// The path doesn't cover any pixels, e.g. because it has no area.
+    EmptyFill,
+    /// The path doesn't overlap the clip rect.
+    ClippedToEmpty,
+    /// The path types don't form valid subpaths or don't match the number of points.
+    InvalidPathTypes,
+    /// The output buffer can't hold the result. `required` is the number of vertices that
+    /// are needed.
+    OutputBufferTooSmall { required: usize },
+    /// The rasterizer failed for a reason that isn't covered by the other errors. `hresult`
+    /// is the HRESULT it failed with.
+    Internal { hresult: i32 },
+}
+
+impl fmt::Display for RasterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RasterError::OutOfRange { point_index } => write!(f, "point {} is out of range", point_index),
+            RasterError::NaN { point_index } => write!(f, "point {} is NaN", point_index),
+            RasterError::EmptyFill => write!(f, "the path is empty"),
+            RasterError::ClippedToEmpty => write!(f, "the path is clipped out"),
+            RasterError::InvalidPathTypes => write!(f, "invalid path types"),
+            RasterError::OutputBufferTooSmall { required } => {
+                write!(f, "the output buffer is too small, {} vertices are needed", required)
+            }
+            RasterError::Internal { hresult } => write!(f, "the rasterizer failed with HRESULT {:#010x}", hresult),
+        }
+    }
+}
+
+impl std::error::Error for RasterError {}
+
+// Checks that the types form valid subpaths that use exactly point_count points.
+pub(crate) fn validate_path(types: &[BYTE], point_count: usize) -> Result<(), RasterError> {
+    let required: usize = types.iter().map(|&t| {
+        match t & PathPointTypePathTypeMask {
+            PathPointTypeBezier => 3,
+            PathPointTypeQuadratic => 2,
+            _ => 1,
+        }
+    }).sum();
+    if required != point_count || point_count > INT::MAX as usize || !ValidatePathTypes(types, point_count as INT) {
+        return Err(RasterError::InvalidPathTypes);
+    }
+    Ok(())
+}
+
+// Maps the result of CHwRasterizer::SendGeometry to a RasterError. invalid_point is the
+// index of the point that made it fail, if any. NaN and out of range points are only
+// reported as such when the rasterizer knows which point it was.
+pub(crate) fn check_hresult(hr: HRESULT, invalid_point: Option<usize>) -> Result<(), RasterError> {
+    match (hr, invalid_point) {
+        (S_OK, _) => Ok(()),
+        (WGXERR_BADNUMBER, Some(point_index)) => Err(RasterError::NaN { point_index }),
+        (WGXERR_VALUEOVERFLOW, Some(point_index)) => Err(RasterError::OutOfRange { point_index }),
+        (WGXHR_EMPTYFILL, _) => Err(RasterError::EmptyFill),
+        (WGXHR_CLIPPEDTOEMPTY, _) => Err(RasterError::ClippedToEmpty),
+        (hresult, _) => Err(RasterError::Internal { hresult }),
+    }
+}
@@ -0,0 +1,227 @@
+// Walks the segments of a path in the same format that FixedPointPathEnumerate consumes and
+// flattens its Beziers with CMILBezier in the same mode, so that consumers see the same
+// polylines that the rasterizer sees. FlattenedSubpaths is also public, for hit testing, measuring and
+// exporting paths.
+//
+// Paths whose points don't fit into 28.4 can only be flattened in floating point, see
+// for_each_flattened_float_subpath. Their curves are split into segments of equal parameter
+// length, with the number of segments given by Wang's formula.
+
+use crate::bezier::CMILBezier;
+use crate::types::*;
+use crate::Flattening;
+
+/// A flattened subpath, see `OutputPath::flatten`.
+#[derive(Clone, Default, PartialEq)]
+pub struct FlattenedSubpath {
+    points: Vec<POINT>,
+    closed: bool,
+}
+
+impl FlattenedSubpath {
+    /// The points of the polyline in 28.4 fixed point, like `OutputPath::points`. The
+    /// first point is the start point of the subpath. A closed subpath does not repeat its
+    /// start point unless the path itself did.
+    pub fn points(&self) -> &[POINT] {
+        &self.points
+    }
+
+    /// The points of the polyline in pixels, in the coordinates that were passed to
+    /// `PathBuilder`.
+    pub fn pixel_points(&self) -> impl Iterator<Item = (f32, f32)> + '_ {
+        self.points.iter().map(|&point| to_pixel(point))
+    }
+
+    /// Whether the subpath was closed. Fills treat every subpath as closed.
+    pub fn is_closed(&self) -> bool {
+        self.closed
+    }
+}
+
+/// Iterates over the subpaths of a path with their curves flattened into lines, see
+/// `OutputPath::flatten`.
+pub struct FlattenedSubpaths<'a> {
+    types: &'a [BYTE],
+    points: &'a [POINT],
+    flattening: Flattening,
+    i_type: usize,
+    i_point: usize,
+    buffer: [POINT; 16],
+}
+
+impl<'a> FlattenedSubpaths<'a> {
+    // The types and points have to form a valid path, see error::validate_path. Beziers
+    // are flattened with `flattening`, like FixedPointPathEnumerate does.
+    pub(crate) fn new(types: &'a [BYTE], points: &'a [POINT], flattening: Flattening) -> Self {
+        FlattenedSubpaths { types, points, flattening, i_type: 0, i_point: 0, buffer: Default::default() }
+    }
+
+    fn flatten(&mut self, mut bezier: CMILBezier, polyline: &mut Vec<POINT>) {
+        let mut more = true;
+        while more {
+            let count = bezier.Flatten(&mut self.buffer, &mut more) as usize;
+            polyline.extend_from_slice(&self.buffer[..count]);
+        }
+    }
+}
+
+impl Iterator for FlattenedSubpaths<'_> {
+    type Item = FlattenedSubpath;
+
+    fn next(&mut self) -> Option<FlattenedSubpath> {
+        let points = self.points;
+        let mut polyline: Vec<POINT> = Vec::new();
+        while let Some(&ty) = self.types.get(self.i_type) {
+            match ty & PathPointTypePathTypeMask {
+                PathPointTypeStart => {
+                    if !polyline.is_empty() {
+                        // Leave the start point for the next subpath
+                        return Some(FlattenedSubpath { points: polyline, closed: false });
+                    }
+                    polyline.push(points[self.i_point]);
+                    self.i_point += 1;
+                }
+                PathPointTypeLine => {
+                    polyline.push(points[self.i_point]);
+                    self.i_point += 1;
+                }
+                PathPointTypeBezier => {
+                    let i = self.i_point;
+                    let bezier_points = [points[i - 1], points[i], points[i + 1], points[i + 2]];
+                    let Flattening { tolerance, .. } = self.flattening;
+                    let bezier = if self.flattening.aligns_to_scanlines() {
+                        CMILBezier::newAlignedToScanlines(&bezier_points, None, tolerance)
+                    } else {
+                        CMILBezier::new(&bezier_points, None, tolerance)
+                    };
+                    self.flatten(bezier, &mut polyline);
+                    // The flattener doesn't necessarily finish on the last control point
+                    if polyline.last() != Some(&bezier_points[3]) {
+                        polyline.push(bezier_points[3]);
+                    }
+                    self.i_point += 3;
+                }
+                PathPointTypeQuadratic => {
+                    let i = self.i_point;
+                    let quadratic_points = [points[i - 1], points[i], points[i + 1]];
+                    let Flattening { tolerance, .. } = self.flattening;
+                    let quadratic = if self.flattening.aligns_to_scanlines() {
+                        CMILBezier::newQuadraticAlignedToScanlines(&quadratic_points, None, tolerance)
+                    } else {
+                        CMILBezier::newQuadratic(&quadratic_points, None, tolerance)
+                    };
+                    self.flatten(quadratic, &mut polyline);
+                    self.i_point += 2;
+                }
+                _ => unreachable!("unknown path point type"),
+            }
+            self.i_type += 1;
+            if ty & PathPointTypeCloseSubpath != 0 {
+                return Some(FlattenedSubpath { points: polyline, closed: true });
+            }
+        }
+        if polyline.is_empty() {
+            None
+        } else {
+            Some(FlattenedSubpath { points: polyline, closed: false })
+        }
+    }
+}
+
+// Calls `f` with the flattened 28.4 points of each subpath along with whether the subpath
+// was closed, see FlattenedSubpaths.
+pub(crate) fn for_each_flattened_subpath(types: &[BYTE], points: &[POINT], flattening: Flattening, mut f: impl FnMut(&[POINT], bool)) {
+    for subpath in FlattenedSubpaths::new(types, points, flattening) {
+        f(&subpath.points, subpath.closed);
+    }
+}
+
+// Curves are never split into more segments than this, so that a curve with far away control
+// points can't produce an unbounded number of points. The parts of such a curve that end up
+// being drawn get clipped down to a small part of it anyway.
+const MAX_FLOAT_CURVE_SEGMENTS: f32 = 1024.;
+
+// Like for_each_flattened_subpath, but for points in pixels that may be outside of the 28.4
+// range. Beziers are flattened to within `tolerance` pixels.
+pub(crate) fn for_each_flattened_float_subpath(types: &[BYTE], points: &[MilPoint2F], tolerance: f32, mut f: impl FnMut(&[MilPoint2F], bool)) {
+    let mut polyline: Vec<MilPoint2F> = Vec::new();
+    let mut i_point = 0;
+    for &ty in types {
+        match ty & PathPointTypePathTypeMask {
+            PathPointTypeStart => {
+                if !polyline.is_empty() {
+                    f(&polyline, false);
+                    polyline.clear();
+                }
+                polyline.push(points[i_point]);
+                i_point += 1;
+            }
+            PathPointTypeLine => {
+                polyline.push(points[i_point]);
+                i_point += 1;
+            }
+            PathPointTypeBezier => {
+                let (p0, p1, p2, p3) = (points[i_point - 1], points[i_point], points[i_point + 1], points[i_point + 2]);
+                // The second differences of the control points bound how far the curve
+                // can get from its chords
+                let dd = second_difference(p0, p1, p2).max(second_difference(p1, p2, p3));
+                let segments = float_curve_segments(0.75 * dd, tolerance);
+                for i in 1..segments {
+                    let t = i as f32 / segments as f32;
+                    let s = 1. - t;
+                    let (a, b, c, d) = (s * s * s, 3. * s * s * t, 3. * s * t * t, t * t * t);
+                    polyline.push(MilPoint2F {
+                        X: a * p0.X + b * p1.X + c * p2.X + d * p3.X,
+                        Y: a * p0.Y + b * p1.Y + c * p2.Y + d * p3.Y,
+                    });
+                }
+                polyline.push(p3);
+                i_point += 3;
+            }
+            PathPointTypeQuadratic => {
+                let (p0, p1, p2) = (points[i_point - 1], points[i_point], points[i_point + 1]);
+                let segments = float_curve_segments(0.25 * second_difference(p0, p1, p2), tolerance);
+                for i in 1..segments {
+                    let t = i as f32 / segments as f32;
+                    let s = 1. - t;
+                    let (a, b, c) = (s * s, 2. * s * t, t * t);
+                    polyline.push(MilPoint2F {
+                        X: a * p0.X + b * p1.X + c * p2.X,
+                        Y: a * p0.Y + b * p1.Y + c * p2.Y,
+                    });
+                }
+                polyline.push(p2);
+                i_point += 2;
+            }
+            _ => unreachable!("unknown path point type"),
+        }
+        if ty & PathPointTypeCloseSubpath != 0 {
+            f(&polyline, true);
+            polyline.clear();
+        }
+    }
+    if !polyline.is_empty() {
+        f(&polyline, false);
+    }
+}
+
+fn second_difference(a: MilPoint2F, b: MilPoint2F, c: MilPoint2F) -> f32 {
+    (a.X - 2. * b.X + c.X).hypot(a.Y - 2. * b.Y + c.Y)
+}
+
+// Returns the number of segments that keep a curve within tolerance of its chords, given
+// the curve's second difference bound scaled by Wang's formula
+fn float_curve_segments(bound: f32, tolerance: f32) -> usize {
+    let segments = (bound / tolerance).sqrt().ceil();
+    if segments.is_nan() {
+        1
+    } else {
+        segments.clamp(1., MAX_FLOAT_CURVE_SEGMENTS) as usize
+    }
+}
+
+// Converts a 28.4 point with the pixel center at 0 back to a pixel space point with
+// the pixel center at 0.5. This is the inverse of the conversion in PathBuilder::add_point.
+pub(crate) fn to_pixel(point: POINT) -> (f32, f32) {
+    (point.x as f32 / 16. + 0.5, point.y as f32 / 16. + 0.5)
+}
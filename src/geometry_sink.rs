@@ -1,4 +1,4 @@
-use crate::aacoverage::CCoverageInterval;
+use crate::aacoverage::{CCoverageInterval, CSubpixelGrid};
 use crate::nullable_ref::Ref;
 use crate::types::*;
 
@@ -75,6 +75,15 @@ pub trait IGeometrySink
         ) -> HRESULT;
 
     fn IsEmpty(&self) -> bool;
+
+    //
+    // The subpixel grid that complex scan coverage is computed in
+    //
+
+    fn SetSubpixelGrid(&mut self,
+        grid: CSubpixelGrid
+            // In: shifts/masks of the grid
+        );
     /*
     virtual HRESULT AddParallelogram(
         __in_ecount(4) const MilPoint2F *rgPosition
@@ -90,3 +99,41 @@ pub trait IGeometrySink
     virtual BOOL IsEmpty() PURE;
 */
 }
+
+//-------------------------------------------------------------------------
+//
+//  Class:   CNullGeometrySink
+//
+//  Synopsis:
+//      A sink that drops all geometry, for rasterizers that don't output
+//      anything, like the planner of CHwRasterizer::PlanBands.
+//
+//-------------------------------------------------------------------------
+#[derive(Default)]
+pub struct CNullGeometrySink;
+
+impl IGeometrySink for CNullGeometrySink {
+    fn AddComplexScan(&mut self, _nPixelY: INT, _pIntervalSpanStart: Ref<CCoverageInterval>) -> HRESULT {
+        S_OK
+    }
+
+    fn AddTrapezoid(
+        &mut self,
+        _rYMin: f32,
+        _rXLeftYMin: f32,
+        _rXRightYMin: f32,
+        _rYMax: f32,
+        _rXLeftYMax: f32,
+        _rXRightYMax: f32,
+        _rXDeltaLeft: f32,
+        _rXDeltaRight: f32,
+    ) -> HRESULT {
+        S_OK
+    }
+
+    fn IsEmpty(&self) -> bool {
+        true
+    }
+
+    fn SetSubpixelGrid(&mut self, _grid: CSubpixelGrid) {}
+}
@@ -4,15 +4,18 @@
 
 #![allow(unused_parens)]
 
-use crate::aacoverage::{CCoverageBuffer, c_rInvShiftSize, c_antiAliasMode, c_nShift, CCoverageInterval, c_nShiftMask, c_nShiftSize, c_nHalfShiftSize};
-use crate::hwvertexbuffer::CHwVertexBufferBuilder;
+use std::cell::Cell;
+
+use crate::aacoverage::{CCoverageBuffer, CCoverageIntervalBuffer, CSubpixelGrid, c_antiAliasMode, c_nShift, CCoverageInterval};
+use crate::arena::{Arena, recycle_chunks, recycle_vec};
+use crate::bezier::BEZIER_DEFAULT_TOLERANCE;
 use crate::matrix::{CMILMatrix, CMatrix};
 use crate::nullable_ref::Ref;
 use crate::aarasterizer::*;
+use crate::clipcoverage::{CClipCoverage, CClipScanBuffers};
 use crate::geometry_sink::IGeometrySink;
 use crate::helpers::Int32x32To64;
 use crate::types::*;
-use typed_arena_nomut::Arena;
 
 //-----------------------------------------------------------------------------
 //
@@ -57,14 +60,15 @@ macro_rules! MIL_THR {
 //        Subpixel - Overscaled space.
 //
 //        To convert between Pixel to Subpixel, we have:
-//            nSubpixelCoordinate = nPixelCoordinate << c_nShift;
-//            nPixelCoordinate = nSubpixelCoordinate >> c_nShift;
+//            nSubpixelCoordinate = nPixelCoordinate << nShift;
+//            nPixelCoordinate = nSubpixelCoordinate >> nShift;
 //
 //        Note that the conversion to nPixelCoordinate needs to also track
-//        (nSubpixelCoordinate & c_nShiftMask) to maintain the full value.
+//        (nSubpixelCoordinate & nShiftMask) to maintain the full value.
 //
-//        Note that since trapezoidal only supports 8x8, c_nShiftSize is always equal to 8.  So,
-//        (1, 2) in pixel space would become (8, 16) in subpixel space.
+//        The shift comes from the CSubpixelGrid of the antialiasing mode.  In the
+//        default 8x8 mode nShiftSize is equal to 8.  So, (1, 2) in pixel space
+//        would become (8, 16) in subpixel space.
 //
 //    [X|Y]
 //        Indicates which coordinate is being referred to.
@@ -223,9 +227,11 @@ AdvanceDDAMultipleSteps(
     }
 
     // At this point, the subtraction above should have generated an error that is within
-    // (-pLeft->ErrorDown, 0)
+    // [-pLeft->ErrorDown, 0).  The error can only reach -ErrorDown for aliased edges, whose
+    // x coordinates aren't snapped to half pixels before InitializeEdges drops the 28.4
+    // fraction from the error.
 
-    assert!((llSubpixelErrorBottom > -pEdgeLeft.ErrorDown as LONGLONG) && (llSubpixelErrorBottom < 0));
+    assert!((llSubpixelErrorBottom >= -pEdgeLeft.ErrorDown as LONGLONG) && (llSubpixelErrorBottom < 0));
     *nSubpixelErrorLeftBottom = (llSubpixelErrorBottom as INT);
 
     //
@@ -253,9 +259,11 @@ AdvanceDDAMultipleSteps(
     }
 
     // At this point, the subtraction above should have generated an error that is within
-    // (-pRight->ErrorDown, 0)
+    // [-pRight->ErrorDown, 0).  The error can only reach -ErrorDown for aliased edges, whose
+    // x coordinates aren't snapped to half pixels before InitializeEdges drops the 28.4
+    // fraction from the error.
 
-    assert!((llSubpixelErrorBottom > -pEdgeRight.ErrorDown as LONGLONG) && (llSubpixelErrorBottom < 0));
+    assert!((llSubpixelErrorBottom >= -pEdgeRight.ErrorDown as LONGLONG) && (llSubpixelErrorBottom < 0));
     *nSubpixelErrorRightBottom = (llSubpixelErrorBottom as INT);
 }
 
@@ -392,11 +400,109 @@ ComputeDistanceLowerBound(
 
     return nSubpixelXDistanceLowerBound;
 }
-pub struct CHwRasterizer<'x, 'y, 'z> {
+//-------------------------------------------------------------------------
+//
+//  Class:   CRasterizerStorage
+//
+//  Synopsis:
+//      The buffers that RasterizePath allocates.  They are kept empty
+//      between paths so that rasterizing the next path can reuse them.
+//
+//-------------------------------------------------------------------------
+#[derive(Default)]
+pub struct CRasterizerStorage {
+    m_rgEdgeChunks: Vec<Vec<CEdge<'static>>>,
+    m_rgIntervalChunks: Vec<Vec<CCoverageIntervalBuffer<'static>>>,
+    m_rgInactiveArray: Vec<CInactiveEdge<'static>>,
+    m_rgptDevice: Vec<MilPoint2F>,
+    m_rgptTransformed: Vec<POINT>,
+}
+
+// Hands the chunks of the arenas and the buffers that RasterizePath works
+// in back to the storage when RasterizePath returns, including when it
+// fails early
+struct CStorageReclaimer<'s, 'a, 'b> {
+    m_pStorage: &'s mut CRasterizerStorage,
+    m_pEdgeStore: &'a Arena<CEdge<'a>>,
+    m_pCoverageBuffer: &'b CCoverageBuffer<'b>,
+    m_rgInactiveArray: Vec<CInactiveEdge<'a>>,
+    m_rgptTransformed: Vec<POINT>,
+}
+
+impl Drop for CStorageReclaimer<'_, '_, '_> {
+    fn drop(&mut self) {
+        // The edges and intervals aren't used once RasterizePath returns
+        unsafe {
+            self.m_pStorage.m_rgEdgeChunks = recycle_chunks(self.m_pEdgeStore.take_chunks());
+            self.m_pStorage.m_rgIntervalChunks = recycle_chunks(self.m_pCoverageBuffer.TakeChunks());
+        }
+        self.m_pStorage.m_rgInactiveArray = recycle_vec(std::mem::take(&mut self.m_rgInactiveArray));
+        self.m_pStorage.m_rgptTransformed = std::mem::take(&mut self.m_rgptTransformed);
+    }
+}
+
+//-------------------------------------------------------------------------
+//
+//  Class:   CBandPlan
+//
+//  Synopsis:
+//      The edges of a path and the subpixel rows that its sweep can be
+//      split into bands at, see PlanBands.  The edges are copies of the
+//      CEdges before the sweep, in the order of the sorted inactive array,
+//      so that one plan can be shared by the rasterizers of all bands.
+//
+//-------------------------------------------------------------------------
+#[derive(Default)]
+pub struct CBandPlan {
+    m_rgEdges: Vec<CPlannedEdge>,
+    m_rgnBoundaries: Vec<INT>,
+}
+
+#[derive(Clone, Copy)]
+struct CPlannedEdge {
+    X: INT,
+    Dx: INT,
+    Error: INT,
+    ErrorUp: INT,
+    ErrorDown: INT,
+    StartY: INT,
+    EndY: INT,
+    WindingDirection: INT,
+}
+
+impl CBandPlan {
+    // Number of bands, which can be fewer than PlanBands was asked for
+    pub fn GetBandCount(&self) -> usize
+    {
+        self.m_rgnBoundaries.len().saturating_sub(1)
+    }
+}
+
+pub struct CHwRasterizer<'x> {
     m_rcClipBounds: MilPointAndSizeL,
     m_matWorldToDevice: CMILMatrix,
-    m_pIGeometrySink: &'x mut CHwVertexBufferBuilder<'y, 'z>,
+    m_fHasWorldTransform: bool,
+    m_pIGeometrySink: &'x mut dyn IGeometrySink,
     m_fillMode: MilFillMode,
+    m_antiAliasMode: MilAntiAliasMode,
+    m_grid: CSubpixelGrid,
+    // Index of the point that made RasterizePath fail, if any
+    m_iInvalidPoint: Option<usize>,
+    // Output complex scans only
+    m_fDisableTrapezoids: bool,
+    // Coverage that complex scans are multiplied with
+    m_pClipCoverage: Option<&'x CClipCoverage>,
+    m_clipScanBuffers: CClipScanBuffers,
+    // Buffers to reuse
+    m_storage: CRasterizerStorage,
+    // When non-zero, RasterizePath plans this many bands into m_bandPlan
+    // instead of sweeping, see PlanBands
+    m_cBandsToPlan: UINT,
+    m_bandPlan: CBandPlan,
+    // Maximum distance in pixels between a Bezier and its flattening
+    m_rBezierTolerance: REAL,
+    // Flatten Beziers to pixel row boundaries, see SetAlignBeziersToScanlines
+    m_fAlignBeziersToScanlines: bool,
     /* 
 DynArray<MilPoint2F> *m_prgPoints;
 DynArray<BYTE>       *m_prgTypes;
@@ -427,11 +533,12 @@ CD3DDeviceLevel1 * m_pDeviceNoRef;*/
 fn ConvertSubpixelXToPixel(
     x: INT,
     error: INT,
-    rErrorDown: f32
+    rErrorDown: f32,
+    rInvShiftSize: f32
     ) -> f32
 {
     assert!(rErrorDown > f32::EPSILON);
-    return ((x as f32) + (error as f32)/rErrorDown)*c_rInvShiftSize;
+    return ((x as f32) + (error as f32)/rErrorDown)*rInvShiftSize;
 }
 
 //-------------------------------------------------------------------------
@@ -444,13 +551,89 @@ fn ConvertSubpixelXToPixel(
 //
 //-------------------------------------------------------------------------
 fn ConvertSubpixelYToPixel(
-    nSubpixel: i32
+    nSubpixel: i32,
+    rInvShiftSize: f32
     ) -> f32
 {
-    return (nSubpixel as f32)*c_rInvShiftSize;
+    return (nSubpixel as f32)*rInvShiftSize;
+}
+
+// The dM that InitializeEdges computed the edge's DDA from
+fn EdgeDeltaX(pEdge: &CEdge) -> INT
+{
+    pEdge.Dx * pEdge.ErrorDown + pEdge.ErrorUp
+}
+
+//-------------------------------------------------------------------------
+//
+//  Function:   IsBandBoundary
+//
+//  Synopsis:
+//      Check that no two edges in rgActive have the same x at the start of
+//      nSubpixelY.  The edges have to be active at nSubpixelY, not counting
+//      the ones that start there.  rgnX is scratch space.
+//
+//-------------------------------------------------------------------------
+fn IsBandBoundary(
+    rgActive: &[Ref<CEdge>],
+    nSubpixelY: INT,
+    rgnX: &mut Vec<INT>
+    ) -> bool
+{
+    rgnX.clear();
+    rgnX.extend(rgActive.iter()
+        .map(|pEdge| ComputeEdgeDDA(pEdge, nSubpixelY, EdgeDeltaX(pEdge)).0));
+    rgnX.sort_unstable();
+    rgnX.windows(2).all(|pair| pair[0] != pair[1])
 }
 
-impl<'x, 'y, 'z> CHwRasterizer<'x, 'y, 'z> {
+//-------------------------------------------------------------------------
+//
+//  Function:   SkipToBand
+//
+//  Synopsis:
+//      Build the active edge list that the full sweep has at the start of
+//      nSubpixelYBandTop, minus the edges that start there, and return
+//      the rest of the inactive array.
+//
+//-------------------------------------------------------------------------
+fn SkipToBand<'a, 'b>(
+    pEdgeActiveList: Ref<'a, CEdge<'a>>,
+    mut pInactiveEdgeArray: &'b mut [CInactiveEdge<'a>],
+    nSubpixelYBandTop: INT
+    ) -> &'b mut [CInactiveEdge<'a>]
+{
+    let mut rgActive: Vec<Ref<CEdge>> = Vec::new();
+    while (pInactiveEdgeArray[0].Edge.StartY < nSubpixelYBandTop)
+    {
+        let pEdge = pInactiveEdgeArray[0].Edge;
+        if (pEdge.EndY > nSubpixelYBandTop)
+        {
+            let (x, error) = ComputeEdgeDDA(&pEdge, nSubpixelYBandTop, EdgeDeltaX(&pEdge));
+            pEdge.X.set(x);
+            pEdge.Error.set(error);
+            rgActive.push(pEdge);
+        }
+        pInactiveEdgeArray = &mut pInactiveEdgeArray[1..];
+    }
+
+    // The boundary has no ties in x, so sorting by x gives the order of
+    // the full sweep's active edge list
+    rgActive.sort_by_key(|pEdge| pEdge.X.get());
+
+    let pEdgeTail = pEdgeActiveList.Next.get();
+    let mut pEdgePrevious = pEdgeActiveList;
+    for pEdge in rgActive
+    {
+        pEdgePrevious.Next.set(pEdge);
+        pEdgePrevious = pEdge;
+    }
+    pEdgePrevious.Next.set(pEdgeTail);
+
+    pInactiveEdgeArray
+}
+
+impl<'x> CHwRasterizer<'x> {
 //-------------------------------------------------------------------------
 //
 //  Function:   CHwRasterizer::RasterizePath
@@ -483,18 +666,42 @@ pub fn RasterizePath(
     cPoints: UINT,
     pmatWorldTransform: &CMILMatrix
     ) -> HRESULT
+{
+    let mut storage = std::mem::take(&mut self.m_storage);
+    let hr = self.RasterizePathWithStorage(&mut storage, rgpt, rgTypes, cPoints, pmatWorldTransform);
+    self.m_storage = storage;
+    hr
+}
+
+fn RasterizePathWithStorage(
+    &mut self,
+    storage: &mut CRasterizerStorage,
+    rgpt: &[POINT],
+    rgTypes: &[BYTE],
+    cPoints: UINT,
+    pmatWorldTransform: &CMILMatrix
+    ) -> HRESULT
 {
     let mut hr;
     // Default is not implemented for arrays of size 40 so we need to use map
     let mut inactiveArrayStack: [CInactiveEdge; INACTIVE_LIST_NUMBER!()] = [(); INACTIVE_LIST_NUMBER!()].map(|_| Default::default());
     let mut pInactiveArray: &mut [CInactiveEdge];
-    let mut pInactiveArrayAllocation: Vec<CInactiveEdge>;
     let mut edgeHead: CEdge = Default::default();
     let mut edgeTail: CEdge = Default::default();
     let pEdgeActiveList: Ref<CEdge>;
-    let mut edgeStore = Arena::new();
-    //edgeStore.init();
-    let mut edgeContext: CInitializeEdgesContext = CInitializeEdgesContext::new(&mut edgeStore);
+    let mut clipBounds : RECT = Default::default();
+    let edgeStore = Arena::with_chunks(recycle_chunks(std::mem::take(&mut storage.m_rgEdgeChunks)));
+    let coverageBuffer = CCoverageBuffer::WithChunks(recycle_chunks(std::mem::take(&mut storage.m_rgIntervalChunks)));
+    let rgInactiveArray = recycle_vec(std::mem::take(&mut storage.m_rgInactiveArray));
+    let rgptTransformed = std::mem::take(&mut storage.m_rgptTransformed);
+    let mut reclaimer = CStorageReclaimer {
+        m_pStorage: storage,
+        m_pEdgeStore: &edgeStore,
+        m_pCoverageBuffer: &coverageBuffer,
+        m_rgInactiveArray: rgInactiveArray,
+        m_rgptTransformed: rgptTransformed,
+    };
+    let mut edgeContext: CInitializeEdgesContext = CInitializeEdgesContext::new(&edgeStore);
 
     edgeContext.ClipRect = None;
 
@@ -509,8 +716,7 @@ pub fn RasterizePath(
     pEdgeActiveList = Ref::new(&mut edgeHead);
     //edgeContext.Store = &mut edgeStore;
 
-    edgeContext.AntiAliasMode = c_antiAliasMode;
-    assert!(edgeContext.AntiAliasMode != MilAntiAliasMode::None);
+    edgeContext.AntiAliasMode = self.m_antiAliasMode;
 
     // If the path contains 0 or 1 points, we can ignore it.
     if (cPoints < 2)
@@ -518,12 +724,17 @@ pub fn RasterizePath(
         return S_OK;
     }
 
+    // Nothing can be drawn into an empty clip rect
+    if (self.m_rcClipBounds.Width <= 0 || self.m_rcClipBounds.Height <= 0)
+    {
+        return WGXHR_CLIPPEDTOEMPTY;
+    }
+
     let nPixelYClipBottom: INT = self.m_rcClipBounds.Y + self.m_rcClipBounds.Height;
 
     // Scale the clip bounds rectangle by 16 to account for our
     // scaling to 28.4 coordinates:
 
-    let mut clipBounds : RECT = Default::default();
     clipBounds.left   = self.m_rcClipBounds.X * FIX4_ONE!();
     clipBounds.top    = self.m_rcClipBounds.Y * FIX4_ONE!();
     clipBounds.right  = (self.m_rcClipBounds.X + self.m_rcClipBounds.Width) * FIX4_ONE!();
@@ -537,9 +748,59 @@ pub fn RasterizePath(
     let mut matrix: CMILMatrix = (*pmatWorldTransform).clone();
     AppendScaleToMatrix(&mut matrix, TOREAL!(16), TOREAL!(16));
 
-    let coverageBuffer: CCoverageBuffer = Default::default();
+    // The incoming points are already in 28.4 with the pixel center fixup
+    // applied, so without a world transform they can be used as is.
+    // Otherwise, convert them back to device space and run them through
+    // the world to 28.4 transform.
+    let mut rgpt = rgpt;
+    if (self.m_fHasWorldTransform)
+    {
+        let mut rgptDevice = std::mem::take(&mut reclaimer.m_pStorage.m_rgptDevice);
+        rgptDevice.clear();
+        rgptDevice.extend(rgpt[..cPoints as usize].iter().map(|pt| MilPoint2F {
+            X: (pt.x as f32) / TOREAL!(16) + 0.5,
+            Y: (pt.y as f32) / TOREAL!(16) + 0.5,
+        }));
+        let rgptTransformed = &mut reclaimer.m_rgptTransformed;
+        rgptTransformed.clear();
+        rgptTransformed.resize(cPoints as usize, Default::default());
+
+        let mut iInvalidPoint: UINT = 0;
+        hr = TransformRasterizerPointsTo28_4(
+            &matrix,
+            &rgptDevice,
+            cPoints,
+            rgptTransformed,
+            &mut iInvalidPoint
+            );
+        reclaimer.m_pStorage.m_rgptDevice = rgptDevice;
+
+        if (FAILED(hr))
+        {
+            // Draw nothing if the transformed points are NaN or out of range
+            self.m_iInvalidPoint = Some(iInvalidPoint as usize);
+            return hr;
+        }
+
+        rgpt = &reclaimer.m_rgptTransformed;
+    }
+
+    // Path points are only range checked for the default grid, so finer
+    // grids need another check to keep the subpixel coordinates in range.
+    if (self.m_grid.nShift > c_nShift)
+    {
+        if let Some(iInvalidPoint) = rgpt[..cPoints as usize].iter().position(|pt| {
+            !CheckValidRange28_4(pt.x as f32, pt.y as f32, self.m_grid.nShift)
+        })
+        {
+            // Draw nothing if the points are out of range
+            self.m_iInvalidPoint = Some(iInvalidPoint);
+            return WGXERR_VALUEOVERFLOW;
+        }
+    }
+
     // Initialize the coverage buffer
-    coverageBuffer.Initialize();
+    coverageBuffer.Initialize(self.m_grid);
 
     // Enumerate the path and construct the edge table:
 
@@ -549,23 +810,27 @@ pub fn RasterizePath(
         cPoints,
         &matrix,
         edgeContext.ClipRect,
+        self.m_rBezierTolerance,
+        self.m_fAlignBeziersToScanlines,
         &mut edgeContext
         ));
 
     if (FAILED(hr))
     {
-        if (hr == WGXERR_VALUEOVERFLOW)
-        {
-            // Draw nothing on value overflow and return
-            hr = S_OK;
-        }
+        // Draw nothing on value overflow and return
         return hr;
     }
 
     let nTotalCount: UINT; nTotalCount = edgeContext.Store.len() as u32;
     if (nTotalCount == 0)
     {
-        hr = S_OK;     // We're outta here (empty path or entirely clipped)
+        // We're outta here (empty path or entirely clipped). The path was
+        // clipped away if none of its points are within the clip's rows.
+        // Points have the pixel center fixup applied, so add half a pixel.
+        let fInsideClipRows = rgpt[..cPoints as usize].iter().any(|pt| {
+            pt.y + FIX4_ONE!() / 2 > clipBounds.top && pt.y + FIX4_ONE!() / 2 < clipBounds.bottom
+        });
+        hr = if fInsideClipRows { S_OK } else { WGXHR_CLIPPEDTOEMPTY };
         return hr;
     }
 
@@ -577,9 +842,9 @@ pub fn RasterizePath(
     pInactiveArray = &mut inactiveArrayStack[..];
     if (nTotalCount > (INACTIVE_LIST_NUMBER!() as u32 - 2))
     {
-        pInactiveArrayAllocation = vec![Default::default(); nTotalCount as usize + 2];
+        reclaimer.m_rgInactiveArray.resize(nTotalCount as usize + 2, Default::default());
 
-        pInactiveArray = &mut pInactiveArrayAllocation;
+        pInactiveArray = &mut reclaimer.m_rgInactiveArray;
     }
 
     // Initialize and sort the inactive array:
@@ -606,7 +871,7 @@ pub fn RasterizePath(
     // 'nPixelYClipBottom' is in screen space and needs to be converted to the
     // format we use for antialiasing.
 
-    nSubpixelYBottom = nSubpixelYBottom.min(nPixelYClipBottom << c_nShift);
+    nSubpixelYBottom = nSubpixelYBottom.min(nPixelYClipBottom << self.m_grid.nShift);
 
     // 'nTotalCount' should have been zero if all the edges were
     // clipped out (RasterizeEdges assumes there's at least one edge
@@ -614,6 +879,26 @@ pub fn RasterizePath(
 
     assert!(nSubpixelYBottom > nSubpixelYCurrent);
 
+    if (self.m_cBandsToPlan > 0)
+    {
+        self.m_bandPlan.m_rgnBoundaries = self.ComputeBandBoundaries(pInactiveArray, nSubpixelYCurrent, nSubpixelYBottom);
+        self.m_bandPlan.m_rgEdges = pInactiveArray.iter()
+            .map(|inactive| inactive.Edge)
+            .take_while(|pEdge| pEdge.StartY != INT::MAX)
+            .map(|pEdge| CPlannedEdge {
+                X: pEdge.X.get(),
+                Dx: pEdge.Dx,
+                Error: pEdge.Error.get(),
+                ErrorUp: pEdge.ErrorUp,
+                ErrorDown: pEdge.ErrorDown,
+                StartY: pEdge.StartY,
+                EndY: pEdge.EndY,
+                WindingDirection: pEdge.WindingDirection,
+            })
+            .collect();
+        return hr;
+    }
+
     IFC!(self.RasterizeEdges(
         pEdgeActiveList,
         pInactiveArray,
@@ -635,7 +920,7 @@ pub fn RasterizePath(
 //
 //-------------------------------------------------------------------------
 pub fn new(
-    pIGeometrySink: &'x mut CHwVertexBufferBuilder<'y, 'z>,
+    pIGeometrySink: &'x mut dyn IGeometrySink,
     fillMode: MilFillMode,
     pmatWorldToDevice: Option<CMatrix<CoordinateSpace::Shape,CoordinateSpace::Device>>,
     clipRect: MilPointAndSizeL,
@@ -656,6 +941,7 @@ pub fn new(
     // antialiased rendering.
     //
 
+    let fHasWorldTransform = pmatWorldToDevice.is_some();
     let mut matWorldHPCToDeviceIPC = pmatWorldToDevice.unwrap_or(CMatrix::Identity());
     matWorldHPCToDeviceIPC.SetDx(matWorldHPCToDeviceIPC.GetDx() - 0.5);
     matWorldHPCToDeviceIPC.SetDy(matWorldHPCToDeviceIPC.GetDy() - 0.5);
@@ -674,9 +960,342 @@ pub fn new(
         m_rcClipBounds: clipRect,
         m_pIGeometrySink: pIGeometrySink,
         m_matWorldToDevice: matWorldHPCToDeviceIPC,
+        m_fHasWorldTransform: fHasWorldTransform,
+        m_antiAliasMode: c_antiAliasMode,
+        m_grid: CSubpixelGrid::FromAntiAliasMode(c_antiAliasMode),
+        m_iInvalidPoint: None,
+        m_fDisableTrapezoids: false,
+        m_pClipCoverage: None,
+        m_clipScanBuffers: Default::default(),
+        m_storage: Default::default(),
+        m_cBandsToPlan: 0,
+        m_bandPlan: Default::default(),
+        m_rBezierTolerance: BEZIER_DEFAULT_TOLERANCE,
+        m_fAlignBeziersToScanlines: false,
     }
 }
 
+//-------------------------------------------------------------------------
+//
+//  Function:   CHwRasterizer::SetAntiAliasMode
+//
+//  Synopsis:
+//      Select the subpixel grid used for antialiasing.  The geometry sink
+//      is told about the grid so that it can normalize complex scan
+//      coverage.
+//
+//-------------------------------------------------------------------------
+pub fn SetAntiAliasMode(&mut self,
+    antiAliasMode: MilAntiAliasMode
+    )
+{
+    self.m_antiAliasMode = antiAliasMode;
+    self.m_grid = CSubpixelGrid::FromAntiAliasMode(antiAliasMode);
+    self.m_pIGeometrySink.SetSubpixelGrid(self.m_grid);
+}
+
+//-------------------------------------------------------------------------
+//
+//  Function:   CHwRasterizer::DisableTrapezoids
+//
+//  Synopsis:
+//      Output all of the geometry as complex scans.
+//
+//-------------------------------------------------------------------------
+pub fn DisableTrapezoids(&mut self)
+{
+    self.m_fDisableTrapezoids = true;
+}
+
+//-------------------------------------------------------------------------
+//
+//  Function:   CHwRasterizer::SetClipCoverage
+//
+//  Synopsis:
+//      Multiply the output coverage with the coverage of a clip path.  The
+//      clip has to use the same subpixel grid.  Trapezoids can't be
+//      clipped, so they are disabled.
+//
+//-------------------------------------------------------------------------
+pub fn SetClipCoverage(&mut self,
+    pClipCoverage: &'x CClipCoverage
+    )
+{
+    self.m_pClipCoverage = Some(pClipCoverage);
+    self.m_fDisableTrapezoids = true;
+}
+
+//-------------------------------------------------------------------------
+//
+//  Function:   CHwRasterizer::SetStorage
+//
+//  Synopsis:
+//      Reuse the buffers of a previous rasterizer.  TakeStorage gives them
+//      back once the geometry has been sent.
+//
+//-------------------------------------------------------------------------
+pub fn SetStorage(&mut self,
+    storage: CRasterizerStorage
+    )
+{
+    self.m_storage = storage;
+}
+
+pub fn TakeStorage(&mut self) -> CRasterizerStorage
+{
+    std::mem::take(&mut self.m_storage)
+}
+
+//-------------------------------------------------------------------------
+//
+//  Function:   CHwRasterizer::SetBezierTolerance
+//
+//  Synopsis:
+//      Set the maximum distance in pixels between a Bezier and the lines
+//      it is flattened to.  See CMILBezier::new for the accepted range.
+//
+//-------------------------------------------------------------------------
+pub fn SetBezierTolerance(&mut self,
+    rTolerance: REAL
+    )
+{
+    self.m_rBezierTolerance = rTolerance;
+}
+
+//-------------------------------------------------------------------------
+//
+//  Function:   CHwRasterizer::SetAlignBeziersToScanlines
+//
+//  Synopsis:
+//      Flatten Beziers so that the lines end on pixel row boundaries where
+//      possible.  Rows that no edge starts or ends in can be output as
+//      trapezoids, so curves need fewer complex scans.
+//
+//-------------------------------------------------------------------------
+pub fn SetAlignBeziersToScanlines(&mut self,
+    fAlign: bool
+    )
+{
+    self.m_fAlignBeziersToScanlines = fAlign;
+}
+
+//-------------------------------------------------------------------------
+//
+//  Function:   CHwRasterizer::PlanBands
+//
+//  Synopsis:
+//      Flatten the path and split its rows into at most cBands bands
+//      that can be rasterized independently with RasterizeBand.  Nothing
+//      is output.
+//
+//-------------------------------------------------------------------------
+pub fn PlanBands(&mut self,
+    points: &[POINT],
+    types: &[BYTE],
+    cBands: UINT,
+    ) -> Result<CBandPlan, HRESULT>
+{
+    self.m_cBandsToPlan = cBands.max(1);
+    self.m_bandPlan = Default::default();
+    let hr = self.RasterizePath(
+        points,
+        types,
+        points.len() as u32,
+        &self.m_matWorldToDevice.clone(),
+        );
+    self.m_cBandsToPlan = 0;
+    if (FAILED(hr))
+    {
+        return Err(hr);
+    }
+    Ok(std::mem::take(&mut self.m_bandPlan))
+}
+
+//-------------------------------------------------------------------------
+//
+//  Function:   CHwRasterizer::RasterizeBand
+//
+//  Synopsis:
+//      Sweep band iBand of a plan made by PlanBands with the same
+//      settings.  The output is exactly the part of the full sweep's
+//      output that is in the band.  The path isn't flattened again, the
+//      band's edges are copied from the plan.
+//
+//-------------------------------------------------------------------------
+pub fn RasterizeBand(&mut self,
+    pPlan: &CBandPlan,
+    iBand: usize
+    ) -> HRESULT
+{
+    let mut storage = std::mem::take(&mut self.m_storage);
+    let hr = self.RasterizeBandWithStorage(&mut storage, pPlan, iBand);
+    self.m_storage = storage;
+    hr
+}
+
+fn RasterizeBandWithStorage(
+    &mut self,
+    storage: &mut CRasterizerStorage,
+    pPlan: &CBandPlan,
+    iBand: usize
+    ) -> HRESULT
+{
+    let nSubpixelYBandTop = pPlan.m_rgnBoundaries[iBand];
+    let nSubpixelYBandBottom = pPlan.m_rgnBoundaries[iBand + 1];
+    let edgeHead: CEdge = Default::default();
+    let mut edgeTail: CEdge = Default::default();
+    let edgeStore = Arena::with_chunks(recycle_chunks(std::mem::take(&mut storage.m_rgEdgeChunks)));
+    let coverageBuffer = CCoverageBuffer::WithChunks(recycle_chunks(std::mem::take(&mut storage.m_rgIntervalChunks)));
+    let rgInactiveArray = recycle_vec(std::mem::take(&mut storage.m_rgInactiveArray));
+    let rgptTransformed = std::mem::take(&mut storage.m_rgptTransformed);
+    let mut reclaimer = CStorageReclaimer {
+        m_pStorage: storage,
+        m_pEdgeStore: &edgeStore,
+        m_pCoverageBuffer: &coverageBuffer,
+        m_rgInactiveArray: rgInactiveArray,
+        m_rgptTransformed: rgptTransformed,
+    };
+
+    edgeTail.X.set(i32::MAX);       // Terminator to active list
+    edgeTail.StartY = i32::MAX;  // Terminator to inactive list
+    edgeTail.EndY = i32::MIN;
+    edgeHead.X.set(i32::MIN);       // Beginning of active list
+    edgeHead.Next.set(Ref::new(&edgeTail));
+    let pEdgeActiveList = Ref::new(&edgeHead);
+
+    // Copy the edges that the band's rows cross.  The plan is sorted, so
+    // they're added to the inactive array in order.
+    let rgInactiveArray = &mut reclaimer.m_rgInactiveArray;
+    rgInactiveArray.clear();
+    for edge in pPlan.m_rgEdges.iter()
+        .filter(|edge| edge.StartY < nSubpixelYBandBottom && edge.EndY > nSubpixelYBandTop)
+    {
+        let pEdge = edgeStore.alloc(CEdge {
+            Next: Cell::new(unsafe { Ref::null() }),
+            X: Cell::new(edge.X),
+            Dx: edge.Dx,
+            Error: Cell::new(edge.Error),
+            ErrorUp: edge.ErrorUp,
+            ErrorDown: edge.ErrorDown,
+            StartY: edge.StartY,
+            EndY: edge.EndY,
+            WindingDirection: edge.WindingDirection,
+        });
+        rgInactiveArray.push(CInactiveEdge::new(Ref::new(pEdge)));
+    }
+    if (rgInactiveArray.is_empty())
+    {
+        return S_OK;
+    }
+    rgInactiveArray.push(CInactiveEdge::new(Ref::new(&edgeTail)));
+
+    coverageBuffer.Initialize(self.m_grid);
+
+    // Start with the edges that the full sweep would have active at the
+    // top of the band
+    let pInactiveArray = SkipToBand(pEdgeActiveList, rgInactiveArray, nSubpixelYBandTop);
+
+    self.RasterizeEdges(
+        pEdgeActiveList,
+        pInactiveArray,
+        &coverageBuffer,
+        nSubpixelYBandTop,
+        nSubpixelYBandBottom
+        )
+}
+
+//-------------------------------------------------------------------------
+//
+//  Function:   CHwRasterizer::ComputeBandBoundaries
+//
+//  Synopsis:
+//      Pick band boundaries for PlanBands.  A band has to start with the
+//      same state that the full sweep has at that row, so a boundary has
+//      to be a row that:
+//
+//          1. The sweep always stops at.  Trapezoids never extend past
+//             the start or end of an edge, so the start and end rows of
+//             edges qualify.
+//          2. Is at a pixel boundary, so that the coverage buffer is
+//             empty.
+//          3. Has no two active edges at the same x, so that the order
+//             of the active edge list only depends on the row.
+//
+//-------------------------------------------------------------------------
+fn ComputeBandBoundaries(&self,
+    pInactiveEdgeArray: &[CInactiveEdge],
+    nSubpixelYTop: INT,
+    nSubpixelYBottom: INT
+    ) -> Vec<INT>
+{
+    let cBands = self.m_cBandsToPlan as i64;
+    let rgEdges: Vec<Ref<CEdge>> = pInactiveEdgeArray.iter()
+        .map(|inactive| inactive.Edge)
+        .take_while(|pEdge| pEdge.StartY != INT::MAX)
+        .collect();
+
+    let mut rgnCandidates: Vec<INT> = rgEdges.iter()
+        .flat_map(|pEdge| [pEdge.StartY, pEdge.EndY])
+        .filter(|&nSubpixelY| {
+            (nSubpixelY & self.m_grid.nShiftMask) == 0
+            && nSubpixelY > nSubpixelYTop
+            && nSubpixelY < nSubpixelYBottom
+        })
+        .collect();
+    rgnCandidates.sort_unstable();
+    rgnCandidates.dedup();
+
+    // Sweep the candidates from the top, adding edges to rgActive as the
+    // sweep passes their start and dropping them once it passes their end.
+    // rgEdges is sorted by start row, so every edge is added once.
+    let mut rgnBoundaries = vec![nSubpixelYTop];
+    let mut rgActive: Vec<Ref<CEdge>> = Vec::new();
+    let mut rgnX: Vec<INT> = Vec::new();
+    let mut iEdge = 0;
+    let mut iBand = 1;
+    for &nSubpixelY in &rgnCandidates
+    {
+        if (iBand >= cBands)
+        {
+            break;
+        }
+        let nSubpixelYTarget = nSubpixelYTop + ((nSubpixelYBottom - nSubpixelYTop) as i64 * iBand / cBands) as INT;
+        if (nSubpixelY < nSubpixelYTarget)
+        {
+            continue;
+        }
+
+        while iEdge < rgEdges.len() && rgEdges[iEdge].StartY < nSubpixelY
+        {
+            rgActive.push(rgEdges[iEdge]);
+            iEdge += 1;
+        }
+        rgActive.retain(|pEdge| pEdge.EndY > nSubpixelY);
+
+        if (IsBandBoundary(&rgActive, nSubpixelY, &mut rgnX))
+        {
+            rgnBoundaries.push(nSubpixelY);
+            iBand += 1;
+        }
+    }
+    rgnBoundaries.push(nSubpixelYBottom);
+    rgnBoundaries
+}
+
+//-------------------------------------------------------------------------
+//
+//  Function:   CHwRasterizer::GetInvalidPoint
+//
+//  Synopsis:
+//      Index of the NaN or out of range point that made SendGeometry
+//      fail, if any.
+//
+//-------------------------------------------------------------------------
+pub fn GetInvalidPoint(&self) -> Option<usize>
+{
+    self.m_iInvalidPoint
+}
+
 //-------------------------------------------------------------------------
 //
 //  Function:   CHwRasterizer::SendGeometry
@@ -766,11 +1385,20 @@ GenerateOutputAndClearCoverage<'a>(&mut self, coverageBuffer: &'a CCoverageBuffe
     ) -> HRESULT
 {
     let hr = S_OK;
-    let nPixelY = nSubpixelY >> c_nShift;
+    let nPixelY = nSubpixelY >> self.m_grid.nShift;
 
     let pIntervalSpanStart: Ref<CCoverageInterval> = coverageBuffer.m_pIntervalStart.get();
 
-    IFC!(self.m_pIGeometrySink.AddComplexScan(nPixelY, pIntervalSpanStart));
+    if let Some(pClipCoverage) = self.m_pClipCoverage
+    {
+        // Multiply with the clip's coverage and output the resulting
+        // intervals instead
+        IFC!(pClipCoverage.AddClippedScan(nPixelY, pIntervalSpanStart, &mut self.m_clipScanBuffers, self.m_pIGeometrySink));
+    }
+    else
+    {
+        IFC!(self.m_pIGeometrySink.AddComplexScan(nPixelY, pIntervalSpanStart));
+    }
 
     coverageBuffer.Reset();
 
@@ -836,7 +1464,7 @@ fn ComputeTrapezoidsEndScan(&mut self,
     // Trapezoids should always start at scanline boundaries
     //
 
-    assert!((nSubpixelYCurrent & c_nShiftMask) == 0);
+    assert!((nSubpixelYCurrent & self.m_grid.nShiftMask) == 0);
 
     //
     // If we are doing a winding mode fill, check that we can ignore mode and do an
@@ -923,9 +1551,9 @@ fn ComputeTrapezoidsEndScan(&mut self,
             //
 
             let nSubpixelExpandDistanceUpperBound: INT =
-                c_nShiftSize
-                + ComputeDeltaUpperBound(&*pEdgeLeft, c_nHalfShiftSize)
-                + ComputeDeltaUpperBound(&*pEdgeRight, c_nHalfShiftSize);
+                self.m_grid.nShiftSize
+                + ComputeDeltaUpperBound(&*pEdgeLeft, self.m_grid.nHalfShiftSize)
+                + ComputeDeltaUpperBound(&*pEdgeRight, self.m_grid.nHalfShiftSize);
 
             //
             // Compute a top edge distance that is <= to the distance between A' and B' as follows:
@@ -1067,7 +1695,7 @@ fn ComputeTrapezoidsEndScan(&mut self,
                     #[cfg(debug_assertions)]
                     assert!(nDbgPreviousSubpixelXBottomTrapezoids >= nSubpixelYBottomTrapezoids);
 
-                    if (nSubpixelYBottomTrapezoids < nSubpixelYCurrent + c_nShiftSize)
+                    if (nSubpixelYBottomTrapezoids < nSubpixelYCurrent + self.m_grid.nShiftSize)
                     {
                         // We no longer have a trapezoid that is at least one scanline high, so
                         // abort
@@ -1086,7 +1714,7 @@ fn ComputeTrapezoidsEndScan(&mut self,
     // Snap to pixel boundary
     //
 
-    nSubpixelYBottomTrapezoids = nSubpixelYBottomTrapezoids & (!c_nShiftMask);
+    nSubpixelYBottomTrapezoids = nSubpixelYBottomTrapezoids & (!self.m_grid.nShiftMask);
 
     //
     // Ensure that we are never less than nSubpixelYCurrent
@@ -1152,7 +1780,7 @@ OutputTrapezoids(&mut self,
     let mut pEdgeLeft = pEdgeCurrent;
     let mut pEdgeRight = (*pEdgeCurrent).Next.get();
 
-    assert!((nSubpixelYCurrent & c_nShiftMask) == 0);
+    assert!((nSubpixelYCurrent & self.m_grid.nShiftMask) == 0);
     assert!(pEdgeLeft.EndY != INT::MIN);
     assert!(pEdgeRight.EndY != INT::MIN);
 
@@ -1202,8 +1830,8 @@ OutputTrapezoids(&mut self,
 
         rSubpixelLeftErrorDown  = pEdgeLeft.ErrorDown as f32;
         rSubpixelRightErrorDown = pEdgeRight.ErrorDown as f32;
-        rPixelXLeft  = ConvertSubpixelXToPixel(pEdgeLeft.X.get(), pEdgeLeft.Error.get(), rSubpixelLeftErrorDown);
-        rPixelXRight = ConvertSubpixelXToPixel(pEdgeRight.X.get(), pEdgeRight.Error.get(), rSubpixelRightErrorDown);
+        rPixelXLeft  = ConvertSubpixelXToPixel(pEdgeLeft.X.get(), pEdgeLeft.Error.get(), rSubpixelLeftErrorDown, self.m_grid.rInvShiftSize);
+        rPixelXRight = ConvertSubpixelXToPixel(pEdgeRight.X.get(), pEdgeRight.Error.get(), rSubpixelRightErrorDown, self.m_grid.rInvShiftSize);
 
         rSubpixelLeftInvSlope     = pEdgeLeft.Dx as f32 + pEdgeLeft.ErrorUp as f32/rSubpixelLeftErrorDown;
         rSubpixelLeftAbsInvSlope  = rSubpixelLeftInvSlope.abs();
@@ -1213,21 +1841,45 @@ OutputTrapezoids(&mut self,
         rPixelXLeftDelta  = 0.5 + 0.5 * rSubpixelLeftAbsInvSlope;
         rPixelXRightDelta = 0.5 + 0.5 * rSubpixelRightAbsInvSlope;
 
-        let rPixelYTop         = ConvertSubpixelYToPixel(nSubpixelYCurrent);
-        let rPixelYBottom      = ConvertSubpixelYToPixel(nSubpixelYNext);
+        let rPixelYTop         = ConvertSubpixelYToPixel(nSubpixelYCurrent, self.m_grid.rInvShiftSize);
+        let rPixelYBottom      = ConvertSubpixelYToPixel(nSubpixelYNext, self.m_grid.rInvShiftSize);
 
-        let rPixelXBottomLeft  = ConvertSubpixelXToPixel(
+        let mut rPixelXBottomLeft  = ConvertSubpixelXToPixel(
                                         nSubpixelXLeftBottom,
                                         nSubpixelErrorLeftBottom,
-                                        pEdgeLeft.ErrorDown as f32
+                                        pEdgeLeft.ErrorDown as f32,
+                                        self.m_grid.rInvShiftSize
                                         );
 
-        let rPixelXBottomRight = ConvertSubpixelXToPixel(
+        let mut rPixelXBottomRight = ConvertSubpixelXToPixel(
                                         nSubpixelXRightBottom,
                                         nSubpixelErrorRightBottom,
-                                        pEdgeRight.ErrorDown as f32
+                                        pEdgeRight.ErrorDown as f32,
+                                        self.m_grid.rInvShiftSize
                                         );
 
+        if (self.m_antiAliasMode == MilAntiAliasMode::None)
+        {
+            //
+            // Aliased edges are sampled at the pixel centers, which are the
+            // integers in subpixel space.  Move the edges from the sampled
+            // scanlines to the top and bottom of the pixel rows so that the
+            // trapezoid covers exactly the sampled pixel centers, and don't
+            // expand it since there is no falloff.
+            //
+
+            let rPixelXLeftOffset  = 0.5 - 0.5 * rSubpixelLeftInvSlope;
+            let rPixelXRightOffset = 0.5 - 0.5 * rSubpixelRightInvSlope;
+
+            rPixelXLeft        += rPixelXLeftOffset;
+            rPixelXBottomLeft  += rPixelXLeftOffset;
+            rPixelXRight       += rPixelXRightOffset;
+            rPixelXBottomRight += rPixelXRightOffset;
+
+            rPixelXLeftDelta  = 0.0;
+            rPixelXRightDelta = 0.0;
+        }
+
         //
         // Output the trapezoid
         //
@@ -1287,7 +1939,7 @@ OutputTrapezoids(&mut self,
 fn
 RasterizeEdges<'a, 'b>(&mut self,
     pEdgeActiveList: Ref<'a, CEdge<'a>>,
-    mut pInactiveEdgeArray: &'a mut [CInactiveEdge<'a>],
+    mut pInactiveEdgeArray: &mut [CInactiveEdge<'a>],
     coverageBuffer: &'b CCoverageBuffer<'b>,
     mut nSubpixelYCurrent: INT,
     nSubpixelYBottom: INT
@@ -1299,12 +1951,20 @@ RasterizeEdges<'a, 'b>(&mut self,
     let mut nSubpixelYNextInactive: INT = 0;
     let mut nSubpixelYNext: INT;
 
-    pInactiveEdgeArray = InsertNewEdges(
-        pEdgeActiveList,
-        nSubpixelYCurrent,
-        pInactiveEdgeArray,
-        &mut nSubpixelYNextInactive
-        );
+    if (pInactiveEdgeArray[0].Edge.StartY == nSubpixelYCurrent)
+    {
+        pInactiveEdgeArray = InsertNewEdges(
+            pEdgeActiveList,
+            nSubpixelYCurrent,
+            pInactiveEdgeArray,
+            &mut nSubpixelYNextInactive
+            );
+    }
+    else
+    {
+        // A band can start with only edges that were already active
+        nSubpixelYNextInactive = pInactiveEdgeArray[0].Edge.StartY;
+    }
 
     while (nSubpixelYCurrent < nSubpixelYBottom)
     {
@@ -1320,9 +1980,10 @@ RasterizeEdges<'a, 'b>(&mut self,
         nSubpixelYNext = nSubpixelYCurrent;
 
         if (!IsTagEnabled!(tagDisableTrapezoids)
-            && (nSubpixelYCurrent & c_nShiftMask) == 0
+            && !self.m_fDisableTrapezoids
+            && (nSubpixelYCurrent & self.m_grid.nShiftMask) == 0
             && pEdgeCurrent.EndY != INT::MIN
-            && nSubpixelYNextInactive >= nSubpixelYCurrent + c_nShiftSize
+            && nSubpixelYNextInactive >= nSubpixelYCurrent + self.m_grid.nShiftSize
             )
         {
             // Edges are paired, so we can assert we have another one
@@ -1343,7 +2004,7 @@ RasterizeEdges<'a, 'b>(&mut self,
             // indicating that we need to fall back to complex scans.
             //
 
-            if (nSubpixelYNext >= nSubpixelYCurrent + c_nShiftSize)
+            if (nSubpixelYNext >= nSubpixelYCurrent + self.m_grid.nShiftSize)
             {
                 IFC!(self.OutputTrapezoids(
                     pEdgeCurrent,
@@ -1361,7 +2022,7 @@ RasterizeEdges<'a, 'b>(&mut self,
         {
             // If we advance, it must be by at least one scan line
 
-            assert!(nSubpixelYNext - nSubpixelYCurrent >= c_nShiftSize);
+            assert!(nSubpixelYNext - nSubpixelYCurrent >= self.m_grid.nShiftSize);
 
             // Advance nSubpixelYCurrent
 
@@ -1413,7 +2074,7 @@ RasterizeEdges<'a, 'b>(&mut self,
             }
 
             // If the next scan is done, output what's there:
-            if (nSubpixelYNext > (nSubpixelYCurrent | c_nShiftMask))
+            if (nSubpixelYNext > (nSubpixelYCurrent | self.m_grid.nShiftMask))
             {
                 IFC!(self.GenerateOutputAndClearCoverage(coverageBuffer, nSubpixelYCurrent));
             }
@@ -1444,7 +2105,7 @@ RasterizeEdges<'a, 'b>(&mut self,
     // Output the last scanline that has partial coverage
     //
 
-    if ((nSubpixelYCurrent & c_nShiftMask) != 0)
+    if ((nSubpixelYCurrent & self.m_grid.nShiftMask) != 0)
     {
         IFC!(self.GenerateOutputAndClearCoverage(coverageBuffer, nSubpixelYCurrent));
     }
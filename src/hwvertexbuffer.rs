@@ -106,7 +106,7 @@ const FLOAT_ONE: f32 = 1.;
 //
 //-----------------------------------------------------------------------------
 
-use crate::{types::*, geometry_sink::IGeometrySink, aacoverage::c_nShiftSizeSquared, OutputVertex, nullable_ref::Ref};
+use crate::{types::*, geometry_sink::IGeometrySink, aacoverage::CSubpixelGrid, OutputVertex, Transform, Vertex, nullable_ref::Ref};
 
 
 //+----------------------------------------------------------------------------
@@ -630,7 +630,15 @@ protected:
     //m_rgVerticesTriList: DynArray<TVertex>,             // Indexed triangle list vertices
     //m_rgVerticesNonIndexedTriList: DynArray<TVertex>,   // Non-indexed triangle list vertices
     m_rgVerticesTriList: DynArray<TVertex>,            // Triangle strip vertices
+    // When this is set, fully covered triangles are added here instead of to m_rgVerticesTriList
+    m_rgVerticesOpaqueTriList: Option<DynArray<TVertex>>,
     //m_rgVerticesLineList: DynArray<TVertex>,            // Linelist vertices
+    // When this is set, the triangles share their vertices through these indices instead of
+    // repeating them in m_rgVerticesTriList
+    m_rgIndices: Option<DynArray<u32>>,
+    // The index of the top right vertex of the last trapezoid that was added to m_rgIndices.
+    // The bottom right vertex follows it.
+    m_wLastTrapezoidRightEdge: Option<u32>,
 
     m_rgVerticesBuffer: Option<&'z mut [TVertex]>,
     m_rgVerticesBufferOffset: usize,
@@ -640,6 +648,7 @@ protected:
     // so that we can ensure that we only waffle 6-vertex tri strips.
     m_fDbgNonLineSegmentTriangleStrip: bool,
     subpixel_bias: f32,
+    m_map: CHwTVertexMappings<TVertex>,
 }
 
 impl<'z, TVertex: Default> CHwTVertexBuffer<'z, TVertex> {
@@ -657,8 +666,90 @@ impl<'z, TVertex: Default> CHwTVertexBuffer<'z, TVertex> {
         }
     }
 
+    // Like new, but the triangle list is built in the allocation of vertices. Its
+    // contents are discarded.
+    pub fn with_storage(rasterization_truncates: bool, mut vertices: Vec<TVertex>) -> Self {
+        vertices.clear();
+        Self {
+            m_rgVerticesTriList: vertices,
+            ..Self::new(rasterization_truncates, None)
+        }
+    }
+
     pub fn flush_output(&mut self) -> Box<[TVertex]> {
-        std::mem::take(&mut self.m_rgVerticesTriList).into_boxed_slice()
+        self.take_vertices().into_boxed_slice()
+    }
+
+    pub fn take_vertices(&mut self) -> Vec<TVertex> {
+        std::mem::take(&mut self.m_rgVerticesTriList)
+    }
+
+    // Makes fully covered triangles go to a separate list that is built in the allocation
+    // of vertices. This isn't supported with an output buffer.
+    pub fn split_opaque(&mut self, mut vertices: Vec<TVertex>) {
+        debug_assert!(self.m_rgVerticesBuffer.is_none());
+        vertices.clear();
+        self.m_rgVerticesOpaqueTriList = Some(vertices);
+    }
+
+    pub fn take_opaque_vertices(&mut self) -> Vec<TVertex> {
+        self.m_rgVerticesOpaqueTriList.take().unwrap_or_default()
+    }
+
+    // Makes the triangles share their vertices through an index list that is built in the
+    // allocation of indices. This isn't supported with an output buffer or split_opaque.
+    pub fn share_vertices(&mut self, mut indices: Vec<u32>) {
+        debug_assert!(self.m_rgVerticesBuffer.is_none() && self.m_rgVerticesOpaqueTriList.is_none());
+        indices.clear();
+        self.m_rgIndices = Some(indices);
+    }
+
+    pub fn take_indices(&mut self) -> Vec<u32> {
+        self.m_rgIndices.take().unwrap_or_default()
+    }
+
+    // Maps the positions of the output vertices through transform.
+    pub fn set_position_transform(&mut self, transform: Transform) {
+        self.m_map.m_matPos2DTransform = Some(transform);
+    }
+
+    // Gives the output vertices texture coordinates that are their positions mapped
+    // through these transforms. Coordinates without a transform are zero.
+    pub fn set_uv_transforms(&mut self, transforms: [Option<Transform>; 2]) {
+        self.m_map.m_rgmatPointToUV = transforms;
+    }
+
+    // An empty buffer with the same settings for rasterizing a band of the path on
+    // another thread. Its vertices are added back with append_band.
+    pub fn new_band<'b>(&self) -> CHwTVertexBuffer<'b, TVertex> where TVertex: Clone {
+        CHwTVertexBuffer {
+            m_rgVerticesOpaqueTriList: self.m_rgVerticesOpaqueTriList.as_ref().map(|_| Vec::new()),
+            m_rgIndices: self.m_rgIndices.as_ref().map(|_| Vec::new()),
+            subpixel_bias: self.subpixel_bias,
+            m_map: self.m_map.clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn append_band(&mut self, mut band: CHwTVertexBuffer<TVertex>) where TVertex: Clone {
+        if let Some(indices) = &mut self.m_rgIndices {
+            let wIndexStart = self.m_rgVerticesTriList.len() as u32;
+            indices.extend(band.take_indices().into_iter().map(|index| index + wIndexStart));
+            self.m_wLastTrapezoidRightEdge = None;
+        }
+        let vertices = band.take_vertices();
+        if let Some(output_buffer) = &mut self.m_rgVerticesBuffer {
+            let offset = self.m_rgVerticesBufferOffset;
+            if offset + vertices.len() <= output_buffer.len() {
+                output_buffer[offset..offset + vertices.len()].clone_from_slice(&vertices);
+            }
+            self.m_rgVerticesBufferOffset = offset + vertices.len();
+        } else {
+            self.m_rgVerticesTriList.extend(vertices);
+        }
+        if let Some(opaque) = &mut self.m_rgVerticesOpaqueTriList {
+            opaque.extend(band.take_opaque_vertices());
+        }
     }
 
     pub fn get_output_buffer_size(&self) -> Option<usize> {
@@ -678,7 +769,7 @@ impl<'z, TVertex: Default> CHwTVertexBuffer<'z, TVertex> {
 //             incoming basic per vertex data, like just X and Y
 //
 //-----------------------------------------------------------------------------
-#[derive(Default)]
+#[derive(Clone, Default)]
 struct CHwTVertexMappings<TVertex>
 {/* 
 public:
@@ -723,6 +814,31 @@ public:
 */
     m_vStatic: TVertex,
     subpixel_bias: f32,
+
+    m_matPos2DTransform: Option<Transform>,
+    m_rgmatPointToUV: [Option<Transform>; 2],
+}
+
+impl<TVertex: Vertex> CHwTVertexMappings<TVertex> {
+    // Builds the output vertex for a point, with texture coordinates if any are mapped.
+    fn PointToVertex(&self, x: f32, y: f32, coverage: f32) -> TVertex {
+        let (x, y) = match self.m_matPos2DTransform {
+            Some(matrix) => matrix.transform_point(x, y),
+            None => (x, y),
+        };
+        if self.m_rgmatPointToUV.iter().all(Option::is_none) {
+            TVertex::new(x, y, coverage)
+        } else {
+            TVertex::with_uv(x, y, coverage, [self.PointToUV(x, y, 0), self.PointToUV(x, y, 1)])
+        }
+    }
+
+    fn PointToUV(&self, x: f32, y: f32, uIndex: usize) -> (f32, f32) {
+        match self.m_rgmatPointToUV[uIndex] {
+            Some(matrix) => matrix.transform_point(x, y),
+            None => (0., 0.),
+        }
+    }
 }
 
 impl<TVertex> CHwTVertexBuffer<'_, TVertex> {
@@ -735,9 +851,15 @@ impl<TVertex> CHwTVertexBuffer<'_, TVertex> {
             self.m_fDbgNonLineSegmentTriangleStrip = false;
         }
 
-        //self.m_rgIndices.SetCount(0);
+        if let Some(indices) = &mut self.m_rgIndices {
+            indices.SetCount(0);
+        }
+        self.m_wLastTrapezoidRightEdge = None;
         //self.m_rgVerticesTriList.SetCount(0);
         self.m_rgVerticesTriList.SetCount(0);
+        if let Some(opaque) = &mut self.m_rgVerticesOpaqueTriList {
+            opaque.SetCount(0);
+        }
         self.m_rgVerticesBufferOffset = 0;
         //self.m_rgVerticesLineList.SetCount(0);
         //self.m_rgVerticesNonIndexedTriList.SetCount(0);
@@ -745,8 +867,13 @@ impl<TVertex> CHwTVertexBuffer<'_, TVertex> {
         //self.m_pBuilder = pVBB;
     }
 
-    fn IsEmpty(&self) -> bool
+    pub fn IsEmpty(&self) -> bool
     {
+        if let Some(opaque) = &self.m_rgVerticesOpaqueTriList {
+            if opaque.GetCount() != 0 {
+                return false;
+            }
+        }
         return true
              //  && (self.m_rgIndices.GetCount() == 0)
             //&& (self.m_rgVerticesLineList.GetCount() == 0)
@@ -1104,6 +1231,7 @@ private:
     m_fNeedOutsideGeometry: bool,
     m_fNeedInsideGeometry: bool,
     m_rcOutsideBounds: CMILSurfaceRect, // Bounds for creation of outside geometry
+    m_nFullCoverage: INT, // Complex scan coverage of a fully covered pixel
 
     /* 
     // Helpful m_rcOutsideBounds casts.
@@ -1212,7 +1340,7 @@ Cleanup:
 }
 */
 
-impl CHwVertexBuffer<'_> {
+impl<TVertex: Vertex> CHwTVertexBuffer<'_, TVertex> {
 //+----------------------------------------------------------------------------
 //
 //  Member:    CHwTVertexBuffer<TVertex>::AddLine
@@ -1311,8 +1439,30 @@ CHwTVertexBuffer<TVertex>::AddTriListVertices(
 
 impl<TVertex: Clone + Default> CHwTVertexBuffer<'_, TVertex> {
 
+// Adds a triangle using the three indices given to the list
+fn AddTriangle(&mut self, i1: u32, i2: u32, i3: u32) {
+    if let Some(indices) = &mut self.m_rgIndices {
+        indices.reserve(3);
+        indices.push(i1);
+        indices.push(i2);
+        indices.push(i3);
+    }
+}
+
+// Adds a vertex that triangles can share through AddTriangle and returns its index
+fn AddTriListVertex(&mut self, v: TVertex) -> u32 {
+    let wIndex = self.m_rgVerticesTriList.len() as u32;
+    self.m_rgVerticesTriList.push(v);
+    wIndex
+}
+
 fn AddTriVertices(&mut self, v0: TVertex, v1: TVertex, v2: TVertex) {
-    if let Some(output_buffer) = &mut self.m_rgVerticesBuffer {
+    if self.m_rgIndices.is_some() {
+        let i0 = self.AddTriListVertex(v0);
+        let i1 = self.AddTriListVertex(v1);
+        let i2 = self.AddTriListVertex(v2);
+        self.AddTriangle(i0, i1, i2);
+    } else if let Some(output_buffer) = &mut self.m_rgVerticesBuffer {
         let offset = self.m_rgVerticesBufferOffset;
         if offset + 3 <= output_buffer.len() {
             output_buffer[offset] = v0;
@@ -1328,8 +1478,20 @@ fn AddTriVertices(&mut self, v0: TVertex, v1: TVertex, v2: TVertex) {
     }
 }
 
-fn AddTrapezoidVertices(&mut self, v0: TVertex, v1: TVertex, v2: TVertex, v3: TVertex) {
-    if let Some(output_buffer) = &mut self.m_rgVerticesBuffer {
+// fSharesLeftEdge is set when v0 and v1 are the v2 and v3 of the last trapezoid that was
+// added, so that they can be reused when the vertices are shared.
+fn AddTrapezoidVertices(&mut self, fSharesLeftEdge: bool, v0: TVertex, v1: TVertex, v2: TVertex, v3: TVertex) {
+    if self.m_rgIndices.is_some() {
+        let (i0, i1) = match self.m_wLastTrapezoidRightEdge {
+            Some(wRightEdge) if fSharesLeftEdge => (wRightEdge, wRightEdge + 1),
+            _ => (self.AddTriListVertex(v0), self.AddTriListVertex(v1)),
+        };
+        let i2 = self.AddTriListVertex(v2);
+        let i3 = self.AddTriListVertex(v3);
+        self.AddTriangle(i0, i1, i2);
+        self.AddTriangle(i1, i2, i3);
+        self.m_wLastTrapezoidRightEdge = Some(i2);
+    } else if let Some(output_buffer) = &mut self.m_rgVerticesBuffer {
         let offset = self.m_rgVerticesBufferOffset;
         if offset + 6 <= output_buffer.len() {
             output_buffer[offset] = v0;
@@ -1354,6 +1516,35 @@ fn AddTrapezoidVertices(&mut self, v0: TVertex, v1: TVertex, v2: TVertex, v3: TV
     }
 }
 
+// Like AddTriVertices, but for a triangle that is fully covered.
+fn AddOpaqueTriVertices(&mut self, v0: TVertex, v1: TVertex, v2: TVertex) {
+    if let Some(opaque) = &mut self.m_rgVerticesOpaqueTriList {
+        opaque.reserve(3);
+        opaque.push(v0);
+        opaque.push(v1);
+        opaque.push(v2);
+    } else {
+        self.AddTriVertices(v0, v1, v2);
+    }
+}
+
+// Like AddTrapezoidVertices, but for a trapezoid that is fully covered.
+fn AddOpaqueTrapezoidVertices(&mut self, fSharesLeftEdge: bool, v0: TVertex, v1: TVertex, v2: TVertex, v3: TVertex) {
+    if let Some(opaque) = &mut self.m_rgVerticesOpaqueTriList {
+        opaque.reserve(6);
+
+        opaque.push(v0);
+        opaque.push(v1.clone());
+        opaque.push(v2.clone());
+
+        opaque.push(v1);
+        opaque.push(v2);
+        opaque.push(v3);
+    } else {
+        self.AddTrapezoidVertices(fSharesLeftEdge, v0, v1, v2, v3);
+    }
+}
+
 fn AddedNonLineSegment(&mut self) {
     #[cfg(debug_assertions)]
     {
@@ -1486,16 +1677,16 @@ CHwTVertexBuffer<CD3DVertexXYZNDSUV4>::Builder::GetOutVertexFormat()
 //
 */
 pub type CHwVertexBufferBuilder<'y, 'z> = CHwTVertexBufferBuilder<'y, 'z, OutputVertex>;
-impl<'y, 'z> CHwVertexBufferBuilder<'y, 'z> {
+impl<'y, 'z, TVertex: Vertex> CHwTVertexBufferBuilder<'y, 'z, TVertex> {
 pub fn Create(
      vfIn: MilVertexFormat,
      vfOut: MilVertexFormat,
      mvfaAntiAliasScaleLocation: MilVertexFormatAttribute,
-    pVertexBuffer: &'y mut CHwVertexBuffer<'z>,
+    pVertexBuffer: &'y mut CHwTVertexBuffer<'z, TVertex>,
     /*pBufferDispenser: &CBufferDispenser*/
-    ) -> CHwVertexBufferBuilder<'y, 'z>
+    ) -> CHwTVertexBufferBuilder<'y, 'z, TVertex>
 {
-    CHwVertexBufferBuilder::CreateTemplate(pVertexBuffer, vfIn, vfOut, mvfaAntiAliasScaleLocation)
+    CHwTVertexBufferBuilder::CreateTemplate(pVertexBuffer, vfIn, vfOut, mvfaAntiAliasScaleLocation)
     //let hr: HRESULT = S_OK;
 
     //assert!(ppVertexBufferBuilder);
@@ -1748,6 +1939,7 @@ fn new(pVertexBuffer: &'y mut CHwTVertexBuffer<'z, TVertex>) -> Self
     m_fHasFlushed: false,
     //m_map: Default::default(),
     m_rcOutsideBounds: Default::default(),
+    m_nFullCoverage: CSubpixelGrid::default().nShiftSizeSquared,
         #[cfg(debug_assertions)]
         m_mvfDbgOut: MilVertexFormatAttribute::MILVFAttrNone as MilVertexFormat,
         m_mvfIn: MilVertexFormatAttribute::MILVFAttrNone as MilVertexFormat,
@@ -1926,7 +2118,22 @@ pub fn BeginBuilding(&mut self,
     RRETURN!(hr);
 }
 }
-impl IGeometrySink for CHwVertexBufferBuilder<'_, '_> {
+impl<TVertex: Vertex> IGeometrySink for CHwTVertexBufferBuilder<'_, '_, TVertex> {
+
+    //+------------------------------------------------------------------------
+    //
+    //  Member:    CHwTVertexBuffer<TVertex>::Builder::SetSubpixelGrid
+    //
+    //  Synopsis:  Sets the subpixel grid that complex scan coverage is
+    //             computed in so that it can be normalized.
+    //
+
+    fn SetSubpixelGrid(&mut self,
+        grid: CSubpixelGrid
+        )
+    {
+        self.m_nFullCoverage = grid.nShiftSizeSquared;
+    }
 
     fn AddTrapezoid(&mut self,
         rPixelYTop: f32,              // In: y coordinate of top of trapezoid
@@ -2216,7 +2423,7 @@ Cleanup:
         //
         if (self.NeedCoverageGeometry((*pIntervalSpanStart).m_nCoverage.get()))
         {
-            let rCoverage: f32 = ((*pIntervalSpanStart).m_nCoverage.get() as f32)/(c_nShiftSizeSquared as f32);
+            let rCoverage: f32 = ((*pIntervalSpanStart).m_nCoverage.get() as f32)/(self.m_nFullCoverage as f32);
             
             let mut iBegin: LONG = (*pIntervalSpanStart).m_nPixelX.get();
             let mut iEnd: LONG = (*(*pIntervalSpanStart).m_pNext.get()).m_nPixelX.get();
@@ -2300,7 +2507,7 @@ Cleanup:
 //             longer be needed.  (Pixel center conventions will also change.)
 //              
 //-----------------------------------------------------------------------------
-impl CHwVertexBuffer<'_> {
+impl<TVertex: Vertex> CHwTVertexBuffer<'_, TVertex> {
     fn AddLineAsTriangleList(&mut self,
     pBegin: &CD3DVertexXYZDUV2, // Begin
     pEnd: &CD3DVertexXYZDUV2    // End
@@ -2334,11 +2541,14 @@ impl CHwVertexBuffer<'_> {
 
 
     // Use a single triangle to cover the entire line
-    self.AddTriVertices(
-        OutputVertex{ x: x0, y: y - 0.5, coverage: dwDiffuse },
-        OutputVertex{ x: x0, y: y + 0.5, coverage: dwDiffuse },
-        OutputVertex{ x: x1, y: y + subpixel_bias, coverage: dwDiffuse },
-    );
+    let v0 = self.m_map.PointToVertex(x0, y - 0.5, dwDiffuse);
+    let v1 = self.m_map.PointToVertex(x0, y + 0.5, dwDiffuse);
+    let v2 = self.m_map.PointToVertex(x1, y + subpixel_bias, dwDiffuse);
+    if dwDiffuse == FLOAT_ONE {
+        self.AddOpaqueTriVertices(v0, v1, v2);
+    } else {
+        self.AddTriVertices(v0, v1, v2);
+    }
 
     self.AddedNonLineSegment();
 
@@ -2540,7 +2750,7 @@ CHwTVertexBuffer<TVertex>::Builder::IsEmpty()
 //    + ^^                        +
 //      delta
 //
-impl CHwVertexBufferBuilder<'_, '_> {
+impl<TVertex: Vertex> CHwTVertexBufferBuilder<'_, '_, TVertex> {
 
 //+----------------------------------------------------------------------------
 //
@@ -2560,7 +2770,6 @@ fn AddTrapezoidStandard(&mut self,
     rPixelXRightDelta: f32        // In: trapezoid expand radius for right edge
     ) -> HRESULT
 {
-    type TVertex = CD3DVertexXYZDUV2;
     let hr = S_OK;
     //TVertex *pVertex;
 
@@ -2586,78 +2795,89 @@ fn AddTrapezoidStandard(&mut self,
     // Fill in the vertices
     //
 
-    self.m_pVB.AddTrapezoidVertices(
-        OutputVertex{
-            x: rPixelXTopLeft - rPixelXLeftDelta,
-            y: rPixelYTop,
-            coverage: FLOAT_ZERO,
-        },
-        OutputVertex{
-            x: rPixelXBottomLeft - rPixelXLeftDelta,
-            y: rPixelYBottom,
-            coverage: FLOAT_ZERO,
-        },
-        OutputVertex{
-            x: rPixelXTopLeft + rPixelXLeftDelta,
-            y: rPixelYTop,
-            coverage: FLOAT_ONE,
-        },
-        OutputVertex{
-            x: rPixelXBottomLeft + rPixelXLeftDelta,
-            y: rPixelYBottom,
-            coverage: FLOAT_ONE,
-        }
-    );
+    // Aliased trapezoids aren't expanded so they have no falloff
+    // on either side.
+    if (rPixelXLeftDelta > 0.0)
+    {
+        self.m_pVB.AddTrapezoidVertices(
+            false,
+            self.m_pVB.m_map.PointToVertex(
+                rPixelXTopLeft - rPixelXLeftDelta,
+                rPixelYTop,
+                FLOAT_ZERO,
+            ),
+            self.m_pVB.m_map.PointToVertex(
+                rPixelXBottomLeft - rPixelXLeftDelta,
+                rPixelYBottom,
+                FLOAT_ZERO,
+            ),
+            self.m_pVB.m_map.PointToVertex(
+                rPixelXTopLeft + rPixelXLeftDelta,
+                rPixelYTop,
+                FLOAT_ONE,
+            ),
+            self.m_pVB.m_map.PointToVertex(
+                rPixelXBottomLeft + rPixelXLeftDelta,
+                rPixelYBottom,
+                FLOAT_ONE,
+            )
+        );
+    }
 
 
     if (fNeedInsideGeometry)
     {
-        self.m_pVB.AddTrapezoidVertices(
-            OutputVertex{
-                x: rPixelXTopLeft + rPixelXLeftDelta,
-                y: rPixelYTop,
-                coverage: FLOAT_ONE,
-            },
-            OutputVertex{
-                x: rPixelXBottomLeft + rPixelXLeftDelta,
-                y: rPixelYBottom,
-                coverage: FLOAT_ONE,
-            },
-            OutputVertex{
-                x: rPixelXTopRight - rPixelXRightDelta,
-                y: rPixelYTop,
-                coverage: FLOAT_ONE,
-            },
-            OutputVertex{
-                x: rPixelXBottomRight - rPixelXRightDelta,
-                y: rPixelYBottom,
-                coverage: FLOAT_ONE,
-            }
+        self.m_pVB.AddOpaqueTrapezoidVertices(
+            rPixelXLeftDelta > 0.0,
+            self.m_pVB.m_map.PointToVertex(
+                rPixelXTopLeft + rPixelXLeftDelta,
+                rPixelYTop,
+                FLOAT_ONE,
+            ),
+            self.m_pVB.m_map.PointToVertex(
+                rPixelXBottomLeft + rPixelXLeftDelta,
+                rPixelYBottom,
+                FLOAT_ONE,
+            ),
+            self.m_pVB.m_map.PointToVertex(
+                rPixelXTopRight - rPixelXRightDelta,
+                rPixelYTop,
+                FLOAT_ONE,
+            ),
+            self.m_pVB.m_map.PointToVertex(
+                rPixelXBottomRight - rPixelXRightDelta,
+                rPixelYBottom,
+                FLOAT_ONE,
+            )
         );
     }
 
-    self.m_pVB.AddTrapezoidVertices(
-        OutputVertex{
-            x: rPixelXTopRight - rPixelXRightDelta,
-            y: rPixelYTop,
-            coverage: FLOAT_ONE,
-        },
-        OutputVertex{
-            x: rPixelXBottomRight - rPixelXRightDelta,
-            y: rPixelYBottom,
-            coverage: FLOAT_ONE,
-        },
-        OutputVertex{
-            x: rPixelXTopRight + rPixelXRightDelta,
-            y: rPixelYTop,
-            coverage: FLOAT_ZERO,
-        },
-        OutputVertex{
-            x: rPixelXBottomRight + rPixelXRightDelta,
-            y: rPixelYBottom,
-            coverage: FLOAT_ZERO,
-        }
-    );
+    if (rPixelXRightDelta > 0.0)
+    {
+        self.m_pVB.AddTrapezoidVertices(
+            fNeedInsideGeometry,
+            self.m_pVB.m_map.PointToVertex(
+                rPixelXTopRight - rPixelXRightDelta,
+                rPixelYTop,
+                FLOAT_ONE,
+            ),
+            self.m_pVB.m_map.PointToVertex(
+                rPixelXBottomRight - rPixelXRightDelta,
+                rPixelYBottom,
+                FLOAT_ONE,
+            ),
+            self.m_pVB.m_map.PointToVertex(
+                rPixelXTopRight + rPixelXRightDelta,
+                rPixelYTop,
+                FLOAT_ZERO,
+            ),
+            self.m_pVB.m_map.PointToVertex(
+                rPixelXBottomRight + rPixelXRightDelta,
+                rPixelYBottom,
+                FLOAT_ZERO,
+            )
+        );
+    }
 
     if (!fNeedOutsideGeometry)
     {
@@ -2760,7 +2980,7 @@ Cleanup:
     RRETURN(hr);
 }
 */
-impl CHwVertexBufferBuilder<'_, '_> {
+impl<TVertex: Vertex> CHwTVertexBufferBuilder<'_, '_, TVertex> {
 
     //+----------------------------------------------------------------------------
 //
@@ -2770,7 +2990,7 @@ impl CHwVertexBufferBuilder<'_, '_> {
 //             based on NeedInsideGeometry() and NeedOutsideGeometry()
 //
 //             Two cases where we don't need to generate geometry:
-//              1. NeedInsideGeometry is false, and coverage is c_nShiftSizeSquared.
+//              1. NeedInsideGeometry is false, and coverage is full.
 //              2. NeedOutsideGeometry is false and coverage is 0
 //
 //-----------------------------------------------------------------------------
@@ -2778,7 +2998,7 @@ fn NeedCoverageGeometry(&self,
     nCoverage: INT
     ) -> bool
 {
-    return    (self.NeedInsideGeometry()  || nCoverage != c_nShiftSizeSquared)
+    return    (self.NeedInsideGeometry()  || nCoverage != self.m_nFullCoverage)
            && (self.NeedOutsideGeometry() || nCoverage != 0);
 }
 
@@ -2875,7 +3095,6 @@ fn PrepareStratumSlow(&mut self,
     rTrapezoidBottomRight: f32,
     ) -> HRESULT
 {
-    type TVertex = OutputVertex;
     let hr: HRESULT = S_OK;
     
     assert!(!(rStratumTop > rStratumBottom));
@@ -2915,26 +3134,27 @@ fn PrepareStratumSlow(&mut self,
             // End current trapezoid stratum.
 
             self.m_pVB.AddTrapezoidVertices(
-                OutputVertex{
-                    x: self.m_rLastTrapezoidTopRight,
-                    y: self.m_rCurStratumTop,
-                    coverage: FLOAT_ZERO,
-                },
-                OutputVertex{
-                    x: self.m_rLastTrapezoidBottomRight,
-                    y: self.m_rCurStratumBottom,
-                    coverage: FLOAT_ZERO,
-                },
-                OutputVertex{
-                    x: rOutsideRight,
-                    y: self.m_rCurStratumTop,
-                    coverage: FLOAT_ZERO,
-                },
-                OutputVertex{
-                    x: rOutsideRight,
-                    y: self.m_rCurStratumBottom,
-                    coverage: FLOAT_ZERO,
-                }
+                false,
+                self.m_pVB.m_map.PointToVertex(
+                    self.m_rLastTrapezoidTopRight,
+                    self.m_rCurStratumTop,
+                    FLOAT_ZERO,
+                ),
+                self.m_pVB.m_map.PointToVertex(
+                    self.m_rLastTrapezoidBottomRight,
+                    self.m_rCurStratumBottom,
+                    FLOAT_ZERO,
+                ),
+                self.m_pVB.m_map.PointToVertex(
+                    rOutsideRight,
+                    self.m_rCurStratumTop,
+                    FLOAT_ZERO,
+                ),
+                self.m_pVB.m_map.PointToVertex(
+                    rOutsideRight,
+                    self.m_rCurStratumBottom,
+                    FLOAT_ZERO,
+                )
             );
         }
         // Compute the gap between where the last stratum ended and where
@@ -2960,26 +3180,27 @@ fn PrepareStratumSlow(&mut self,
             
             // Duplicate first vertex.
             self.m_pVB.AddTrapezoidVertices(
-                OutputVertex{
-                    x: outside_left,
-                    y: flRectTop,
-                    coverage: FLOAT_ZERO,
-                },
-                OutputVertex{
-                    x: outside_left,
-                    y: flRectBot,
-                    coverage: FLOAT_ZERO,
-                },
-                OutputVertex{
-                    x: outside_right,
-                    y: flRectTop,
-                    coverage: FLOAT_ZERO,
-                },
-                OutputVertex{
-                    x: outside_right,
-                    y: flRectBot,
-                    coverage: FLOAT_ZERO,
-                }
+                false,
+                self.m_pVB.m_map.PointToVertex(
+                    outside_left,
+                    flRectTop,
+                    FLOAT_ZERO,
+                ),
+                self.m_pVB.m_map.PointToVertex(
+                    outside_left,
+                    flRectBot,
+                    FLOAT_ZERO,
+                ),
+                self.m_pVB.m_map.PointToVertex(
+                    outside_right,
+                    flRectTop,
+                    FLOAT_ZERO,
+                ),
+                self.m_pVB.m_map.PointToVertex(
+                    outside_right,
+                    flRectBot,
+                    FLOAT_ZERO,
+                )
             );
         }
 
@@ -2994,26 +3215,27 @@ fn PrepareStratumSlow(&mut self,
             // Begin new trapezoid stratum.
 
             self.m_pVB.AddTrapezoidVertices(
-                OutputVertex{
-                    x: rOutsideLeft,
-                    y: rStratumTop,
-                    coverage: FLOAT_ZERO,
-                },
-                OutputVertex{
-                    x: rOutsideLeft,
-                    y: rStratumBottom,
-                    coverage: FLOAT_ZERO,
-                },
-                OutputVertex{
-                    x: rTrapezoidTopLeft,
-                    y: rStratumTop,
-                    coverage: FLOAT_ZERO,
-                },
-                OutputVertex{
-                    x: rTrapezoidBottomLeft,
-                    y: rStratumBottom,
-                    coverage: FLOAT_ZERO,
-                }
+                false,
+                self.m_pVB.m_map.PointToVertex(
+                    rOutsideLeft,
+                    rStratumTop,
+                    FLOAT_ZERO,
+                ),
+                self.m_pVB.m_map.PointToVertex(
+                    rOutsideLeft,
+                    rStratumBottom,
+                    FLOAT_ZERO,
+                ),
+                self.m_pVB.m_map.PointToVertex(
+                    rTrapezoidTopLeft,
+                    rStratumTop,
+                    FLOAT_ZERO,
+                ),
+                self.m_pVB.m_map.PointToVertex(
+                    rTrapezoidBottomLeft,
+                    rStratumBottom,
+                    FLOAT_ZERO,
+                )
             );
         }
     }
@@ -30,6 +30,8 @@ mod bezier;
 mod aarasterizer;
 mod hwrasterizer;
 mod aacoverage;
+mod arena;
+mod band_pool;
 mod hwvertexbuffer;
 
 mod types;
@@ -38,17 +40,52 @@ mod matrix;
 
 mod nullable_ref;
 
+mod batch;
+mod boolean;
+mod clipcoverage;
+mod error;
+mod flatten;
+mod mask;
+mod measure;
+mod mesh;
+mod preclip;
+mod rasterizer;
+mod sink;
+mod stroke;
+mod svg_path;
+
 #[cfg(feature = "c_bindings")]
 pub mod c_bindings;
 
 #[cfg(test)]
 mod tri_rasterize;
 
+use std::sync::{Arc, Mutex};
+
+use aacoverage::{c_nShift, CSubpixelGrid};
+use bezier::BEZIER_DEFAULT_TOLERANCE;
 use aarasterizer::CheckValidRange28_4;
-use hwrasterizer::CHwRasterizer;
-use hwvertexbuffer::{CHwVertexBuffer, CHwVertexBufferBuilder};
+use band_pool::DEFAULT_BAND_POOL;
+use clipcoverage::CClipCoverage;
+use geometry_sink::{CNullGeometrySink, IGeometrySink};
+use hwrasterizer::{CHwRasterizer, CRasterizerStorage};
+use hwvertexbuffer::{CHwTVertexBuffer, CHwTVertexBufferBuilder, CHwVertexBuffer};
+use matrix::CMILMatrix;
 use real::CFloatFPU;
-use types::{MilFillMode, PathPointTypeStart, MilPoint2F, MilPointAndSizeL, PathPointTypeLine, MilVertexFormat, MilVertexFormatAttribute, DynArray, BYTE, PathPointTypeBezier, PathPointTypeCloseSubpath, CMILSurfaceRect, POINT};
+use sink::{OffsetSink, SinkAdapter};
+pub use band_pool::BandPool;
+pub use batch::{Batch, BatchVertex, VertexRange};
+pub use boolean::BooleanOp;
+pub use error::RasterError;
+pub use flatten::{FlattenedSubpath, FlattenedSubpaths};
+pub use mask::Mask;
+pub use measure::Bounds;
+pub use mesh::{Mesh, MeshIndices};
+pub use rasterizer::Rasterizer;
+pub use sink::{CoverageSpan, GeometrySink, Trapezoid};
+pub use stroke::{LineCap, LineJoin, StrokeStyle, Stroker};
+pub use svg_path::{SvgPathError, SvgPathErrorKind};
+use types::{MilAntiAliasMode, MilFillMode, PathPointTypeStart, MilPoint2F, MilPointAndSizeL, PathPointTypeLine, MilVertexFormat, MilVertexFormatAttribute, DynArray, BYTE, PathPointTypeBezier, PathPointTypeQuadratic, PathPointTypeCloseSubpath, CMILSurfaceRect, POINT};
 
 #[repr(C)]
 #[derive(Clone, Debug, Default)]
@@ -58,6 +95,48 @@ pub struct OutputVertex {
     pub coverage: f32
 }
 
+/// A vertex of the triangle list. Implement this to have the rasterizer write vertices
+/// in a custom layout, e.g. with packed positions or extra attributes.
+pub trait Vertex: Clone + Default + Send {
+    fn new(x: f32, y: f32, coverage: f32) -> Self;
+
+    /// Like `new`, but also gets the texture coordinates that `PathBuilder::set_uv_transforms`
+    /// maps the position to. Layouts without texture coordinates can ignore them.
+    fn with_uv(x: f32, y: f32, coverage: f32, uv: [(f32, f32); 2]) -> Self {
+        let _ = uv;
+        Self::new(x, y, coverage)
+    }
+}
+
+impl Vertex for OutputVertex {
+    fn new(x: f32, y: f32, coverage: f32) -> Self {
+        OutputVertex { x, y, coverage }
+    }
+}
+
+/// An `OutputVertex` with two sets of texture coordinates, see `PathBuilder::set_uv_transforms`.
+#[repr(C)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OutputVertexUV {
+    pub x: f32,
+    pub y: f32,
+    pub coverage: f32,
+    pub u0: f32,
+    pub v0: f32,
+    pub u1: f32,
+    pub v1: f32,
+}
+
+impl Vertex for OutputVertexUV {
+    fn new(x: f32, y: f32, coverage: f32) -> Self {
+        OutputVertexUV { x, y, coverage, ..Default::default() }
+    }
+
+    fn with_uv(x: f32, y: f32, coverage: f32, [(u0, v0), (u1, v1)]: [(f32, f32); 2]) -> Self {
+        OutputVertexUV { x, y, coverage, u0, v0, u1, v1 }
+    }
+}
+
 #[repr(C)]
 #[derive(Copy, Clone)]
 pub enum FillMode {
@@ -71,11 +150,310 @@ impl Default for FillMode {
     }
 }
 
+impl FillMode {
+    fn to_mil(self) -> MilFillMode {
+        match self {
+            FillMode::EvenOdd => MilFillMode::Alternate,
+            FillMode::Winding => MilFillMode::Winding,
+        }
+    }
+}
+
+/// The size of the subpixel grid that is used to compute antialiased coverage.
+/// Finer grids give more accurate coverage for thin features at the cost of
+/// more work on complex scans.
+///
+/// `Aliased` samples once at each pixel center instead, so every vertex has a
+/// coverage of 1.0 (or 0.0 for outside geometry) and there are no falloff ramps.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum AntialiasMode {
+    FourByFour = 0,
+    #[default]
+    EightByEight = 1,
+    SixteenBySixteen = 2,
+    Aliased = 3,
+}
+
+impl AntialiasMode {
+    fn to_mil(self) -> MilAntiAliasMode {
+        match self {
+            AntialiasMode::FourByFour => MilAntiAliasMode::FourByFour,
+            AntialiasMode::EightByEight => MilAntiAliasMode::EightByEight,
+            AntialiasMode::SixteenBySixteen => MilAntiAliasMode::SixteenBySixteen,
+            AntialiasMode::Aliased => MilAntiAliasMode::None,
+        }
+    }
+}
+
+/// How curves are flattened into lines before they are rasterized.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum CurveFlattening {
+    /// Forward differencing, like WPF. The lines end wherever the steps of the
+    /// differencing end.
+    #[default]
+    ForwardDifferencing = 0,
+    /// The lines end on pixel row boundaries where the curve allows it. Rows that no
+    /// line starts or ends in can be output as trapezoids, so curved paths produce fewer
+    /// complex scans, at the cost of slower flattening.
+    Scanlines = 1,
+}
+
+// The flattening settings of a PathBuilder, which its OutputPaths keep
+#[derive(Copy, Clone, Debug)]
+struct Flattening {
+    tolerance: f32,
+    curve_flattening: CurveFlattening,
+}
+
+impl Default for Flattening {
+    fn default() -> Self {
+        Flattening { tolerance: BEZIER_DEFAULT_TOLERANCE, curve_flattening: CurveFlattening::default() }
+    }
+}
+
+impl Flattening {
+    fn apply(&self, rasterizer: &mut CHwRasterizer) {
+        rasterizer.SetBezierTolerance(self.tolerance);
+        rasterizer.SetAlignBeziersToScanlines(self.aligns_to_scanlines());
+    }
+
+    fn aligns_to_scanlines(&self) -> bool {
+        self.curve_flattening == CurveFlattening::Scanlines
+    }
+}
+
+// The settings that are passed down from the entry points to the rasterizers
+#[derive(Clone)]
+struct RasterOptions<'a> {
+    fill_mode: FillMode,
+    transform: Option<Transform>,
+    antialias_mode: AntialiasMode,
+    clip_rect: MilPointAndSizeL,
+    clip_path: Option<&'a OutputPath>,
+    flattening: Flattening,
+    // The number of bands to split the rows into and the pool to run them on, see
+    // rasterize_path_in_bands
+    bands: usize,
+    band_pool: Option<&'a dyn BandPool>,
+}
+
+impl<'a> RasterOptions<'a> {
+    fn new(fill_mode: FillMode, transform: Option<Transform>, antialias_mode: AntialiasMode,
+           clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> Self {
+        RasterOptions {
+            fill_mode,
+            transform,
+            antialias_mode,
+            clip_rect: MilPointAndSizeL { X: clip_x, Y: clip_y, Width: clip_width, Height: clip_height },
+            clip_path: None,
+            flattening: Flattening::default(),
+            bands: 1,
+            band_pool: None,
+        }
+    }
+
+    // Creates a rasterizer for the fill with these settings
+    fn fill_rasterizer<'s>(&self, sink: &'s mut dyn IGeometrySink) -> CHwRasterizer<'s> {
+        let mut rasterizer = CHwRasterizer::new(
+            sink, self.fill_mode.to_mil(), self.transform.map(|t| t.to_matrix()), self.clip_rect.clone());
+        rasterizer.SetAntiAliasMode(self.antialias_mode.to_mil());
+        self.flattening.apply(&mut rasterizer);
+        rasterizer
+    }
+}
+
+/// The settings of the free rasterization functions, e.g. `rasterize_to_tri_list_with_options`.
+/// `new` sets the clip rect and leaves the rest at the defaults of `PathBuilder`.
+#[derive(Copy, Clone)]
+pub struct RasterizeOptions {
+    pub fill_mode: FillMode,
+    /// Maps the 28.4 points before they are rasterized, in the same space as the clip rect,
+    /// i.e. in pixels with integers at the pixel corners.
+    pub transform: Option<Transform>,
+    pub antialias_mode: AntialiasMode,
+    pub clip_x: i32,
+    pub clip_y: i32,
+    pub clip_width: i32,
+    pub clip_height: i32,
+    /// Whether the inside of the path is output.
+    pub need_inside: bool,
+    /// Whether the outside of the path is output, out to the clip rect.
+    pub need_outside: bool,
+    pub rasterization_truncates: bool,
+    /// The maximum distance between curves and the lines they are flattened into, in pixels.
+    pub flattening_tolerance: f32,
+    pub curve_flattening: CurveFlattening,
+}
+
+impl RasterizeOptions {
+    pub fn new(clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> Self {
+        RasterizeOptions {
+            fill_mode: FillMode::default(),
+            transform: None,
+            antialias_mode: AntialiasMode::default(),
+            clip_x,
+            clip_y,
+            clip_width,
+            clip_height,
+            need_inside: true,
+            need_outside: false,
+            rasterization_truncates: false,
+            flattening_tolerance: Flattening::default().tolerance,
+            curve_flattening: CurveFlattening::default(),
+        }
+    }
+
+    fn raster_options(&self) -> RasterOptions<'static> {
+        RasterOptions {
+            flattening: Flattening { tolerance: self.flattening_tolerance, curve_flattening: self.curve_flattening },
+            ..RasterOptions::new(self.fill_mode, self.transform, self.antialias_mode,
+                                 self.clip_x, self.clip_y, self.clip_width, self.clip_height)
+        }
+    }
+}
+
+/// A 2D affine transform that maps a path point (x, y) to
+/// `(m11 * x + m21 * y + dx, m12 * x + m22 * y + dy)`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub m11: f32,
+    pub m12: f32,
+    pub m21: f32,
+    pub m22: f32,
+    pub dx: f32,
+    pub dy: f32,
+}
+
+impl Transform {
+    pub fn new(m11: f32, m12: f32, m21: f32, m22: f32, dx: f32, dy: f32) -> Self {
+        Transform { m11, m12, m21, m22, dx, dy }
+    }
+    pub(crate) fn transform_point(self, x: f32, y: f32) -> (f32, f32) {
+        (self.m11 * x + self.m21 * y + self.dx, self.m12 * x + self.m22 * y + self.dy)
+    }
+    // Like transform_point, but without rounding the intermediate results to f32
+    pub(crate) fn transform_point_f64(self, x: f32, y: f32) -> (f64, f64) {
+        let (x, y) = (x as f64, y as f64);
+        (self.m11 as f64 * x + self.m21 as f64 * y + self.dx as f64,
+         self.m12 as f64 * x + self.m22 as f64 * y + self.dy as f64)
+    }
+    fn to_matrix(self) -> CMILMatrix {
+        let mut matrix = CMILMatrix::Identity();
+        matrix.SetM11(self.m11);
+        matrix.SetM12(self.m12);
+        matrix.SetM21(self.m21);
+        matrix.SetM22(self.m22);
+        matrix.SetDx(self.dx);
+        matrix.SetDy(self.dy);
+        matrix
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::new(1., 0., 0., 1., 0., 0.)
+    }
+}
+
+/// A triangle list that is split in two. The opaque triangles are fully covered and can
+/// be drawn without blending, e.g. front to back with depth testing. The fringe has the
+/// partially covered triangles that need blending. Drawing both gives the same result as
+/// drawing the whole triangle list.
+#[derive(Clone, Debug, Default)]
+pub struct OpaqueAndFringe {
+    pub opaque: Box<[OutputVertex]>,
+    pub fringe: Box<[OutputVertex]>,
+}
+
 #[derive(Clone, Default)]
 pub struct OutputPath {
     fill_mode: FillMode,
     points: Box<[POINT]>,
     types: Box<[BYTE]>,
+    // The flattening settings of the PathBuilder that the path came from, which the
+    // queries, strokes and boolean operations flatten the curves with
+    flattening: Flattening,
+}
+
+impl OutputPath {
+    pub fn fill_mode(&self) -> FillMode {
+        self.fill_mode
+    }
+
+    /// The points in 28.4 fixed point, in the format that `rasterize_to_tri_list` takes.
+    pub fn points(&self) -> &[POINT] {
+        &self.points
+    }
+
+    /// The type of each segment: 0 starts a subpath, 1 is a line to the next point,
+    /// 2 is a quadratic curve through the next 2 points and 3 is a cubic curve through
+    /// the next 3 points. 0x80 closes the subpath.
+    pub fn types(&self) -> &[BYTE] {
+        &self.types
+    }
+
+    /// Returns the subpaths of the path with their curves flattened into lines that are
+    /// within `tolerance` pixels of the curves, using the same `CurveFlattening` as the
+    /// `PathBuilder` that the path came from. Open and closed subpaths are returned as they
+    /// are. A path with invalid types has no subpaths.
+    pub fn flatten(&self, tolerance: f32) -> FlattenedSubpaths<'_> {
+        let flattening = Flattening { tolerance, ..self.flattening };
+        match error::validate_path(&self.types, self.points.len()) {
+            Ok(()) => FlattenedSubpaths::new(&self.types, &self.points, flattening),
+            Err(_) => FlattenedSubpaths::new(&[], &[], flattening),
+        }
+    }
+
+    // The subpaths flattened with the settings of the PathBuilder that the path came from
+    fn flatten_as_drawn(&self) -> FlattenedSubpaths<'_> {
+        self.flatten(self.flattening.tolerance)
+    }
+
+    /// Returns the bounds of the path as it is drawn, with its curves flattened with the
+    /// tolerance and `CurveFlattening` of the `PathBuilder` that the path came from.
+    /// Returns None for an empty or invalid path.
+    pub fn bounds(&self) -> Option<Bounds> {
+        measure::flattened_bounds(self.flatten_as_drawn())
+    }
+
+    /// Returns the bounds of all of the points of the path, including the control points
+    /// of its curves. This contains `bounds()` and is cheaper to compute.
+    pub fn control_bounds(&self) -> Option<Bounds> {
+        measure::bounds_of(self.points.iter().copied())
+    }
+
+    /// Returns the signed area that the path encloses in square pixels, with its curves
+    /// flattened like `bounds()` and every subpath treated as closed. Subpaths that run
+    /// clockwise on screen add to the area and counterclockwise ones subtract from it.
+    pub fn area(&self) -> f32 {
+        measure::signed_area(self.flatten_as_drawn()) as f32
+    }
+
+    /// Returns the length in pixels of each subpath that `flatten` returns, with the curves
+    /// flattened like `bounds()`. Closed subpaths include the line back to their start.
+    pub fn subpath_perimeters(&self) -> Vec<f32> {
+        measure::perimeters(self.flatten_as_drawn())
+    }
+
+    /// Returns whether the point (x, y) in pixels is inside of the path when it is filled
+    /// with `fill_mode`. The curves are flattened like `bounds()` and points on the edges
+    /// follow the rasterizer's convention: the top and left edges of a shape are inside
+    /// and the bottom and right edges are outside. Aliased rasterization covers a pixel
+    /// when the path contains the pixel's center, except within 1/16 of a pixel of an
+    /// edge, where the rasterizer rounds the edge's position.
+    pub fn contains(&self, x: f32, y: f32, fill_mode: FillMode) -> bool {
+        measure::contains(self.flatten_as_drawn(), x, y, fill_mode)
+    }
+
+    /// Combines this path with `other` and returns the outline of the result. Curves are
+    /// flattened and every subpath is treated as closed, like when it is filled. The
+    /// result uses the winding fill mode.
+    pub fn boolean(&self, op: BooleanOp, other: &OutputPath) -> OutputPath {
+        boolean::boolean(self, op, other)
+    }
 }
 
 impl std::hash::Hash for OutputVertex {
@@ -95,8 +473,20 @@ pub struct PathBuilder {
     fill_mode: FillMode,
     outside_bounds: Option<CMILSurfaceRect>,
     need_inside: bool,
-    valid_range: bool,
+    // The first point that is NaN or outside of the 28.4 range
+    range_error: Option<RasterError>,
+    // The points as they were added, before the transform. They are used to convert the
+    // points again when the transform changes, and to clip paths that don't fit into the
+    // 28.4 range before they are rasterized.
+    source_points: DynArray<MilPoint2F>,
     rasterization_truncates: bool,
+    transform: Option<Transform>,
+    antialias_mode: AntialiasMode,
+    clip_path: Option<OutputPath>,
+    uv_transforms: [Option<Transform>; 2],
+    parallel_bands: usize,
+    band_pool: Option<Arc<dyn BandPool>>,
+    flattening: Flattening,
 }
 
 impl PathBuilder {
@@ -110,25 +500,56 @@ impl PathBuilder {
             fill_mode: FillMode::EvenOdd,
             outside_bounds: None,
             need_inside: true,
-            valid_range: true,
+            range_error: None,
+            source_points: Vec::new(),
             rasterization_truncates: false,
+            transform: None,
+            antialias_mode: AntialiasMode::default(),
+            clip_path: None,
+            uv_transforms: [None; 2],
+            parallel_bands: 1,
+            band_pool: None,
+            flattening: Flattening::default(),
         }
     }
+    /// Builds a path from SVG path data, i.e. the contents of a `d` attribute.
+    pub fn from_svg_path_data(data: &str) -> Result<Self, SvgPathError> {
+        svg_path::parse(data)
+    }
     fn reset(&mut self) {
         *self = Self {
             points: std::mem::take(&mut self.points),
             types: std::mem::take(&mut self.types),
+            source_points: std::mem::take(&mut self.source_points),
             ..Self::new()
         };
         self.points.clear();
         self.types.clear();
+        self.source_points.clear();
     }
     fn add_point(&mut self, x: f32, y: f32) {
-        self.current_point = Some(MilPoint2F{X: x, Y: y});
+        let point = MilPoint2F{X: x, Y: y};
+        self.current_point = Some(point);
+        self.source_points.push(point);
+        self.convert_point(point);
+    }
+    // Transforms point and appends it to the 28.4 points
+    fn convert_point(&mut self, point: MilPoint2F) {
+        let (x, y) = match self.transform {
+            Some(transform) => transform.transform_point_f64(point.X, point.Y),
+            None => (point.X as f64, point.Y as f64),
+        };
         // Transform from pixel corner at 0.0 to pixel center at 0.0. Scale into 28.4 range.
         // Validate that the point before rounding is within expected bounds for the rasterizer.
-        let (x, y) = ((x - 0.5) * 16.0, (y - 0.5) * 16.0);
-        self.valid_range = self.valid_range && CheckValidRange28_4(x, y);
+        let (x, y) = (((x - 0.5) * 16.0) as f32, ((y - 0.5) * 16.0) as f32);
+        let point_index = self.points.len();
+        if x.is_nan() || y.is_nan() {
+            if !matches!(self.range_error, Some(RasterError::NaN { .. })) {
+                self.range_error = Some(RasterError::NaN { point_index });
+            }
+        } else if !CheckValidRange28_4(x, y, c_nShift) && self.range_error.is_none() {
+            self.range_error = Some(RasterError::OutOfRange { point_index });
+        }
         self.points.push(POINT {
             x: CFloatFPU::Round(x),
             y: CFloatFPU::Round(y),
@@ -169,21 +590,177 @@ impl PathBuilder {
         self.add_point(x, y);
     }
     pub fn quad_to(&mut self, cx: f32, cy: f32, x: f32, y: f32) {
-        // For now we just implement quad_to on top of curve_to.
-        // Long term we probably want to support quad curves
-        // directly.
-        let c0 = match self.current_point {
-            Some(current_point) => current_point,
+        let initial_point = match self.initial_point {
+            Some(initial_point) => initial_point,
             None => MilPoint2F{X:cx, Y:cy}
         };
+        if !self.in_shape {
+            self.types.push(PathPointTypeStart);
+            self.add_point(initial_point.X, initial_point.Y);
+            self.initial_point = Some(initial_point);
+            self.in_shape = true;
+        }
+        self.types.push(PathPointTypeQuadratic);
+        self.add_point(cx, cy);
+        self.add_point(x, y);
+    }
+    // Appends cubic Beziers approximating an elliptical arc. The current point is expected to
+    // already be at the start of the arc. Angles are in radians and `rotation` is the angle of
+    // the x axis of the ellipse. Sweeps of more than a full turn are drawn as a full turn.
+    fn append_arc(&mut self, cx: f32, cy: f32, rx: f32, ry: f32, rotation: f32, start_angle: f32, sweep_angle: f32) {
+        if ![cx, cy, rx, ry, rotation, start_angle, sweep_angle].iter().all(|value| value.is_finite()) {
+            // There's no arc to approximate. Add a line to where the arc would end instead so
+            // that add_point records the NaN or out of range end point in range_error.
+            let end_angle = start_angle + sweep_angle;
+            self.line_to(cx + rx * end_angle.cos(), cy + ry * end_angle.sin());
+            return;
+        }
+        let sweep_angle = sweep_angle.clamp(-2. * std::f32::consts::PI, 2. * std::f32::consts::PI);
+
+        // The radial error of a cubic approximating a circular arc of angle a is
+        // r * 2/27 * sin(a/4)^6 / cos(a/4)^2. Use enough segments to keep it below
+        // ARC_TOLERANCE and never span more than a quarter turn.
+        const ARC_TOLERANCE: f32 = 0.01;
+        const MAX_ARC_SEGMENTS: f32 = 1024.;
+        let radius = rx.abs().max(ry.abs());
+        let error = |a: f32| radius * (2. / 27.) * (a / 4.).sin().powi(6) / (a / 4.).cos().powi(2);
+        let mut count = (sweep_angle.abs() / std::f32::consts::FRAC_PI_2).ceil().max(1.);
+        while count < MAX_ARC_SEGMENTS && error(sweep_angle.abs() / count) > ARC_TOLERANCE {
+            count += 1.;
+        }
+
+        let step = sweep_angle / count;
+        let k = 4. / 3. * (step / 4.).tan();
+        let (sin_rotation, cos_rotation) = rotation.sin_cos();
+        // Maps a point on the unit circle onto the ellipse
+        let map = |x: f32, y: f32| {
+            let (x, y) = (x * rx, y * ry);
+            (cx + x * cos_rotation - y * sin_rotation, cy + x * sin_rotation + y * cos_rotation)
+        };
+        let mut angle = start_angle;
+        for _ in 0..count as usize {
+            let (sin0, cos0) = angle.sin_cos();
+            angle += step;
+            let (sin1, cos1) = angle.sin_cos();
+            let (c1x, c1y) = map(cos0 - k * sin0, sin0 + k * cos0);
+            let (c2x, c2y) = map(cos1 + k * sin1, sin1 - k * cos1);
+            let (x, y) = map(cos1, sin1);
+            self.curve_to(c1x, c1y, c2x, c2y, x, y);
+        }
+    }
+    /// Adds a circular arc around (`cx`, `cy`). Angles are in radians and positive angles
+    /// go clockwise, from the positive x axis towards the positive y axis. If there is a
+    /// current point a line is added from it to the start of the arc. Sweeps of more than
+    /// a full turn are clamped to a full turn, like the canvas `arc()`. NaN or infinite
+    /// arguments make the path invalid.
+    pub fn arc(&mut self, cx: f32, cy: f32, radius: f32, start_angle: f32, sweep_angle: f32) {
+        let (sin, cos) = start_angle.sin_cos();
+        let (x, y) = (cx + radius * cos, cy + radius * sin);
+        match self.current_point {
+            Some(current_point) if current_point.X == x && current_point.Y == y => {}
+            Some(_) => self.line_to(x, y),
+            None => self.move_to(x, y),
+        }
+        self.append_arc(cx, cy, radius, radius, 0., start_angle, sweep_angle);
+    }
+    /// Adds an elliptical arc from the current point to (`x`, `y`) using the SVG endpoint
+    /// parameterization. `x_axis_rotation` is in degrees. Out of range radii are handled
+    /// the way SVG does: they are scaled up if they are too small to reach the end point
+    /// and a zero radius becomes a straight line.
+    pub fn arc_to(&mut self, rx: f32, ry: f32, x_axis_rotation: f32, large_arc: bool, sweep: bool, x: f32, y: f32) {
+        let start = match self.current_point {
+            Some(current_point) => current_point,
+            None => {
+                self.move_to(x, y);
+                return;
+            }
+        };
+        if start.X == x && start.Y == y {
+            return;
+        }
+        let (mut rx, mut ry) = (rx.abs(), ry.abs());
+        if rx == 0. || ry == 0. {
+            self.line_to(x, y);
+            return;
+        }
+
+        // Convert to the center parameterization. See "Conversion from endpoint to center
+        // parameterization" in the implementation notes of the SVG spec.
+        let rotation = x_axis_rotation.to_radians();
+        let (sin_rotation, cos_rotation) = rotation.sin_cos();
+        let (dx, dy) = ((start.X - x) / 2., (start.Y - y) / 2.);
+        let x1 = cos_rotation * dx + sin_rotation * dy;
+        let y1 = -sin_rotation * dx + cos_rotation * dy;
 
-        let c1x = c0.X + (2./3.) * (cx - c0.X);
-        let c1y = c0.Y + (2./3.) * (cy - c0.Y);
+        let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+        if lambda > 1. {
+            rx *= lambda.sqrt();
+            ry *= lambda.sqrt();
+        }
+        let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+        let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+        let mut coefficient = (numerator / denominator).max(0.).sqrt();
+        if large_arc == sweep {
+            coefficient = -coefficient;
+        }
+        let cx1 = coefficient * rx * y1 / ry;
+        let cy1 = -coefficient * ry * x1 / rx;
+        let cx = cos_rotation * cx1 - sin_rotation * cy1 + (start.X + x) / 2.;
+        let cy = sin_rotation * cx1 + cos_rotation * cy1 + (start.Y + y) / 2.;
 
-        let c2x = x + (2./3.) * (cx - x);
-        let c2y = y + (2./3.) * (cy - y);
+        let angle = |ux: f32, uy: f32| uy.atan2(ux);
+        let start_angle = angle((x1 - cx1) / rx, (y1 - cy1) / ry);
+        let end_angle = angle((-x1 - cx1) / rx, (-y1 - cy1) / ry);
+        let mut sweep_angle = end_angle - start_angle;
+        if sweep && sweep_angle < 0. {
+            sweep_angle += 2. * std::f32::consts::PI;
+        } else if !sweep && sweep_angle > 0. {
+            sweep_angle -= 2. * std::f32::consts::PI;
+        }
 
-        self.curve_to(c1x, c1y, c2x, c2y, x, y);
+        self.append_arc(cx, cy, rx, ry, rotation, start_angle, sweep_angle);
+        // Make sure we end exactly on the requested point
+        if let Some(current_point) = self.current_point {
+            if current_point.X != x || current_point.Y != y {
+                self.line_to(x, y);
+            }
+        }
+    }
+    /// Adds a closed ellipse as a new subpath.
+    pub fn add_ellipse(&mut self, cx: f32, cy: f32, rx: f32, ry: f32) {
+        self.move_to(cx + rx, cy);
+        self.append_arc(cx, cy, rx, ry, 0., 0., 2. * std::f32::consts::PI);
+        self.close();
+    }
+    /// Adds a closed circle as a new subpath.
+    pub fn add_circle(&mut self, cx: f32, cy: f32, radius: f32) {
+        self.add_ellipse(cx, cy, radius, radius);
+    }
+    /// Adds a closed rectangle with elliptical corners as a new subpath. The corner radii are
+    /// clamped to half of the width and height.
+    pub fn add_rounded_rect(&mut self, x: f32, y: f32, width: f32, height: f32, rx: f32, ry: f32) {
+        use std::f32::consts::{FRAC_PI_2, PI};
+        let rx = rx.max(0.).min(width / 2.);
+        let ry = ry.max(0.).min(height / 2.);
+        let (right, bottom) = (x + width, y + height);
+        self.move_to(x + rx, y);
+        self.line_to(right - rx, y);
+        if rx > 0. && ry > 0. {
+            self.append_arc(right - rx, y + ry, rx, ry, 0., -FRAC_PI_2, FRAC_PI_2);
+        }
+        self.line_to(right, bottom - ry);
+        if rx > 0. && ry > 0. {
+            self.append_arc(right - rx, bottom - ry, rx, ry, 0., 0., FRAC_PI_2);
+        }
+        self.line_to(x + rx, bottom);
+        if rx > 0. && ry > 0. {
+            self.append_arc(x + rx, bottom - ry, rx, ry, 0., FRAC_PI_2, FRAC_PI_2);
+        }
+        self.line_to(x, y + ry);
+        if rx > 0. && ry > 0. {
+            self.append_arc(x + rx, y + ry, rx, ry, 0., PI, FRAC_PI_2);
+        }
+        self.close();
     }
     pub fn close(&mut self) {
         if self.in_shape {
@@ -223,12 +800,170 @@ impl PathBuilder {
         self.rasterization_truncates = rasterization_truncates;
     }
 
+    /// Sets a transform that is applied to the points of the path, both the ones that were
+    /// already added and the ones that are added later. This allows a path to be built once
+    /// and drawn at many positions and scales. The points are transformed before they are
+    /// rounded to 28.4 fixed point, and `get_path` returns the transformed points.
+    pub fn set_transform(&mut self, transform: Option<Transform>) {
+        self.transform = transform;
+        self.points.clear();
+        self.range_error = None;
+        for i in 0..self.source_points.len() {
+            self.convert_point(self.source_points[i]);
+        }
+    }
+
+    /// Sets the subpixel grid used to compute coverage, or disables antialiasing.
+    /// The default is 8x8.
+    ///
+    /// Note: with a 16x16 grid the valid coordinate range is halved, so paths
+    /// with points beyond +/-2^18 pixels have to be clipped before they are rasterized.
+    pub fn set_antialias_mode(&mut self, antialias_mode: AntialiasMode) {
+        self.antialias_mode = antialias_mode;
+    }
+
+    /// Sets a path that the fill is clipped to in addition to the clip rect. The coverage
+    /// of the fill is multiplied with the coverage of the clip path, so nested clips don't
+    /// need a separate stencil pass. The clip path is in pixels and isn't transformed.
+    ///
+    /// Note: clipped paths are output as complex scans only, so they produce more
+    /// geometry than unclipped ones
+    pub fn set_clip_path(&mut self, clip_path: Option<OutputPath>) {
+        self.clip_path = clip_path;
+    }
+
+    /// Sets the transforms that map the position of each output vertex, in pixels, to its
+    /// two sets of texture coordinates, so that image fills and gradients can be sampled
+    /// without recomputing coordinates in the shader. The coordinates are passed to
+    /// `Vertex::with_uv` and are zero for a missing transform. Use `OutputVertexUV` or a
+    /// custom vertex with `rasterize_to_vertices` to get them.
+    pub fn set_uv_transforms(&mut self, uv0: Option<Transform>, uv1: Option<Transform>) {
+        self.uv_transforms = [uv0, uv1];
+    }
+
+    /// Rasterizes triangle lists in up to `bands` horizontal bands on the pool set with
+    /// `set_band_pool`. The output is the same as with a single band. Bands can only be
+    /// split at rows where the sweep over the path starts over, so small or simple paths
+    /// can end up with fewer bands. Paths with outside bounds are always rasterized on one
+    /// thread, and so are all paths if there is no pool.
+    pub fn set_parallel_bands(&mut self, bands: usize) {
+        self.parallel_bands = bands.max(1);
+    }
+
+    /// Sets the pool that `set_parallel_bands` runs the bands on. The path is flattened
+    /// once on the calling thread and the bands share its edges. `None` selects rayon's
+    /// global pool if the `rayon` feature is enabled and no pool otherwise.
+    pub fn set_band_pool(&mut self, pool: Option<Arc<dyn BandPool>>) {
+        self.band_pool = pool;
+    }
+
+    /// Sets the maximum distance in pixels between the curves of the path and the lines
+    /// they are flattened to. The default is 0.25. Larger tolerances give fewer edges,
+    /// which is faster, and smaller ones give smoother curves when zoomed in. The tolerance
+    /// is clamped to [1/64, 64] and NaN selects the default.
+    pub fn set_flattening_tolerance(&mut self, tolerance: f32) {
+        self.flattening.tolerance = tolerance;
+    }
+
+    /// Selects how curves are flattened, see `CurveFlattening`.
+    pub fn set_curve_flattening(&mut self, curve_flattening: CurveFlattening) {
+        self.flattening.curve_flattening = curve_flattening;
+    }
+
     /// Note: trapezoidal areas won't necessarily be clipped to the clip rect
     pub fn rasterize_to_tri_list(&self, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> Box<[OutputVertex]> {
-        if !self.valid_range {
-            // If any of the points are outside of valid 28.4 range, then just return an empty triangle list.
-            return Box::new([]);
+        // If the path can't be rasterized, then just return an empty triangle list.
+        self.try_rasterize_to_tri_list(clip_x, clip_y, clip_width, clip_height).unwrap_or_default()
+    }
+
+    /// Like `rasterize_to_tri_list`, but reports why no triangles were produced.
+    ///
+    /// `RasterError::EmptyFill` and `RasterError::ClippedToEmpty` are only returned when
+    /// the triangle list is empty, so a path that is clipped out can still produce the
+    /// geometry for its outside bounds.
+    ///
+    /// Paths with points outside of the rasterizer's 28.4 range, either as given or after
+    /// the transform is applied, are clipped to the clip rect in floating point first.
+    /// `RasterError::OutOfRange` is only returned if the clip rect itself is too large.
+    pub fn try_rasterize_to_tri_list(&self, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> Result<Box<[OutputVertex]>, RasterError> {
+        self.try_rasterize_to_vertices(clip_x, clip_y, clip_width, clip_height)
+    }
+
+    /// Like `rasterize_to_tri_list`, but the triangles share their vertices through an
+    /// index buffer, see `Mesh`.
+    pub fn rasterize_to_mesh(&self, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> Mesh {
+        self.try_rasterize_to_mesh(clip_x, clip_y, clip_width, clip_height).unwrap_or_default()
+    }
+
+    /// Like `try_rasterize_to_tri_list`, but returns an indexed mesh.
+    pub fn try_rasterize_to_mesh(&self, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> Result<Mesh, RasterError> {
+        self.try_rasterize_to_indexed_vertices(clip_x, clip_y, clip_width, clip_height)
+    }
+
+    /// Like `try_rasterize_to_mesh`, but the vertices are written in a custom vertex layout.
+    pub fn try_rasterize_to_indexed_vertices<V: Vertex>(&self, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> Result<Mesh<V>, RasterError> {
+        let mut vertex_buffer = CHwTVertexBuffer::new(self.rasterization_truncates, None);
+        vertex_buffer.share_vertices(Vec::new());
+        self.build_into(&mut Default::default(), clip_x, clip_y, clip_width, clip_height, &mut vertex_buffer)?;
+        Ok(Mesh::new(vertex_buffer.take_vertices(), vertex_buffer.take_indices()))
+    }
+
+    /// Like `rasterize_to_tri_list`, but the triangles are written in a custom vertex layout.
+    pub fn rasterize_to_vertices<V: Vertex>(&self, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> Box<[V]> {
+        self.try_rasterize_to_vertices(clip_x, clip_y, clip_width, clip_height).unwrap_or_default()
+    }
+
+    /// Like `try_rasterize_to_tri_list`, but the triangles are written in a custom vertex layout.
+    pub fn try_rasterize_to_vertices<V: Vertex>(&self, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> Result<Box<[V]>, RasterError> {
+        let mut vertices = Vec::new();
+        self.build_vertices(&mut Default::default(), clip_x, clip_y, clip_width, clip_height, &mut vertices)?;
+        Ok(vertices.into_boxed_slice())
+    }
+
+    /// Like `rasterize_to_tri_list`, but the triangles are split into the fully covered
+    /// interior and the fringe with partial coverage, see `OpaqueAndFringe`.
+    pub fn rasterize_to_opaque_and_fringe(&self, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> OpaqueAndFringe {
+        self.try_rasterize_to_opaque_and_fringe(clip_x, clip_y, clip_width, clip_height).unwrap_or_default()
+    }
+
+    /// Like `try_rasterize_to_tri_list`, but split like `rasterize_to_opaque_and_fringe`.
+    pub fn try_rasterize_to_opaque_and_fringe(&self, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> Result<OpaqueAndFringe, RasterError> {
+        let mut opaque = Vec::new();
+        let mut fringe = Vec::new();
+        self.build_split_vertices(&mut Default::default(), clip_x, clip_y, clip_width, clip_height, &mut fringe, Some(&mut opaque))?;
+        Ok(OpaqueAndFringe { opaque: opaque.into_boxed_slice(), fringe: fringe.into_boxed_slice() })
+    }
+
+    // Replaces the contents of vertices with the triangle list of the path, reusing its
+    // allocation. vertices is left empty if the path can't be rasterized.
+    pub(crate) fn build_vertices<V: Vertex>(&self, storage: &mut CRasterizerStorage, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32, vertices: &mut Vec<V>) -> Result<(), RasterError> {
+        self.build_split_vertices(storage, clip_x, clip_y, clip_width, clip_height, vertices, None)
+    }
+
+    // Like build_vertices, but if opaque is given the fully covered triangles are put
+    // there instead.
+    fn build_split_vertices<V: Vertex>(&self, storage: &mut CRasterizerStorage, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32, vertices: &mut Vec<V>, mut opaque: Option<&mut Vec<V>>) -> Result<(), RasterError> {
+        let mut vertex_buffer = CHwTVertexBuffer::with_storage(self.rasterization_truncates, std::mem::take(vertices));
+        if let Some(opaque) = &mut opaque {
+            vertex_buffer.split_opaque(std::mem::take(*opaque));
+        }
+        let result = self.build_into(storage, clip_x, clip_y, clip_width, clip_height, &mut vertex_buffer);
+        *vertices = vertex_buffer.take_vertices();
+        if result.is_err() {
+            vertices.clear();
+        }
+        if let Some(opaque) = opaque {
+            *opaque = vertex_buffer.take_opaque_vertices();
+            if result.is_err() {
+                opaque.clear();
+            }
         }
+        result
+    }
+
+    // Rasterizes the path into vertex_buffer, which is set up by the callers and can be
+    // left with partial output on failure.
+    fn build_into<V: Vertex>(&self, storage: &mut CRasterizerStorage, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32, vertex_buffer: &mut CHwTVertexBuffer<V>) -> Result<(), RasterError> {
         let (x, y, width, height, need_outside) = if let Some(CMILSurfaceRect { left, top, right, bottom }) = self.outside_bounds {
             let x0 = clip_x.max(left);
             let y0 = clip_y.max(top);
@@ -238,16 +973,107 @@ impl PathBuilder {
         } else {
             (clip_x, clip_y, clip_width, clip_height, false)
         };
-        rasterize_to_tri_list(self.fill_mode, &self.types, &self.points, x, y, width, height, self.need_inside, need_outside, self.rasterization_truncates, None)
-            .flush_output()
+        vertex_buffer.set_uv_transforms(self.uv_transforms);
+        let options = self.raster_options(x, y, width, height, self.clip_path.as_ref());
+        let mut result = match self.range_error {
+            Some(error) => Err(error),
+            None => build_tri_list(storage, vertex_buffer, &self.types, &self.points, &options, self.need_inside, need_outside),
+        };
+        if let Err(error @ RasterError::OutOfRange { .. }) = result {
+            result = match self.preclip(x, y, width, height) {
+                Some((path, clip_path)) => {
+                    // The preclipped path is relative to the clip rect, so its vertices are
+                    // moved back into place as they're output.
+                    vertex_buffer.set_position_transform(Transform::new(1., 0., 0., 1., x as f32, y as f32));
+                    let options = self.raster_options(0, 0, width, height, clip_path.as_ref());
+                    build_tri_list(storage, vertex_buffer, &path.types, &path.points, &options, self.need_inside, need_outside)
+                }
+                None => Err(error),
+            };
+        }
+        check_tri_list(vertex_buffer, result, None)
+    }
+
+    /// Rasterizes the path into `sink` instead of building a triangle list. Outside
+    /// bounds aren't supported by sinks and are ignored.
+    pub fn rasterize_to_sink(&self, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32, sink: &mut dyn GeometrySink) -> Result<(), RasterError> {
+        let result = match self.range_error {
+            Some(error) => Err(error),
+            None => {
+                let options = self.raster_options(clip_x, clip_y, clip_width, clip_height, self.clip_path.as_ref());
+                rasterize_path(&mut Default::default(), &mut SinkAdapter::new(sink), &self.types, &self.points, &options)
+            }
+        };
+        match result {
+            Err(error @ RasterError::OutOfRange { .. }) => {
+                let (path, clip_path) = self.preclip(clip_x, clip_y, clip_width, clip_height).ok_or(error)?;
+                let options = self.raster_options(0, 0, clip_width, clip_height, clip_path.as_ref());
+                let mut offset_sink = OffsetSink::new(sink, clip_x, clip_y);
+                rasterize_path(&mut Default::default(), &mut SinkAdapter::new(&mut offset_sink), &path.types, &path.points, &options)
+            }
+            result => result,
+        }
+    }
+
+    /// Rasterizes the path into an A8 coverage mask that covers the clip rect. Outside
+    /// bounds are ignored.
+    pub fn rasterize_to_mask(&self, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> Mask {
+        let mut mask = Mask::new(clip_x, clip_y, clip_width.max(0) as u32, clip_height.max(0) as u32);
+        // Errors leave the mask empty
+        let _ = self.rasterize_to_sink(clip_x, clip_y, clip_width, clip_height, &mut mask);
+        mask
+    }
+
+    // The settings that the path is rasterized with for the given clip rect
+    fn raster_options<'a>(&'a self, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32, clip_path: Option<&'a OutputPath>) -> RasterOptions<'a> {
+        RasterOptions {
+            clip_path,
+            flattening: self.flattening,
+            bands: self.parallel_bands,
+            band_pool: self.band_pool.as_deref().or(DEFAULT_BAND_POOL),
+            ..RasterOptions::new(self.fill_mode, None, self.antialias_mode, clip_x, clip_y, clip_width, clip_height)
+        }
+    }
+
+    // Returns the points converted to 28.4 without the transform, along with the first
+    // point that is NaN or out of range
+    pub(crate) fn untransformed_points(&self) -> (DynArray<POINT>, Option<RasterError>) {
+        let mut builder = PathBuilder::new();
+        for &point in &self.source_points {
+            builder.convert_point(point);
+        }
+        (builder.points, builder.range_error)
+    }
+
+    // Clips the path and the clip path to the clip rect and moves the clip rect's origin
+    // to (0, 0). This is used for paths that don't fit into the 28.4 range.
+    fn preclip(&self, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> Option<(OutputPath, Option<OutputPath>)> {
+        let nShift = CSubpixelGrid::FromAntiAliasMode(self.antialias_mode.to_mil()).nShift;
+        let preclip_path = |fill_mode, types: &[BYTE], points: &[MilPoint2F], transform| {
+            let (types, points) = preclip::preclip(types, points, transform, clip_x, clip_y, clip_width, clip_height, nShift)?;
+            Some(OutputPath { fill_mode, points: points.into_boxed_slice(), types: types.into_boxed_slice(), flattening: self.flattening })
+        };
+        let path = preclip_path(self.fill_mode, &self.types, &self.source_points, self.transform)?;
+        let clip_path = match &self.clip_path {
+            Some(clip_path) => {
+                let points: Vec<MilPoint2F> = clip_path.points.iter().map(|&point| {
+                    let (x, y) = flatten::to_pixel(point);
+                    MilPoint2F { X: x, Y: y }
+                }).collect();
+                Some(preclip_path(clip_path.fill_mode, &clip_path.types, &points, None)?)
+            }
+            None => None,
+        };
+        Some((path, clip_path))
     }
 
     pub fn get_path(&mut self) -> Option<OutputPath> {
-        if self.valid_range && !self.points.is_empty() && !self.types.is_empty() {
+        if self.range_error.is_none() && !self.points.is_empty() && !self.types.is_empty() {
             Some(OutputPath {
                 fill_mode: self.fill_mode,
                 points: Box::from(self.points.as_slice()),
                 types: Box::from(self.types.as_slice()),
+                flattening: self.flattening,
             })
         } else {
             None
@@ -274,50 +1100,272 @@ pub fn rasterize_to_tri_list<'a>(
     rasterization_truncates: bool,
     output_buffer: Option<&'a mut [OutputVertex]>,
 ) -> CHwVertexBuffer<'a> {
-    let clipRect = MilPointAndSizeL {
-        X: clip_x,
-        Y: clip_y,
-        Width: clip_width,
-        Height: clip_height,
+    let options = RasterizeOptions {
+        fill_mode,
+        need_inside,
+        need_outside,
+        rasterization_truncates,
+        ..RasterizeOptions::new(clip_x, clip_y, clip_width, clip_height)
     };
+    rasterize_to_tri_list_with_options(types, points, &options, output_buffer)
+}
 
-    let mil_fill_mode = match fill_mode {
-        FillMode::EvenOdd => MilFillMode::Alternate,
-        FillMode::Winding => MilFillMode::Winding,
-    };
+// Same as rasterize_to_tri_list, but with all of the settings in RasterizeOptions, e.g. a
+// transform or the antialias mode.
+pub fn rasterize_to_tri_list_with_options<'a>(
+    types: &[BYTE],
+    points: &[POINT],
+    options: &RasterizeOptions,
+    output_buffer: Option<&'a mut [OutputVertex]>,
+) -> CHwVertexBuffer<'a> {
+    // Failures just leave the vertex buffer empty
+    let (vertex_buffer, _) = build_tri_list_with_options(types, points, options, output_buffer);
+    vertex_buffer
+}
 
-    let m_mvfIn: MilVertexFormat = MilVertexFormatAttribute::MILVFAttrXY as MilVertexFormat;
-    let m_mvfGenerated: MilVertexFormat  = MilVertexFormatAttribute::MILVFAttrNone as MilVertexFormat;
-    //let mvfaAALocation  = MILVFAttrNone;
-    const HWPIPELINE_ANTIALIAS_LOCATION: MilVertexFormatAttribute = MilVertexFormatAttribute::MILVFAttrDiffuse;
-    let mvfaAALocation = HWPIPELINE_ANTIALIAS_LOCATION;
+// Same as rasterize_to_tri_list_with_options, but reports why the path couldn't be
+// rasterized. EmptyFill and ClippedToEmpty are only reported when no triangles were
+// produced. If output_buffer can't hold the triangles, OutputBufferTooSmall gives the
+// number of vertices that are needed.
+pub fn try_rasterize_to_tri_list<'a>(
+    types: &[BYTE],
+    points: &[POINT],
+    options: &RasterizeOptions,
+    output_buffer: Option<&'a mut [OutputVertex]>,
+) -> Result<CHwVertexBuffer<'a>, RasterError> {
+    try_rasterize_to_vertices(types, points, options, output_buffer)
+}
+
+// Same as try_rasterize_to_tri_list, but the triangles are written in the vertex layout V
+// so that they can be copied straight into a vertex buffer.
+pub fn try_rasterize_to_vertices<'a, V: Vertex>(
+    types: &[BYTE],
+    points: &[POINT],
+    options: &RasterizeOptions,
+    output_buffer: Option<&'a mut [V]>,
+) -> Result<CHwTVertexBuffer<'a, V>, RasterError> {
+    let capacity = output_buffer.as_ref().map(|buffer| buffer.len());
+    let (vertex_buffer, result) = build_tri_list_with_options(types, points, options, output_buffer);
+    check_tri_list(&vertex_buffer, result, capacity)?;
+    Ok(vertex_buffer)
+}
+
+// Like try_rasterize_to_vertices, but the triangles share their vertices through the
+// indices of the mesh.
+pub fn try_rasterize_to_indexed_vertices<V: Vertex>(
+    types: &[BYTE],
+    points: &[POINT],
+    options: &RasterizeOptions,
+) -> Result<Mesh<V>, RasterError> {
+    let mut vertex_buffer = CHwTVertexBuffer::new(options.rasterization_truncates, None);
+    vertex_buffer.share_vertices(Vec::new());
+    let result = build_tri_list(&mut Default::default(), &mut vertex_buffer, types, points, &options.raster_options(),
+                                options.need_inside, options.need_outside);
+    check_tri_list(&vertex_buffer, result, None)?;
+    Ok(Mesh::new(vertex_buffer.take_vertices(), vertex_buffer.take_indices()))
+}
+
+// Builds the triangles of the free rasterization functions, which either drop the result or
+// check it with check_tri_list.
+fn build_tri_list_with_options<'a, V: Vertex>(
+    types: &[BYTE],
+    points: &[POINT],
+    options: &RasterizeOptions,
+    output_buffer: Option<&'a mut [V]>,
+) -> (CHwTVertexBuffer<'a, V>, Result<(), RasterError>) {
+    let mut vertex_buffer = CHwTVertexBuffer::new(options.rasterization_truncates, output_buffer);
+    let result = build_tri_list(&mut Default::default(), &mut vertex_buffer, types, points, &options.raster_options(),
+                                options.need_inside, options.need_outside);
+    (vertex_buffer, result)
+}
+
+// Turns the result of build_tri_list into the result of try_rasterize_to_tri_list.
+// capacity is the size of the output buffer, if there is one.
+fn check_tri_list<V: Vertex>(vertex_buffer: &CHwTVertexBuffer<V>, result: Result<(), RasterError>, capacity: Option<usize>) -> Result<(), RasterError> {
+    match result {
+        Err(RasterError::EmptyFill | RasterError::ClippedToEmpty) if !vertex_buffer.IsEmpty() => {}
+        Err(error) => return Err(error),
+        Ok(()) => {}
+    }
+    if let (Some(capacity), Some(required)) = (capacity, vertex_buffer.get_output_buffer_size()) {
+        if required > capacity {
+            return Err(RasterError::OutputBufferTooSmall { required });
+        }
+    }
+    Ok(())
+}
+
+fn build_tri_list<V: Vertex>(
+    storage: &mut CRasterizerStorage,
+    vertexBuffer: &mut CHwTVertexBuffer<V>,
+    types: &[BYTE],
+    points: &[POINT],
+    options: &RasterOptions,
+    need_inside: bool,
+    need_outside: bool,
+) -> Result<(), RasterError> {
+    // The outside geometry is built from the gaps between all of the geometry, so it
+    // can't be split into bands
+    if let (true, false, Some(pool)) = (options.bands > 1, need_outside, options.band_pool) {
+        if let Some(result) = rasterize_path_in_bands(storage, vertexBuffer, types, points, options, pool, need_inside) {
+            return result;
+        }
+    }
 
     let outside_bounds = if need_outside {
+        let clipRect = &options.clip_rect;
         Some(CMILSurfaceRect {
-            left: clip_x,
-            top: clip_y,
-            right: clip_x + clip_width,
-            bottom: clip_y + clip_height,
+            left: clipRect.X,
+            top: clipRect.Y,
+            right: clipRect.X + clipRect.Width,
+            bottom: clipRect.Y + clipRect.Height,
         })
     } else {
         None
     };
 
-    let mut vertexBuffer = CHwVertexBuffer::new(rasterization_truncates, output_buffer);
-    {
-        let mut vertexBuilder = CHwVertexBufferBuilder::Create(
-            m_mvfIn, m_mvfIn | m_mvfGenerated, mvfaAALocation, &mut vertexBuffer);
-        vertexBuilder.SetOutsideBounds(outside_bounds.as_ref(), need_inside);
+    let mut vertexBuilder = create_vertex_builder(vertexBuffer, outside_bounds.as_ref(), need_inside);
+    vertexBuilder.BeginBuilding();
+    let result = rasterize_path(storage, &mut vertexBuilder, types, points, options);
+    vertexBuilder.EndBuilding();
+    result
+}
+
+fn create_vertex_builder<'y, 'z, V: Vertex>(
+    vertexBuffer: &'y mut CHwTVertexBuffer<'z, V>,
+    outside_bounds: Option<&CMILSurfaceRect>,
+    need_inside: bool,
+) -> CHwTVertexBufferBuilder<'y, 'z, V> {
+    let m_mvfIn: MilVertexFormat = MilVertexFormatAttribute::MILVFAttrXY as MilVertexFormat;
+    let m_mvfGenerated: MilVertexFormat  = MilVertexFormatAttribute::MILVFAttrNone as MilVertexFormat;
+    //let mvfaAALocation  = MILVFAttrNone;
+    const HWPIPELINE_ANTIALIAS_LOCATION: MilVertexFormatAttribute = MilVertexFormatAttribute::MILVFAttrDiffuse;
+    let mvfaAALocation = HWPIPELINE_ANTIALIAS_LOCATION;
+
+    let mut vertexBuilder = CHwTVertexBufferBuilder::Create(
+        m_mvfIn, m_mvfIn | m_mvfGenerated, mvfaAALocation, vertexBuffer);
+    vertexBuilder.SetOutsideBounds(outside_bounds, need_inside);
+    vertexBuilder
+}
+
+// Like rasterize_path, but the rows of the path are split into bands that are rasterized
+// on pool and then appended to vertexBuffer in order. The path is flattened once and the
+// bands share its edges. The result is the same as rasterizing the path in one go.
+// Returns None if the path can't be split, in which case nothing was output.
+fn rasterize_path_in_bands<V: Vertex>(
+    storage: &mut CRasterizerStorage,
+    vertexBuffer: &mut CHwTVertexBuffer<V>,
+    types: &[BYTE],
+    points: &[POINT],
+    options: &RasterOptions,
+    pool: &dyn BandPool,
+    need_inside: bool,
+) -> Option<Result<(), RasterError>> {
+    error::validate_path(types, points.len()).ok()?;
+
+    // Planning doesn't output anything. Errors are left to the serial rasterizer to report.
+    let mut planning_sink = CNullGeometrySink;
+    let mut planner = options.fill_rasterizer(&mut planning_sink);
+    planner.SetStorage(std::mem::take(storage));
+    let plan = planner.PlanBands(points, types, options.bands as u32);
+    *storage = planner.TakeStorage();
+    let plan = plan.ok().filter(|plan| plan.GetBandCount() > 1)?;
+
+    let clip_coverage = match rasterize_clip_path(storage, options) {
+        Ok(clip_coverage) => clip_coverage,
+        Err(error) => return Some(Err(error)),
+    };
+
+    let bands: Vec<_> = (0..plan.GetBandCount())
+        .map(|_| Mutex::new((vertexBuffer.new_band(), Ok(()))))
+        .collect();
+    pool.run_bands(bands.len(), &|iBand| {
+        let mut band = bands[iBand].lock().unwrap();
+        let (bandBuffer, result) = &mut *band;
+        let mut vertexBuilder = create_vertex_builder(bandBuffer, None, need_inside);
         vertexBuilder.BeginBuilding();
-        {
-            let mut rasterizer = CHwRasterizer::new(
-                &mut vertexBuilder, mil_fill_mode, None, clipRect);
-            rasterizer.SendGeometry(points, types);
+        let mut rasterizer = options.fill_rasterizer(&mut vertexBuilder);
+        if let Some(clip_coverage) = &clip_coverage {
+            rasterizer.SetClipCoverage(clip_coverage);
         }
+        let hr = rasterizer.RasterizeBand(&plan, iBand);
+        *result = error::check_hresult(hr, rasterizer.GetInvalidPoint());
         vertexBuilder.EndBuilding();
+    });
+
+    for band in bands {
+        let (bandBuffer, result) = band.into_inner().unwrap();
+        match result {
+            // Only some of the bands need to have geometry
+            Ok(()) | Err(RasterError::EmptyFill) => vertexBuffer.append_band(bandBuffer),
+            Err(error) => return Some(Err(error)),
+        }
     }
+    if vertexBuffer.IsEmpty() {
+        return Some(Err(RasterError::EmptyFill));
+    }
+    Some(Ok(()))
+}
 
-    vertexBuffer
+// Like rasterize_to_tri_list_with_options, but the trapezoids and complex scans are
+// passed to sink instead of being turned into triangles. need_inside, need_outside and
+// rasterization_truncates only apply to triangles and are ignored.
+pub fn rasterize_to_sink(
+    types: &[BYTE],
+    points: &[POINT],
+    options: &RasterizeOptions,
+    sink: &mut dyn GeometrySink,
+) -> Result<(), RasterError> {
+    rasterize_path(&mut Default::default(), &mut SinkAdapter::new(sink), types, points, &options.raster_options())
+}
+
+fn rasterize_path(
+    storage: &mut CRasterizerStorage,
+    sink: &mut dyn IGeometrySink,
+    types: &[BYTE],
+    points: &[POINT],
+    options: &RasterOptions,
+) -> Result<(), RasterError> {
+    error::validate_path(types, points.len())?;
+
+    // Rasterize the clip path first so that its coverage can be multiplied with the fill's
+    let clip_coverage = rasterize_clip_path(storage, options)?;
+
+    let mut rasterizer = options.fill_rasterizer(sink);
+    if let Some(clip_coverage) = &clip_coverage {
+        rasterizer.SetClipCoverage(clip_coverage);
+    }
+    rasterizer.SetStorage(std::mem::take(storage));
+    let hr = rasterizer.SendGeometry(points, types);
+    *storage = rasterizer.TakeStorage();
+    error::check_hresult(hr, rasterizer.GetInvalidPoint())
+}
+
+fn rasterize_clip_path(
+    storage: &mut CRasterizerStorage,
+    options: &RasterOptions,
+) -> Result<Option<CClipCoverage>, RasterError> {
+    let Some(clip_path) = options.clip_path else {
+        return Ok(None);
+    };
+    error::validate_path(&clip_path.types, clip_path.points.len())?;
+    let mut clip_coverage = CClipCoverage::new();
+    let mut rasterizer = CHwRasterizer::new(
+        &mut clip_coverage, clip_path.fill_mode.to_mil(), None, options.clip_rect.clone());
+    rasterizer.SetAntiAliasMode(options.antialias_mode.to_mil());
+    rasterizer.DisableTrapezoids();
+    // Clip coverage is only output as complex scans, so aligning curves to scanlines
+    // wouldn't help
+    rasterizer.SetBezierTolerance(options.flattening.tolerance);
+    rasterizer.SetStorage(std::mem::take(storage));
+    let hr = rasterizer.SendGeometry(&clip_path.points, &clip_path.types);
+    *storage = rasterizer.TakeStorage();
+    match error::check_hresult(hr, rasterizer.GetInvalidPoint()) {
+        // An empty clip leaves nothing of the fill, which is handled by the fill's
+        // rasterizer like any other empty scan
+        Ok(()) | Err(RasterError::EmptyFill | RasterError::ClippedToEmpty) => Ok(Some(clip_coverage)),
+        Err(error) => Err(error),
+    }
 }
 
 #[cfg(test)]
@@ -679,8 +1727,19 @@ mod tests {
         p.quad_to(60., 30., 60., 10.);
         p.close();
         let result = p.rasterize_to_tri_list(0, 0, 70, 40);
-        assert_eq!(result.len(), 279);
-        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 70, 40)), 0xbd2eec3cfe9bd30b);
+        assert_eq!(result.len(), 258);
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 70, 40)), 0x253aef299acb21d7);
+
+        // quadratics are stored and flattened natively, which takes fewer points and
+        // segments than the equivalent cubics
+        let mut cubic = PathBuilder::new();
+        cubic.move_to(10., 10.0);
+        cubic.curve_to(10. + 20. * 2. / 3., 10., 30., 30. - 20. * 2. / 3., 30., 30.);
+        cubic.curve_to(30. - 20. * 2. / 3., 30., 30. - 20. * 2. / 3., 30., 30., 30.);
+        cubic.curve_to(30. + 30. * 2. / 3., 30., 60., 10. + 20. * 2. / 3., 60., 10.);
+        cubic.close();
+        assert!(p.get_path().unwrap().points.len() < cubic.get_path().unwrap().points.len());
+        assert!(result.len() < cubic.rasterize_to_tri_list(0, 0, 70, 40).len());
     }
 
     #[test]
@@ -697,4 +1756,1419 @@ mod tests {
         assert_eq!(result.len(), 27);
         assert_eq!(dbg!(calculate_hash(&result)), 0xecfdf5bdfa25a1dd);
     }
+
+    #[test]
+    fn transform() {
+        let mut p = PathBuilder::new();
+        p.move_to(10., 10.);
+        p.line_to(40., 10.);
+        p.line_to(10., 40.);
+        p.line_to(40., 40.);
+        p.close();
+        let expected = p.rasterize_to_tri_list(0, 0, 100, 100);
+
+        // an identity transform shouldn't change the output
+        p.set_transform(Some(Transform::default()));
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        assert_eq!(calculate_hash(&result), calculate_hash(&expected));
+
+        let mut p = PathBuilder::new();
+        p.move_to(0., 0.);
+        p.line_to(15., 0.);
+        p.line_to(0., 15.);
+        p.line_to(15., 15.);
+        p.close();
+        let path = p.get_path().unwrap();
+        p.set_transform(Some(Transform::new(2., 0., 0., 2., 10., 10.)));
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 100, 100)),
+                   calculate_hash(&rasterize_to_mask(&expected, 100, 100)));
+
+        // the free functions take the transform through RasterizeOptions
+        let options = RasterizeOptions {
+            fill_mode: path.fill_mode(),
+            transform: Some(Transform::new(2., 0., 0., 2., 10., 10.)),
+            ..RasterizeOptions::new(0, 0, 100, 100)
+        };
+        let result = rasterize_to_tri_list_with_options(path.types(), path.points(), &options, None).flush_output();
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 100, 100)),
+                   calculate_hash(&rasterize_to_mask(&expected, 100, 100)));
+
+        // the points are transformed before they are rounded to 28.4, so small paths can be
+        // scaled up without magnifying the rounding, and the transform can be set before or
+        // after the points are added
+        let mut small = PathBuilder::new();
+        small.set_transform(Some(Transform::new(200., 0., 0., 200., 10., 10.)));
+        small.move_to(0., 0.);
+        small.line_to(0.15, 0.);
+        small.line_to(0., 0.15);
+        small.line_to(0.15, 0.15);
+        small.close();
+        assert_eq!(calculate_hash(&small.rasterize_to_tri_list(0, 0, 100, 100)), calculate_hash(&expected));
+        let small_path = small.get_path().unwrap();
+        small.set_transform(None);
+        small.set_transform(Some(Transform::new(200., 0., 0., 200., 10., 10.)));
+        assert!(small.get_path().unwrap().points() == small_path.points());
+        assert!(small_path.points() == p.get_path().unwrap().points());
+
+        // points that are moved out of range are clipped before rasterizing. Near the
+        // origin only the triangle between the top edge and the closing edge is visible.
+        p.set_transform(Some(Transform::new(1e9, 0., 0., 1e9, 0., 0.)));
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        let mut visible = PathBuilder::new();
+        visible.move_to(0., 0.);
+        visible.line_to(200., 0.);
+        visible.line_to(200., 200.);
+        visible.close();
+        let expected = visible.rasterize_to_tri_list(0, 0, 100, 100);
+        assert!(max_difference(&rasterize_to_mask(&result, 100, 100), &rasterize_to_mask(&expected, 100, 100)) <= 1);
+    }
+
+    #[test]
+    fn antialias_modes() {
+        let mut p = PathBuilder::new();
+        p.add_circle(50., 50., 40.);
+        let expected = p.rasterize_to_tri_list(0, 0, 100, 100);
+        p.set_antialias_mode(AntialiasMode::EightByEight);
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        assert_eq!(calculate_hash(&result), calculate_hash(&expected));
+
+        let area = std::f32::consts::PI * 40. * 40.;
+        p.set_antialias_mode(AntialiasMode::FourByFour);
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        assert!(mask_area_error(&result, 100, 100, area) < 0.005);
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 100, 100)), 0xefbee25fc60e8368);
+        p.set_antialias_mode(AntialiasMode::SixteenBySixteen);
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        assert!(mask_area_error(&result, 100, 100, area) < 0.005);
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 100, 100)), 0xf543f9a28e3a016c);
+
+        // edges on quarter pixels are exact for every grid
+        for mode in [AntialiasMode::FourByFour, AntialiasMode::EightByEight, AntialiasMode::SixteenBySixteen] {
+            let mut p = PathBuilder::new();
+            fill_rect(&mut p, 10.25, 10.25, 20.75, 30.5);
+            p.set_antialias_mode(mode);
+            let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+            assert!(mask_area_error(&result, 100, 100, 10.5 * 20.25) < 0.001);
+        }
+
+        // the finer grid has a smaller valid range, so the path is clipped first
+        let mut p = PathBuilder::new();
+        p.move_to(10., 10.);
+        p.line_to(300000., 10.);
+        p.line_to(10., 40.);
+        p.close();
+        let expected = rasterize_to_mask(&p.rasterize_to_tri_list(0, 0, 100, 100), 100, 100);
+        p.set_antialias_mode(AntialiasMode::SixteenBySixteen);
+        let result = rasterize_to_mask(&p.rasterize_to_tri_list(0, 0, 100, 100), 100, 100);
+        assert!(max_difference(&result, &expected) <= 8);
+    }
+
+    // Even-odd point in polygon test. Returns None if the point is too close to an
+    // edge for the result to survive the snapping of the test rasterizer.
+    fn polygon_contains(polygon: &[(f32, f32)], x: f32, y: f32) -> Option<bool> {
+        let mut inside = false;
+        for (i, &(x0, y0)) in polygon.iter().enumerate() {
+            let (x1, y1) = polygon[(i + 1) % polygon.len()];
+            if (y0 > y) != (y1 > y) {
+                let edge_x = x0 + (y - y0) * (x1 - x0) / (y1 - y0);
+                if (x - edge_x).abs() < 1. / 64. {
+                    return None;
+                }
+                if x < edge_x {
+                    inside = !inside;
+                }
+            }
+        }
+        Some(inside)
+    }
+
+    #[test]
+    fn aliased() {
+        // a triangle is output as trapezoids and the overlapping part of the
+        // self-intersecting shape as complex scans
+        let polygons: [&[(f32, f32)]; 2] = [
+            &[(12.0625, 8.1875), (87.3125, 30.4375), (40.8125, 91.6875)],
+            &[(10.1875, 10.3125), (90.0625, 20.8125), (20.4375, 90.1875), (80.3125, 85.0625), (50.6875, 5.1875)],
+        ];
+        for polygon in polygons {
+            let mut p = PathBuilder::new();
+            p.move_to(polygon[0].0, polygon[0].1);
+            for &(x, y) in &polygon[1..] {
+                p.line_to(x, y);
+            }
+            p.close();
+            p.set_antialias_mode(AntialiasMode::Aliased);
+            let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+            assert!(result.iter().all(|v| v.coverage == 1.));
+            let mask = rasterize_to_mask(&result, 100, 100);
+            for y in 0..100 {
+                for x in 0..100 {
+                    if let Some(expected) = polygon_contains(polygon, x as f32 + 0.5, y as f32 + 0.5) {
+                        assert_eq!(mask[y * 100 + x], if expected { 255 } else { 0 }, "pixel {}, {}", x, y);
+                    }
+                }
+            }
+        }
+
+        // outside geometry has no falloff either
+        let mut p = PathBuilder::new();
+        p.add_circle(50.3, 50.7, 40.);
+        p.set_antialias_mode(AntialiasMode::Aliased);
+        p.set_outside_bounds(Some((0, 0, 100, 100)), true);
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        assert!(result.iter().all(|v| v.coverage == 0. || v.coverage == 1.));
+        assert!(rasterize_to_mask(&result, 100, 100).iter().all(|&c| c == 0 || c == 255));
+    }
+
+    #[derive(Default)]
+    struct AreaSink {
+        area: f32,
+        trapezoids: usize,
+        scans: usize,
+    }
+
+    impl GeometrySink for AreaSink {
+        fn add_trapezoid(&mut self, t: &Trapezoid) {
+            assert!(t.y_bottom > t.y_top);
+            let top_width = t.x_top_right - t.x_top_left;
+            let bottom_width = t.x_bottom_right - t.x_bottom_left;
+            self.area += (top_width + bottom_width) / 2. * (t.y_bottom - t.y_top);
+            self.trapezoids += 1;
+        }
+        fn add_complex_scan(&mut self, _y: i32, spans: &[CoverageSpan]) {
+            for (i, span) in spans.iter().enumerate() {
+                assert!(span.x0 < span.x1);
+                assert!(i == 0 || spans[i - 1].x1 <= span.x0);
+                assert!(span.coverage > 0. && span.coverage <= 1.);
+                self.area += (span.x1 - span.x0) as f32 * span.coverage;
+            }
+            self.scans += 1;
+        }
+    }
+
+    #[test]
+    fn sink() {
+        let mut p = PathBuilder::new();
+        p.add_circle(50., 50., 40.);
+        let mut sink = AreaSink::default();
+        p.rasterize_to_sink(0, 0, 100, 100, &mut sink).unwrap();
+        assert!(sink.trapezoids > 0 && sink.scans > 0);
+        let area = std::f32::consts::PI * 40. * 40.;
+        assert!((sink.area - area).abs() / area < 0.005);
+
+        // the sink sees the same coverage as the triangles
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        assert!(mask_area_error(&result, 100, 100, sink.area) < 0.001);
+
+        // and so does the free function
+        let path = p.get_path().unwrap();
+        let mut free_sink = AreaSink::default();
+        rasterize_to_sink(path.types(), path.points(), &RasterizeOptions::new(0, 0, 100, 100), &mut free_sink).unwrap();
+        assert_eq!((free_sink.area, free_sink.trapezoids, free_sink.scans), (sink.area, sink.trapezoids, sink.scans));
+
+        let mut p = PathBuilder::new();
+        p.move_to(10., 10.);
+        p.line_to(40., 40.);
+        let mut sink = AreaSink::default();
+        assert_eq!(p.rasterize_to_sink(0, 0, 100, 100, &mut sink), Err(RasterError::EmptyFill));
+        assert_eq!((sink.trapezoids, sink.scans), (0, 0));
+    }
+
+    #[test]
+    fn flatten_path() {
+        let mut p = PathBuilder::new();
+        p.add_circle(50.3, 50.7, 40.);
+        p.move_to(10., 10.);
+        p.line_to(20., 10.);
+        p.quad_to(30., 10., 30., 20.);
+        let path = p.get_path().unwrap();
+
+        let subpaths: Vec<_> = path.flatten(0.25).collect();
+        assert_eq!(subpaths.len(), 2);
+        assert!(subpaths[0].is_closed() && !subpaths[1].is_closed());
+        for (x, y) in subpaths[0].pixel_points() {
+            let distance = ((x - 50.3).powi(2) + (y - 50.7).powi(2)).sqrt();
+            assert!((distance - 40.).abs() <= 0.25 + 1. / 16.);
+        }
+        let open: Vec<_> = subpaths[1].pixel_points().collect();
+        assert_eq!(open[..2], [(10., 10.), (20., 10.)]);
+        assert_eq!(open.last(), Some(&(30., 20.)));
+
+        // the rasterizer sees the same polylines
+        let mut polygon = PathBuilder::new();
+        for subpath in &subpaths {
+            let mut points = subpath.pixel_points();
+            let (x, y) = points.next().unwrap();
+            polygon.move_to(x, y);
+            for (x, y) in points {
+                polygon.line_to(x, y);
+            }
+            polygon.close();
+        }
+        assert_eq!(calculate_hash(&polygon.rasterize_to_tri_list(0, 0, 100, 100)), calculate_hash(&p.rasterize_to_tri_list(0, 0, 100, 100)));
+
+        assert!(path.flatten(4.).next().unwrap().points().len() < subpaths[0].points().len());
+        assert_eq!(OutputPath::default().flatten(0.25).count(), 0);
+    }
+
+    #[test]
+    fn path_queries() {
+        let mut p = PathBuilder::new();
+        p.move_to(10., 20.);
+        p.line_to(40., 20.);
+        p.line_to(40., 60.);
+        p.line_to(10., 60.);
+        p.close();
+        let rect = p.get_path().unwrap();
+        let bounds = rect.bounds().unwrap();
+        assert_eq!(bounds, Bounds { min_x: 10., min_y: 20., max_x: 40., max_y: 60. });
+        assert_eq!((bounds.width(), bounds.height()), (30., 40.));
+        assert_eq!(rect.area(), 1200.);
+        assert_eq!(rect.subpath_perimeters(), [140.]);
+        // top and left edges are inside, bottom and right edges are outside
+        assert!(rect.contains(10., 20., FillMode::EvenOdd) && rect.contains(25., 20., FillMode::EvenOdd));
+        assert!(!rect.contains(40., 30., FillMode::EvenOdd) && !rect.contains(25., 60., FillMode::EvenOdd));
+        assert!(!rect.contains(9.9, 30., FillMode::EvenOdd));
+
+        let mut p = PathBuilder::new();
+        p.add_circle(50.3, 50.7, 40.);
+        p.move_to(10., 10.);
+        p.line_to(20., 10.);
+        p.quad_to(30., 10., 30., 20.);
+        let circle = p.get_path().unwrap();
+        let bounds = circle.bounds().unwrap();
+        let control_bounds = circle.control_bounds().unwrap();
+        assert!((bounds.min_x - 10.).abs() < 0.1 && (bounds.max_x - 90.3).abs() < 0.1);
+        assert!((bounds.min_y - 10.).abs() < 0.1 && (bounds.max_y - 90.7).abs() < 0.1);
+        assert!(control_bounds.min_x <= bounds.min_x && control_bounds.max_x >= bounds.max_x);
+        assert!(control_bounds.min_y <= bounds.min_y && control_bounds.max_y >= bounds.max_y);
+        let perimeters = circle.subpath_perimeters();
+        assert_eq!(perimeters.len(), 2);
+        assert!((perimeters[0] - 2. * std::f32::consts::PI * 40.).abs() < 1.);
+        assert!(perimeters[1] > 10. + 10. * std::f32::consts::SQRT_2 && perimeters[1] < 30.);
+        // the open subpath is closed for the area, which adds its triangle and the 2/3 of the
+        // control triangle that the curve encloses
+        let exact_area = std::f32::consts::PI * 40. * 40. + 50. + 100. / 3.;
+        assert!((circle.area() - exact_area).abs() < perimeters[0] * 0.25);
+
+        // reversing a path negates its area
+        let mut p = PathBuilder::new();
+        p.move_to(10., 20.);
+        p.line_to(10., 60.);
+        p.line_to(40., 60.);
+        p.line_to(40., 20.);
+        assert_eq!(p.get_path().unwrap().area(), -1200.);
+
+        // a pentagram whose center is only filled with the winding rule, with its
+        // vertices and edges between pixel centers and on them
+        let mut p = PathBuilder::new();
+        p.move_to(50.5, 5.5);
+        p.line_to(77.5, 90.5);
+        p.line_to(5., 37.5);
+        p.line_to(95.5, 37.5);
+        p.line_to(23., 90.);
+        p.close();
+        p.add_rounded_rect(60.5, 60., 30., 30.5, 8., 5.);
+        let star = p.get_path().unwrap();
+        assert!(star.contains(50., 50., FillMode::Winding) && !star.contains(50., 50., FillMode::EvenOdd));
+
+        // contains agrees with aliased rasterization at every pixel center, except within the
+        // 1/16 of a pixel that the rasterizer rounds edge positions to
+        for (path, fill_mode) in [(&rect, FillMode::EvenOdd), (&circle, FillMode::Winding), (&star, FillMode::EvenOdd), (&star, FillMode::Winding)] {
+            let mut p = PathBuilder::new();
+            p.set_fill_mode(fill_mode);
+            p.set_antialias_mode(AntialiasMode::Aliased);
+            for subpath in path.flatten(BEZIER_DEFAULT_TOLERANCE) {
+                let mut points = subpath.pixel_points();
+                let (x, y) = points.next().unwrap();
+                p.move_to(x, y);
+                for (x, y) in points {
+                    p.line_to(x, y);
+                }
+                p.close();
+            }
+            let mask = p.rasterize_to_mask(0, 0, 100, 100);
+            let mut rounded = 0;
+            for y in 0..100 {
+                for x in 0..100 {
+                    let (center_x, center_y) = (x as f32 + 0.5, y as f32 + 0.5);
+                    let inside = path.contains(center_x, center_y, fill_mode);
+                    if inside != (mask.data[y * 100 + x] != 0) {
+                        let near_edge = path.contains(center_x - 1. / 16., center_y, fill_mode) != inside ||
+                                        path.contains(center_x + 1. / 16., center_y, fill_mode) != inside;
+                        assert!(near_edge, "{} {}", x, y);
+                        rounded += 1;
+                    }
+                }
+            }
+            assert!(rounded <= 2);
+        }
+
+        // the queries flatten the curves with the settings of the builder
+        let mut p = PathBuilder::new();
+        p.add_circle(50., 50., 40.);
+        p.set_flattening_tolerance(4.);
+        p.set_curve_flattening(CurveFlattening::Scanlines);
+        let coarse = p.get_path().unwrap();
+        let mut p = PathBuilder::new();
+        for subpath in coarse.flatten(4.) {
+            let mut points = subpath.pixel_points();
+            let (x, y) = points.next().unwrap();
+            p.move_to(x, y);
+            for (x, y) in points {
+                p.line_to(x, y);
+            }
+            p.close();
+        }
+        let polygon = p.get_path().unwrap();
+        assert_eq!(coarse.bounds(), polygon.bounds());
+        assert_eq!(coarse.area(), polygon.area());
+        assert_eq!(coarse.subpath_perimeters(), polygon.subpath_perimeters());
+        let mut p = PathBuilder::new();
+        p.add_circle(50., 50., 40.);
+        let fine = p.get_path().unwrap();
+        assert!(coarse.area() < fine.area() - 10.);
+        p.set_flattening_tolerance(4.);
+        let forward = p.get_path().unwrap();
+        assert!(forward.flatten(4.).next() != coarse.flatten(4.).next());
+
+        assert_eq!(OutputPath::default().bounds(), None);
+        assert_eq!(OutputPath::default().area(), 0.);
+        assert!(!OutputPath::default().contains(0., 0., FillMode::Winding));
+    }
+
+    #[test]
+    fn scanline_flattening() {
+        let mut paths = Vec::new();
+        let mut p = PathBuilder::new();
+        p.add_circle(50.3, 50.7, 40.);
+        paths.push(p);
+        let mut p = PathBuilder::new();
+        p.add_circle(50., 50., 20.);
+        p.set_transform(Some(Transform::new(2., 0.3, -0.2, 1.5, -10., 3.)));
+        paths.push(p);
+        let mut p = PathBuilder::new();
+        p.move_to(5., 90.);
+        p.curve_to(30., -40., 70., 160., 95., 10.);
+        p.line_to(95., 95.);
+        p.close();
+        paths.push(p);
+
+        for mut p in paths {
+            for mode in [AntialiasMode::FourByFour, AntialiasMode::EightByEight, AntialiasMode::SixteenBySixteen] {
+                p.set_antialias_mode(mode);
+                // trapezoids aren't clipped, so the clip has to contain the paths for the
+                // areas to match
+                let mut sink = AreaSink::default();
+                p.rasterize_to_sink(0, 0, 200, 200, &mut sink).unwrap();
+
+                p.set_curve_flattening(CurveFlattening::Scanlines);
+                let mut aligned = AreaSink::default();
+                p.rasterize_to_sink(0, 0, 200, 200, &mut aligned).unwrap();
+                assert!(aligned.scans < sink.scans / 2, "{} complex scans instead of {}", aligned.scans, sink.scans);
+                assert!((aligned.area - sink.area).abs() / sink.area < 0.005);
+
+                // the bands flatten the same way
+                let serial = p.rasterize_to_tri_list(0, 0, 100, 100);
+                p.set_band_pool(Some(Arc::new(ThreadPerBand)));
+                p.set_parallel_bands(4);
+                assert_eq!(calculate_hash(&p.rasterize_to_tri_list(0, 0, 100, 100)), calculate_hash(&serial));
+                p.set_parallel_bands(1);
+                p.set_curve_flattening(CurveFlattening::ForwardDifferencing);
+            }
+        }
+    }
+
+    #[test]
+    fn mask() {
+        let mut p = PathBuilder::new();
+        p.add_circle(50.3, 50.7, 40.);
+        p.move_to(10., 10.);
+        p.line_to(90., 20.);
+        p.line_to(20., 90.);
+        p.line_to(80., 85.);
+        p.line_to(50., 5.);
+        p.close();
+        for mode in [AntialiasMode::FourByFour, AntialiasMode::EightByEight, AntialiasMode::SixteenBySixteen] {
+            p.set_antialias_mode(mode);
+            let mask = p.rasterize_to_mask(0, 0, 100, 100);
+            assert_eq!((mask.width, mask.height, mask.data.len()), (100, 100, 10000));
+            // up to rounding, the mask matches the triangles
+            let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+            let expected = rasterize_to_mask(&result, 100, 100);
+            assert!(mask.data.iter().zip(expected.iter()).all(|(&a, &b)| (a as i32 - b as i32).abs() <= 1));
+        }
+
+        // a mask that doesn't start at the origin
+        p.set_antialias_mode(AntialiasMode::EightByEight);
+        let mask = p.rasterize_to_mask(30, 40, 50, 20);
+        assert_eq!((mask.x, mask.y, mask.data.len()), (30, 40, 1000));
+        let result = p.rasterize_to_tri_list(30, 40, 50, 20);
+        let expected = rasterize_to_mask(&result, 100, 100);
+        for y in 0..20 {
+            for x in 0..50 {
+                let expected = expected[(y + 40) * 100 + x + 30] as i32;
+                assert!((mask.data[y * 50 + x] as i32 - expected).abs() <= 1);
+            }
+        }
+        assert_eq!(calculate_hash(&mask.data), 0xbe8c36a1e802d555);
+    }
+
+    fn fill_rect(p: &mut PathBuilder, x0: f32, y0: f32, x1: f32, y1: f32) {
+        p.move_to(x0, y0);
+        p.line_to(x1, y0);
+        p.line_to(x1, y1);
+        p.line_to(x0, y1);
+        p.close();
+    }
+
+    #[test]
+    fn stroke_line() {
+        let mut p = PathBuilder::new();
+        p.move_to(10., 20.);
+        p.line_to(40., 20.);
+        let stroker = Stroker::new(StrokeStyle { width: 10., ..Default::default() });
+        let result = stroker.stroke_builder(&p).rasterize_to_tri_list(0, 0, 100, 100);
+        let mut expected = PathBuilder::new();
+        fill_rect(&mut expected, 10., 15., 40., 25.);
+        let expected = expected.rasterize_to_tri_list(0, 0, 100, 100);
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 100, 100)),
+                   calculate_hash(&rasterize_to_mask(&expected, 100, 100)));
+
+        let stroker = Stroker::new(StrokeStyle { width: 10., cap: LineCap::Square, ..Default::default() });
+        let result = stroker.stroke_builder(&p).rasterize_to_tri_list(0, 0, 100, 100);
+        let mut expected = PathBuilder::new();
+        fill_rect(&mut expected, 5., 15., 45., 25.);
+        let expected = expected.rasterize_to_tri_list(0, 0, 100, 100);
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 100, 100)),
+                   calculate_hash(&rasterize_to_mask(&expected, 100, 100)));
+    }
+
+    #[test]
+    fn stroke_far_points() {
+        // points outside of the 28.4 range are stroked in floating point and then clipped
+        let mut p = PathBuilder::new();
+        p.move_to(10., 20.);
+        p.line_to(1e9, 20.);
+        let stroker = Stroker::new(StrokeStyle { width: 10., ..Default::default() });
+        let result = stroker.stroke_builder(&p).rasterize_to_tri_list(0, 0, 100, 100);
+        let mut expected = PathBuilder::new();
+        fill_rect(&mut expected, 10., 15., 110., 25.);
+        let expected = expected.rasterize_to_tri_list(0, 0, 100, 100);
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 100, 100)),
+                   calculate_hash(&rasterize_to_mask(&expected, 100, 100)));
+
+        // curves with far away control points are flattened in floating point as well
+        let mut p = PathBuilder::new();
+        p.move_to(10., 50.);
+        p.curve_to(1e9, 50., -1e9, 50., 90., 50.);
+        let result = stroker.stroke_builder(&p).rasterize_to_tri_list(0, 0, 100, 100);
+        let mask = rasterize_to_mask(&result, 100, 100);
+        assert_eq!(mask[50 * 100 + 50], 0xff);
+        assert_eq!(mask[10 * 100 + 50], 0);
+    }
+
+    #[test]
+    fn stroke_closed() {
+        let mut p = PathBuilder::new();
+        fill_rect(&mut p, 20., 20., 60., 60.);
+        let stroker = Stroker::new(StrokeStyle { width: 4., ..Default::default() });
+        let result = stroker.stroke_path(&p.get_path().unwrap()).rasterize_to_tri_list(0, 0, 100, 100);
+        let mut expected = PathBuilder::new();
+        fill_rect(&mut expected, 18., 18., 62., 62.);
+        fill_rect(&mut expected, 22., 22., 58., 58.);
+        let expected = expected.rasterize_to_tri_list(0, 0, 100, 100);
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 100, 100)),
+                   calculate_hash(&rasterize_to_mask(&expected, 100, 100)));
+    }
+
+    #[test]
+    fn stroke_joins_and_caps() {
+        let mut p = PathBuilder::new();
+        p.move_to(10., 10.);
+        p.line_to(50., 20.);
+        p.line_to(10., 30.);
+        p.curve_to(40., 40., 40., 60., 10., 80.);
+        p.move_to(80., 80.);
+        p.line_to(80., 80.);
+
+        let mut hashes = Vec::new();
+        for (join, cap) in [(LineJoin::Miter, LineCap::Butt), (LineJoin::Round, LineCap::Round), (LineJoin::Bevel, LineCap::Square)] {
+            let stroker = Stroker::new(StrokeStyle { width: 6., join, cap, miter_limit: 4., ..Default::default() });
+            let result = stroker.stroke_builder(&p).rasterize_to_tri_list(0, 0, 100, 100);
+            hashes.push(calculate_hash(&rasterize_to_mask(&result, 100, 100)));
+        }
+        assert_eq!(hashes, [0x8f538c07891181fc, 0xfd28caa8796729fd, 0xa9dc216718bad857]);
+    }
+
+    #[test]
+    fn stroke_dashes() {
+        let mut p = PathBuilder::new();
+        p.move_to(10., 20.);
+        p.line_to(70., 20.);
+        let mut style = StrokeStyle { width: 4., dash_array: vec![10.], ..Default::default() };
+        let result = Stroker::new(style.clone()).stroke_builder(&p).rasterize_to_tri_list(0, 0, 100, 100);
+        let mut expected = PathBuilder::new();
+        fill_rect(&mut expected, 10., 18., 20., 22.);
+        fill_rect(&mut expected, 30., 18., 40., 22.);
+        fill_rect(&mut expected, 50., 18., 60., 22.);
+        let expected = expected.rasterize_to_tri_list(0, 0, 100, 100);
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 100, 100)),
+                   calculate_hash(&rasterize_to_mask(&expected, 100, 100)));
+
+        style.dash_offset = -5.;
+        let result = Stroker::new(style.clone()).stroke_builder(&p).rasterize_to_tri_list(0, 0, 100, 100);
+        let mut expected = PathBuilder::new();
+        fill_rect(&mut expected, 15., 18., 25., 22.);
+        fill_rect(&mut expected, 35., 18., 45., 22.);
+        fill_rect(&mut expected, 55., 18., 65., 22.);
+        let expected = expected.rasterize_to_tri_list(0, 0, 100, 100);
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 100, 100)),
+                   calculate_hash(&rasterize_to_mask(&expected, 100, 100)));
+
+        // dashes follow curves and continue around corners
+        let mut p = PathBuilder::new();
+        p.move_to(10., 10.);
+        p.curve_to(90., 10., 90., 90., 10., 90.);
+        p.close();
+        style.width = 2.;
+        style.dash_array = vec![8., 4., 0., 4.];
+        style.cap = LineCap::Round;
+        let result = Stroker::new(style).stroke_builder(&p).rasterize_to_tri_list(0, 0, 100, 100);
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 100, 100)), 0x78109cc1418dc24c);
+
+        // on a closed subpath the dash that crosses the start point is a single dash
+        let mut p = PathBuilder::new();
+        fill_rect(&mut p, 10., 10., 50., 50.);
+        let style = StrokeStyle { width: 4., cap: LineCap::Square, dash_array: vec![30., 10.], dash_offset: 20., ..Default::default() };
+        let contours = |style: StrokeStyle| {
+            let path = Stroker::new(style).stroke_builder(&p).get_path().unwrap();
+            path.types().iter().filter(|&&ty| ty & !PathPointTypeCloseSubpath == PathPointTypeStart).count()
+        };
+        assert_eq!(contours(style.clone()), 4);
+        // a dash that covers the whole subpath is drawn like a solid stroke
+        let dashed = StrokeStyle { dash_array: vec![1000., 10.], ..style.clone() };
+        let solid = StrokeStyle { dash_array: Vec::new(), ..style };
+        assert_eq!(calculate_hash(&Stroker::new(dashed).stroke_builder(&p).rasterize_to_tri_list(0, 0, 100, 100)),
+                   calculate_hash(&Stroker::new(solid).stroke_builder(&p).rasterize_to_tri_list(0, 0, 100, 100)));
+    }
+
+    // Returns how far the area of the mask is from `area` as a fraction of `area`. The
+    // rasterizer flattens curves into chords so curved shapes come out slightly small.
+    fn max_difference(a: &[u8], b: &[u8]) -> u8 {
+        a.iter().zip(b.iter()).map(|(&a, &b)| a.abs_diff(b)).max().unwrap_or(0)
+    }
+
+    fn mask_area_error(result: &[OutputVertex], width: u32, height: u32, area: f32) -> f32 {
+        let mask_area: f32 = rasterize_to_mask(result, width, height).iter().map(|&c| c as f32 / 255.).sum();
+        (mask_area - area).abs() / area
+    }
+
+    #[test]
+    fn circles_and_ellipses() {
+        let mut p = PathBuilder::new();
+        p.add_circle(50., 50., 40.);
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        let area = std::f32::consts::PI * 40. * 40.;
+        assert!(mask_area_error(&result, 100, 100, area) < 0.005);
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 100, 100)), 0x2816d3ada767ddff);
+
+        let mut p = PathBuilder::new();
+        p.add_ellipse(50., 50., 40., 20.);
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        let area = std::f32::consts::PI * 40. * 20.;
+        assert!(mask_area_error(&result, 100, 100, area) < 0.005);
+
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(10., 20., 80., 60., 20., 10.);
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        let area = 80. * 60. - (4. - std::f32::consts::PI) * 20. * 10.;
+        assert!(mask_area_error(&result, 100, 100, area) < 0.005);
+    }
+
+    #[test]
+    fn arcs() {
+        // a half circle using the center parameterization
+        let mut p = PathBuilder::new();
+        p.arc(50., 50., 40., 0., std::f32::consts::PI);
+        p.close();
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        let area = std::f32::consts::PI * 40. * 40. / 2.;
+        assert!(mask_area_error(&result, 100, 100, area) < 0.005);
+
+        // the same half circle using the SVG endpoint parameterization
+        let mut p = PathBuilder::new();
+        p.move_to(90., 50.);
+        p.arc_to(40., 40., 0., false, true, 10., 50.);
+        p.close();
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        assert!(mask_area_error(&result, 100, 100, area) < 0.005);
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 100, 100)), 0x95722b8cd7fcd45f);
+
+        // the large arc of a rotated ellipse whose radii are too small to reach the end point
+        let mut p = PathBuilder::new();
+        p.move_to(20., 50.);
+        p.arc_to(10., 5., 30., true, false, 80., 50.);
+        p.close();
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        assert_eq!(calculate_hash(&rasterize_to_mask(&result, 100, 100)), 0xc8518067c8959c48);
+
+        // huge sweeps are clamped to a full turn and non-finite arguments invalidate the path
+        let mut p = PathBuilder::new();
+        p.arc(50., 50., 40., 0., 1e30);
+        let mut circle = PathBuilder::new();
+        circle.arc(50., 50., 40., 0., 2. * std::f32::consts::PI);
+        assert!(p.get_path().unwrap().types() == circle.get_path().unwrap().types());
+        for (radius, sweep) in [(40., f32::INFINITY), (40., f32::NAN), (f32::INFINITY, 1.)] {
+            let mut p = PathBuilder::new();
+            p.move_to(10., 10.);
+            p.arc(50., 50., radius, 0., sweep);
+            assert!(p.types.len() <= 3);
+            assert!(p.try_rasterize_to_tri_list(0, 0, 100, 100).is_err());
+        }
+        let mut p = PathBuilder::new();
+        p.move_to(10., 10.);
+        p.arc_to(f32::INFINITY, 40., f32::NAN, false, true, 90., 50.);
+        assert!(p.try_rasterize_to_tri_list(0, 0, 100, 100).is_err());
+    }
+
+    #[test]
+    fn svg_path_data() {
+        let p = PathBuilder::from_svg_path_data("M10 10 H40 V40 L10,40 z").unwrap();
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        let mut expected = PathBuilder::new();
+        fill_rect(&mut expected, 10., 10., 40., 40.);
+        assert_eq!(calculate_hash(&result), calculate_hash(&expected.rasterize_to_tri_list(0, 0, 100, 100)));
+
+        // relative commands, implicit repeats, exponents and numbers without separators
+        let p = PathBuilder::from_svg_path_data("m10,10 3e1,0 0 30-.3E2-0Zm5 5h20v20h-2e1z").unwrap();
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        fill_rect(&mut expected, 15., 15., 35., 35.);
+        assert_eq!(calculate_hash(&result), calculate_hash(&expected.rasterize_to_tri_list(0, 0, 100, 100)));
+
+        let p = PathBuilder::from_svg_path_data(
+            "M10 50 C 10 10 40 10 40 50 S 70 90 70 50 s30-40 30 0 Q 90 90 70 90 T 30 90 t-20-40").unwrap();
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        let mut expected = PathBuilder::new();
+        expected.move_to(10., 50.);
+        expected.curve_to(10., 10., 40., 10., 40., 50.);
+        expected.curve_to(40., 90., 70., 90., 70., 50.);
+        expected.curve_to(70., 10., 100., 10., 100., 50.);
+        expected.quad_to(90., 90., 70., 90.);
+        expected.quad_to(50., 90., 30., 90.);
+        expected.quad_to(10., 90., 10., 50.);
+        assert_eq!(calculate_hash(&result), calculate_hash(&expected.rasterize_to_tri_list(0, 0, 100, 100)));
+
+        let p = PathBuilder::from_svg_path_data("M10 50a40 40 0 1 1 80 0a40,40,0,00-80,0z").unwrap();
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        let mut expected = PathBuilder::new();
+        expected.move_to(10., 50.);
+        expected.arc_to(40., 40., 0., true, true, 90., 50.);
+        expected.arc_to(40., 40., 0., false, false, 10., 50.);
+        expected.close();
+        assert_eq!(calculate_hash(&result), calculate_hash(&expected.rasterize_to_tri_list(0, 0, 100, 100)));
+
+        assert!(PathBuilder::from_svg_path_data(" \n").is_ok());
+        let error = |data| PathBuilder::from_svg_path_data(data).err().unwrap();
+        assert_eq!(error("L10 10"), SvgPathError { kind: SvgPathErrorKind::ExpectedMoveTo, offset: 0 });
+        assert_eq!(error(" 10 10"), SvgPathError { kind: SvgPathErrorKind::ExpectedMoveTo, offset: 1 });
+        assert_eq!(error("M10 10 L 20 x"), SvgPathError { kind: SvgPathErrorKind::ExpectedNumber, offset: 12 });
+        assert_eq!(error("M10 10 L 20 20 X"), SvgPathError { kind: SvgPathErrorKind::ExpectedCommand, offset: 15 });
+        assert_eq!(error("M0 0 A 1 1 0 2 0 5 5"), SvgPathError { kind: SvgPathErrorKind::ExpectedFlag, offset: 13 });
+        assert_eq!(error("M0 0 L 1 1 Z 2 2"), SvgPathError { kind: SvgPathErrorKind::ExpectedCommand, offset: 13 });
+    }
+
+    #[test]
+    fn errors() {
+        let triangle = |x: f32, y: f32| {
+            let mut p = PathBuilder::new();
+            p.move_to(10., 10.);
+            p.line_to(40., 10.);
+            p.line_to(x, y);
+            p.close();
+            p
+        };
+        assert!(triangle(40., 40.).try_rasterize_to_tri_list(0, 0, 100, 100).is_ok());
+        assert_eq!(triangle(f32::NAN, 40.).try_rasterize_to_tri_list(0, 0, 100, 100).err(), Some(RasterError::NaN { point_index: 2 }));
+        // points that are out of range are only a problem when the clip rect is too large
+        assert!(triangle(1e9, 1e9).try_rasterize_to_tri_list(0, 0, 100, 100).is_ok());
+        assert_eq!(triangle(1e9, 1e9).try_rasterize_to_tri_list(0, 0, 1 << 20, 1 << 20).err(), Some(RasterError::OutOfRange { point_index: 2 }));
+
+        let mut p = triangle(300000., 40.);
+        assert!(p.try_rasterize_to_tri_list(0, 0, 1 << 19, 100).is_ok());
+        p.set_antialias_mode(AntialiasMode::SixteenBySixteen);
+        assert_eq!(p.try_rasterize_to_tri_list(0, 0, 1 << 19, 100).err(), Some(RasterError::OutOfRange { point_index: 2 }));
+
+        let mut p = triangle(40., 40.);
+        p.set_transform(Some(Transform::new(1e7, 0., 0., 1., -2e8, 0.)));
+        assert!(p.try_rasterize_to_tri_list(0, 0, 100, 100).is_ok());
+        assert_eq!(p.try_rasterize_to_tri_list(0, 0, 1 << 20, 1 << 20).err(), Some(RasterError::OutOfRange { point_index: 0 }));
+        p.set_transform(Some(Transform::new(1., 0., 0., f32::NAN, 0., 0.)));
+        assert_eq!(p.try_rasterize_to_tri_list(0, 0, 100, 100).err(), Some(RasterError::NaN { point_index: 0 }));
+
+        // a path without area and one that is outside of the clip
+        assert_eq!(triangle(70., 10.).try_rasterize_to_tri_list(0, 0, 100, 100).err(), Some(RasterError::EmptyFill));
+        let mut p = triangle(40., 40.);
+        assert_eq!(p.try_rasterize_to_tri_list(0, 50, 100, 100).err(), Some(RasterError::ClippedToEmpty));
+        assert_eq!(p.try_rasterize_to_tri_list(0, 0, 0, 0).err(), Some(RasterError::ClippedToEmpty));
+        // the outside geometry is still output
+        p.set_outside_bounds(Some((0, 0, 100, 100)), true);
+        assert!(p.try_rasterize_to_tri_list(0, 50, 100, 100).unwrap().len() > 0);
+
+        let points = [POINT { x: 0, y: 0 }, POINT { x: 160, y: 0 }, POINT { x: 160, y: 160 }];
+        let options = RasterizeOptions { fill_mode: FillMode::Winding, ..RasterizeOptions::new(0, 0, 100, 100) };
+        let try_rasterize = |types: &[BYTE], output_buffer| {
+            try_rasterize_to_tri_list(types, &points, &options, output_buffer).map(|_| ())
+        };
+        assert_eq!(try_rasterize(&[PathPointTypeLine, PathPointTypeLine, PathPointTypeLine], None), Err(RasterError::InvalidPathTypes));
+        assert_eq!(try_rasterize(&[PathPointTypeStart, PathPointTypeLine], None), Err(RasterError::InvalidPathTypes));
+        assert_eq!(try_rasterize(&[PathPointTypeStart, PathPointTypeBezier], None), Err(RasterError::InvalidPathTypes));
+        assert_eq!(try_rasterize(&[PathPointTypeStart, PathPointTypeQuadratic, PathPointTypeLine], None), Err(RasterError::InvalidPathTypes));
+        assert_eq!(try_rasterize(&[PathPointTypeStart, PathPointTypeQuadratic], None), Ok(()));
+
+        let types = [PathPointTypeStart, PathPointTypeLine, PathPointTypeLine | PathPointTypeCloseSubpath];
+        let required = try_rasterize_to_tri_list(&types, &points, &options, None).unwrap().flush_output().len();
+        let mut small_output = vec![OutputVertex::default(); 3];
+        assert_eq!(try_rasterize(&types, Some(&mut small_output)), Err(RasterError::OutputBufferTooSmall { required }));
+        let mut output = vec![OutputVertex::default(); required];
+        assert_eq!(try_rasterize(&types, Some(&mut output)), Ok(()));
+    }
+
+    #[test]
+    fn large_coordinates() {
+        // a rect whose far corner is out of range
+        let mut p = PathBuilder::new();
+        p.move_to(10.5, 10.);
+        p.line_to(1e9, 10.);
+        p.line_to(1e9, 1e9);
+        p.line_to(10.5, 1e9);
+        p.close();
+        let mut expected = PathBuilder::new();
+        expected.move_to(10.5, 10.);
+        expected.line_to(200., 10.);
+        expected.line_to(200., 200.);
+        expected.line_to(10.5, 200.);
+        expected.close();
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        let expected_mask = rasterize_to_mask(&expected.rasterize_to_tri_list(0, 0, 100, 100), 100, 100);
+        assert!(max_difference(&rasterize_to_mask(&result, 100, 100), &expected_mask) <= 1);
+        assert!(max_difference(&p.rasterize_to_mask(0, 0, 100, 100).data, &expected.rasterize_to_mask(0, 0, 100, 100).data) <= 1);
+
+        // the visible part of a huge circle is almost a straight line
+        let mut p = PathBuilder::new();
+        p.add_circle(1e6 + 50., 50., 1e6);
+        let mut expected = PathBuilder::new();
+        expected.move_to(50., -10.);
+        expected.line_to(200., -10.);
+        expected.line_to(200., 200.);
+        expected.line_to(50., 200.);
+        expected.close();
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        let expected_mask = rasterize_to_mask(&expected.rasterize_to_tri_list(0, 0, 100, 100), 100, 100);
+        assert!(max_difference(&rasterize_to_mask(&result, 100, 100), &expected_mask) <= 2);
+
+        // quadratics are clipped like cubics
+        let mut p = PathBuilder::new();
+        p.move_to(10., 10.);
+        p.quad_to(1e9, 50., 10., 90.);
+        p.close();
+        let mut expected = PathBuilder::new();
+        expected.move_to(10., 10.);
+        expected.curve_to(10. + (1e9 - 10.) * 2. / 3., 10. + 40. * 2. / 3., 10. + (1e9 - 10.) * 2. / 3., 90. - 40. * 2. / 3., 10., 90.);
+        expected.close();
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        assert!(!result.is_empty());
+        assert!(max_difference(&rasterize_to_mask(&result, 100, 100), &rasterize_to_mask(&expected.rasterize_to_tri_list(0, 0, 100, 100), 100, 100)) <= 1);
+
+        // a clip rect far away from the origin
+        let mut p = PathBuilder::new();
+        p.move_to(1e6 + 10., 1e6 + 10.);
+        p.line_to(1e6 + 30., 1e6 + 10.);
+        p.line_to(1e6 + 30., 1e6 + 30.);
+        p.line_to(1e6 + 10., 1e6 + 30.);
+        p.close();
+        let mask = p.rasterize_to_mask(1_000_000, 1_000_000, 50, 50);
+        assert_eq!((mask.x, mask.y), (1_000_000, 1_000_000));
+        let mut expected = PathBuilder::new();
+        expected.add_rounded_rect(10., 10., 20., 20., 0., 0.);
+        assert!(mask.data == expected.rasterize_to_mask(0, 0, 50, 50).data);
+        let result = p.rasterize_to_tri_list(1_000_000, 1_000_000, 50, 50);
+        assert!(result.len() > 0 && result.iter().all(|v| v.x >= 1e6 && v.y >= 1e6));
+
+        // a path that is zoomed in until it covers the whole clip rect
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(0., 0., 1., 1., 0., 0.);
+        p.set_transform(Some(Transform::new(1e6, 0., 0., 1e6, -5e5, -5e5)));
+        assert!(p.rasterize_to_mask(0, 0, 100, 100).data.iter().all(|&c| c == 255));
+    }
+
+    #[test]
+    fn clip_path() {
+        let mut circle = PathBuilder::new();
+        circle.add_circle(50., 50., 30.);
+        let circle = circle.get_path().unwrap();
+
+        // clipping is symmetric, a rect clipped to a circle matches the circle clipped to the rect
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(10., 10., 80., 80., 0., 0.);
+        let rect = p.get_path().unwrap();
+        let unclipped = p.rasterize_to_tri_list(0, 0, 100, 100);
+        p.set_clip_path(Some(circle.clone()));
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        let mut expected = PathBuilder::new();
+        expected.add_circle(50., 50., 30.);
+        expected.set_clip_path(Some(rect));
+        let expected_mask = rasterize_to_mask(&expected.rasterize_to_tri_list(0, 0, 100, 100), 100, 100);
+        assert!(rasterize_to_mask(&result, 100, 100) == expected_mask);
+        assert!(p.rasterize_to_mask(0, 0, 100, 100).data == expected.rasterize_to_mask(0, 0, 100, 100).data);
+        assert!(mask_area_error(&result, 100, 100, std::f32::consts::PI * 30. * 30.) < 0.01);
+
+        // the coverage is the product of both coverages
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(50., 0., 50., 100., 0., 0.);
+        p.set_clip_path(Some(circle.clone()));
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        assert!(mask_area_error(&result, 100, 100, std::f32::consts::PI * 30. * 30. / 2.) < 0.005);
+
+        // no clip path gives the same result as before
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(10., 10., 80., 80., 0., 0.);
+        p.set_clip_path(None);
+        assert_eq!(calculate_hash(&p.rasterize_to_tri_list(0, 0, 100, 100)), calculate_hash(&unclipped));
+
+        // a disjoint clip path leaves nothing
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(0., 0., 10., 10., 0., 0.);
+        p.set_clip_path(Some(circle.clone()));
+        assert!(p.rasterize_to_mask(0, 0, 100, 100).data.iter().all(|&c| c == 0));
+        assert!(rasterize_to_mask(&p.rasterize_to_tri_list(0, 0, 100, 100), 100, 100).iter().all(|&c| c == 0));
+
+        // an empty clip path leaves nothing either
+        let mut empty = PathBuilder::new();
+        empty.add_rounded_rect(20., 20., 0., 10., 0., 0.);
+        p.add_rounded_rect(10., 10., 80., 80., 0., 0.);
+        p.set_clip_path(empty.get_path());
+        assert!(p.rasterize_to_mask(0, 0, 100, 100).data.iter().all(|&c| c == 0));
+
+        // the clip path isn't transformed and is clipped along with far away paths
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(0., 0., 1., 1., 0., 0.);
+        p.set_transform(Some(Transform::new(1e6, 0., 0., 1e6, -5e5, -5e5)));
+        p.set_clip_path(Some(circle.clone()));
+        assert!(max_difference(&p.rasterize_to_mask(0, 0, 100, 100).data, &expected.rasterize_to_mask(0, 0, 100, 100).data) <= 1);
+        let result = p.rasterize_to_tri_list(0, 0, 100, 100);
+        assert!(max_difference(&rasterize_to_mask(&result, 100, 100), &expected_mask) <= 1);
+
+        // invalid clip paths are reported
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(10., 10., 80., 80., 0., 0.);
+        p.set_clip_path(Some(OutputPath { fill_mode: FillMode::Winding, points: Box::new([]), types: Box::new([PathPointTypeLine]), ..Default::default() }));
+        assert_eq!(p.try_rasterize_to_tri_list(0, 0, 100, 100).err(), Some(RasterError::InvalidPathTypes));
+    }
+
+    fn rasterize_path_to_mask(path: &OutputPath) -> Box<[u8]> {
+        let result = rasterize_to_tri_list(path.fill_mode(), path.types(), path.points(), 0, 0, 100, 100, true, false, false, None).flush_output();
+        rasterize_to_mask(&result, 100, 100)
+    }
+
+    fn path_area(path: &OutputPath) -> f32 {
+        rasterize_path_to_mask(path).iter().map(|&c| c as f32 / 255.).sum()
+    }
+
+    fn subpath_count(path: &OutputPath) -> usize {
+        path.types().iter().filter(|&&t| t == PathPointTypeStart).count()
+    }
+
+    #[test]
+    fn boolean() {
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(10., 10., 40., 40., 0., 0.);
+        let a = p.get_path().unwrap();
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(30., 30., 40., 40., 0., 0.);
+        let b = p.get_path().unwrap();
+
+        let union = a.boolean(BooleanOp::Union, &b);
+        assert_eq!((subpath_count(&union), union.points().len()), (1, 8));
+        assert!((path_area(&union) - 2800.).abs() < 1.);
+        assert!((path_area(&a.boolean(BooleanOp::Difference, &b)) - 1200.).abs() < 1.);
+        assert!((path_area(&b.boolean(BooleanOp::Difference, &a)) - 1200.).abs() < 1.);
+        let xor = a.boolean(BooleanOp::Xor, &b);
+        assert_eq!(subpath_count(&xor), 2);
+        assert!((path_area(&xor) - 2400.).abs() < 1.);
+
+        // the intersection of two rects is the same rect as the one built directly
+        let intersection = a.boolean(BooleanOp::Intersection, &b);
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(30., 30., 20., 20., 0., 0.);
+        let expected = p.get_path().unwrap();
+        assert_eq!(intersection.points().len(), 4);
+        assert!(rasterize_path_to_mask(&intersection) == rasterize_path_to_mask(&expected));
+
+        // points that are too far away for the 16x16 grid are swept on the 8x8 grid
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(300010., 10., 40., 40., 0., 0.);
+        let far_a = p.get_path().unwrap();
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(300030., 30., 40., 40., 0., 0.);
+        let far_union = far_a.boolean(BooleanOp::Union, &p.get_path().unwrap());
+        assert_eq!((subpath_count(&far_union), far_union.points().len()), (1, 8));
+        assert_eq!(far_union.control_bounds(), Some(Bounds { min_x: 300010., min_y: 10., max_x: 300070., max_y: 70. }));
+
+        // disjoint paths
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(80., 80., 10., 10., 0., 0.);
+        let c = p.get_path().unwrap();
+        assert!(a.boolean(BooleanOp::Intersection, &c).types().is_empty());
+        assert_eq!(subpath_count(&a.boolean(BooleanOp::Union, &c)), 2);
+        assert!(a.boolean(BooleanOp::Union, &OutputPath::default()).types().len() == 4);
+        assert!(OutputPath::default().boolean(BooleanOp::Union, &OutputPath::default()).types().is_empty());
+
+        // a ring made from two circles has a hole, curves are flattened
+        let mut p = PathBuilder::new();
+        p.add_circle(50., 50., 40.);
+        let outer = p.get_path().unwrap();
+        let mut p = PathBuilder::new();
+        p.add_circle(50., 50., 20.);
+        let inner = p.get_path().unwrap();
+        let ring = outer.boolean(BooleanOp::Difference, &inner);
+        assert_eq!(subpath_count(&ring), 2);
+        let area = std::f32::consts::PI * (40. * 40. - 20. * 20.);
+        assert!((path_area(&ring) - area).abs() / area < 0.005);
+        // either fill mode gives the same result
+        let even_odd = OutputPath { fill_mode: FillMode::EvenOdd, ..ring.clone() };
+        assert!(rasterize_path_to_mask(&even_odd) == rasterize_path_to_mask(&ring));
+
+        // the fill mode of the operands is respected: overlapping rects with even-odd filling
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(10., 10., 40., 40., 0., 0.);
+        p.add_rounded_rect(30., 30., 40., 40., 0., 0.);
+        let mut even_odd = p.get_path().unwrap();
+        even_odd.fill_mode = FillMode::EvenOdd;
+        let result = even_odd.boolean(BooleanOp::Union, &OutputPath::default());
+        assert!((path_area(&result) - 2400.).abs() < 1.);
+        even_odd.fill_mode = FillMode::Winding;
+        let result = even_odd.boolean(BooleanOp::Union, &OutputPath::default());
+        assert_eq!((subpath_count(&result), result.points().len()), (1, 8));
+
+        // crossing edges: a bow tie intersected with a rect
+        let mut p = PathBuilder::new();
+        p.move_to(10., 10.);
+        p.line_to(90., 90.);
+        p.line_to(90., 10.);
+        p.line_to(10., 90.);
+        p.close();
+        let bow_tie = p.get_path().unwrap();
+        let mut p = PathBuilder::new();
+        p.add_rounded_rect(0., 0., 100., 50., 0., 0.);
+        let top = p.get_path().unwrap();
+        let result = bow_tie.boolean(BooleanOp::Intersection, &top);
+        assert_eq!(subpath_count(&result), 1);
+        assert!((path_area(&result) - 1600.).abs() < 1.);
+        assert!((path_area(&bow_tie.boolean(BooleanOp::Union, &OutputPath::default())) - 3200.).abs() < 1.);
+
+        // overlapping circles with many crossings satisfy inclusion-exclusion
+        for i in 0..8 {
+            let mut p = PathBuilder::new();
+            p.add_ellipse(40. + i as f32 * 1.7, 45., 30., 20. + i as f32);
+            let a = p.get_path().unwrap();
+            let mut p = PathBuilder::new();
+            p.add_ellipse(55., 50. - i as f32 * 0.9, 25., 35.);
+            let b = p.get_path().unwrap();
+            let union = path_area(&a.boolean(BooleanOp::Union, &b));
+            let intersection = path_area(&a.boolean(BooleanOp::Intersection, &b));
+            let total = path_area(&a) + path_area(&b);
+            assert!((union + intersection - total).abs() / total < 0.001);
+            let xor = path_area(&a.boolean(BooleanOp::Xor, &b));
+            assert!((union - intersection - xor).abs() / total < 0.001);
+        }
+    }
+
+    #[test]
+    fn batch() {
+        let mut rect = PathBuilder::new();
+        rect.add_rounded_rect(10., 10., 40., 40., 0., 0.);
+        let mut circle = PathBuilder::new();
+        circle.add_circle(50., 50., 30.);
+        circle.set_transform(Some(Transform::new(1., 0., 0., 1., 5., 5.)));
+        circle.set_outside_bounds(Some((0, 0, 100, 100)), true);
+        let mut nan = PathBuilder::new();
+        nan.add_rounded_rect(f32::NAN, 10., 40., 40., 0., 0.);
+
+        let mut batch = Batch::new();
+        for _ in 0..2 {
+            batch.clear();
+            assert_eq!(batch.add_path(&rect, 0, 0, 100, 100), 0);
+            assert_eq!(batch.add_path(&circle, 0, 0, 100, 100), 1);
+            assert_eq!(batch.add_path(&nan, 0, 0, 100, 100), 2);
+            assert_eq!(batch.add_path(&rect, 0, 0, 100, 100), 3);
+            assert_eq!(batch.ranges().len(), 4);
+            assert_eq!(batch.ranges()[2].count, 0);
+
+            let mut start = 0;
+            for (index, path) in [&rect, &circle, &nan, &rect].into_iter().enumerate() {
+                let expected = path.rasterize_to_tri_list(0, 0, 100, 100);
+                let range = batch.ranges()[index];
+                assert_eq!(range, VertexRange { start, count: expected.len() });
+                let vertices = &batch.vertices()[range.start..range.start + range.count];
+                assert!(vertices.iter().zip(expected.iter()).all(|(v, e)| {
+                    (v.x, v.y, v.coverage, v.path_index) == (e.x, e.y, e.coverage, index as u32)
+                }));
+                start += range.count;
+            }
+            assert_eq!(batch.vertices().len(), start);
+        }
+
+        // paths that need to be clipped before they're rasterized are offset correctly
+        let mut far = PathBuilder::new();
+        far.add_rounded_rect(10., 10., 1e9, 1e9, 0., 0.);
+        batch.clear();
+        batch.add_path(&far, 0, 0, 100, 100);
+        let vertices: Vec<OutputVertex> = batch.vertices().iter().map(|v| OutputVertex { x: v.x, y: v.y, coverage: v.coverage }).collect();
+        assert!(!vertices.is_empty());
+        assert!(calculate_hash(&rasterize_to_mask(&vertices, 100, 100)) == calculate_hash(&rasterize_to_mask(&far.rasterize_to_tri_list(0, 0, 100, 100), 100, 100)));
+    }
+
+    #[test]
+    fn rasterizer() {
+        let mut circle = PathBuilder::new();
+        circle.add_circle(50., 50., 30.);
+        circle.set_transform(Some(Transform::new(1., 0., 0., 1., 5., 5.)));
+        // enough edges to need more than one arena chunk
+        let star = || {
+            let mut star = PathBuilder::new();
+            star.move_to(50., 0.);
+            for i in 1..200 {
+                let angle = i as f32 * std::f32::consts::PI * 0.99;
+                star.line_to(50. + 50. * angle.sin(), 50. - 50. * angle.cos());
+            }
+            star.close();
+            star
+        };
+        let mut clipped = star();
+        let star = star();
+        let mut clip = PathBuilder::new();
+        clip.add_circle(50., 50., 20.);
+        clipped.set_clip_path(Some(clip.get_path().unwrap()));
+        clipped.set_outside_bounds(Some((0, 0, 100, 100)), true);
+        let mut far = PathBuilder::new();
+        far.add_rounded_rect(10., 10., 1e9, 1e9, 0., 0.);
+        let mut nan = PathBuilder::new();
+        nan.add_rounded_rect(f32::NAN, 10., 40., 40., 0., 0.);
+
+        let mut rasterizer = Rasterizer::new();
+        for _ in 0..2 {
+            for path in [&circle, &star, &nan, &clipped, &far, &circle] {
+                let expected = path.try_rasterize_to_tri_list(0, 0, 100, 100);
+                let result = rasterizer.try_rasterize_to_tri_list(path, 0, 0, 100, 100);
+                match (result, expected) {
+                    (Ok(vertices), Ok(expected)) => assert_eq!(calculate_hash(&vertices), calculate_hash(&&*expected)),
+                    (Err(error), Err(expected)) => assert_eq!(error, expected),
+                    _ => panic!("results differ"),
+                }
+            }
+        }
+        assert!(rasterizer.rasterize_to_tri_list(&nan, 0, 0, 100, 100).is_empty());
+    }
+
+    // Runs every band on a thread of its own
+    struct ThreadPerBand;
+
+    impl BandPool for ThreadPerBand {
+        fn run_bands(&self, count: usize, band: &(dyn Fn(usize) + Sync)) {
+            std::thread::scope(|scope| {
+                for i in 0..count {
+                    scope.spawn(move || band(i));
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn parallel_bands() {
+        fn bits(vertices: &[OutputVertex]) -> Vec<(u32, u32, u32)> {
+            vertices.iter().map(|v| (v.x.to_bits(), v.y.to_bits(), v.coverage.to_bits())).collect()
+        }
+        let mut seed = 1u32;
+        let mut random = move || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 8) as f32 / (1 << 24) as f32 * 200.
+        };
+        let mut paths = Vec::new();
+        for i in 0..24 {
+            let mut path = PathBuilder::new();
+            path.move_to(random(), random());
+            for _ in 0..(i % 8 + 1) * 10 {
+                path.line_to(random(), random());
+            }
+            path.close();
+            path.set_fill_mode(if i % 2 == 0 { FillMode::EvenOdd } else { FillMode::Winding });
+            path.set_antialias_mode([AntialiasMode::EightByEight, AntialiasMode::FourByFour,
+                                     AntialiasMode::SixteenBySixteen, AntialiasMode::Aliased][i % 4]);
+            if i % 5 == 0 {
+                path.set_transform(Some(Transform::new(0.8, 0.3, -0.3, 0.8, 20., -10.)));
+            }
+            if i % 7 == 0 {
+                let mut clip = PathBuilder::new();
+                clip.add_circle(100., 100., 70.);
+                path.set_clip_path(Some(clip.get_path().unwrap()));
+            }
+            paths.push(path);
+        }
+        let mut circles = PathBuilder::new();
+        for i in 0..20 {
+            circles.add_circle(10. * i as f32, 9. * i as f32, 15.);
+        }
+        paths.push(circles);
+        // edges that touch, so that band boundaries need to avoid ties
+        for fill_mode in [FillMode::EvenOdd, FillMode::Winding] {
+            let mut grid = PathBuilder::new();
+            for i in 0..100 {
+                let (x, y) = ((i % 10) as f32 * 20., (i / 10) as f32 * 20. + (i % 3) as f32 * 5.);
+                grid.move_to(x, y);
+                grid.line_to(x + 20., y + 3.);
+                grid.line_to(x + 20., y + 23.);
+                grid.line_to(x, y + 20.);
+                grid.close();
+            }
+            grid.set_fill_mode(fill_mode);
+            paths.push(grid);
+        }
+        let mut star = PathBuilder::new();
+        star.move_to(100., 0.);
+        for i in 1..200 {
+            let angle = i as f32 * std::f32::consts::PI * 0.99;
+            star.line_to(100. + 100. * angle.sin(), 100. - 100. * angle.cos());
+        }
+        star.close();
+        paths.push(star);
+
+        let mut banded = 0;
+        for path in &mut paths {
+            let serial = path.rasterize_to_tri_list(0, 0, 200, 200);
+            let serial_split = path.rasterize_to_opaque_and_fringe(0, 0, 200, 200);
+            path.set_band_pool(Some(Arc::new(ThreadPerBand)));
+            for bands in [3, 8] {
+                path.set_parallel_bands(bands);
+                assert_eq!(bits(&path.rasterize_to_tri_list(0, 0, 200, 200)), bits(&serial));
+                let split = path.rasterize_to_opaque_and_fringe(0, 0, 200, 200);
+                assert_eq!(bits(&split.opaque), bits(&serial_split.opaque));
+                assert_eq!(bits(&split.fringe), bits(&serial_split.fringe));
+            }
+            path.set_parallel_bands(1);
+
+            let mut sink = CNullGeometrySink;
+            let mut rasterizer = CHwRasterizer::new(&mut sink, path.fill_mode.to_mil(), None,
+                                                    MilPointAndSizeL { X: 0, Y: 0, Width: 200, Height: 200 });
+            rasterizer.SetAntiAliasMode(path.antialias_mode.to_mil());
+            if rasterizer.PlanBands(&path.points, &path.types, 8).map_or(false, |plan| plan.GetBandCount() > 2) {
+                banded += 1;
+            }
+        }
+        // most of the paths really are split
+        assert!(banded > paths.len() / 2);
+    }
+
+    #[test]
+    fn flattening_tolerance() {
+        fn bits(vertices: &[OutputVertex]) -> Vec<(u32, u32, u32)> {
+            vertices.iter().map(|v| (v.x.to_bits(), v.y.to_bits(), v.coverage.to_bits())).collect()
+        }
+        let mut circle = PathBuilder::new();
+        circle.add_circle(100., 100., 90.);
+        let default = circle.rasterize_to_tri_list(0, 0, 200, 200);
+        circle.set_flattening_tolerance(0.25);
+        assert_eq!(bits(&circle.rasterize_to_tri_list(0, 0, 200, 200)), bits(&default));
+
+        circle.set_flattening_tolerance(4.);
+        let coarse = circle.rasterize_to_tri_list(0, 0, 200, 200);
+        assert!(coarse.len() < default.len());
+        // the free functions take it through RasterizeOptions and C paths keep it
+        let path = circle.get_path().unwrap();
+        let options = RasterizeOptions { flattening_tolerance: 4., ..RasterizeOptions::new(0, 0, 200, 200) };
+        let result = rasterize_to_tri_list_with_options(path.types(), path.points(), &options, None).flush_output();
+        assert_eq!(bits(&result), bits(&coarse));
+        let path: OutputPath = c_bindings::Path::from(path).into();
+        assert_eq!(path.flattening.tolerance, 4.);
+        // the bands and the stroke use the same tolerance
+        circle.set_band_pool(Some(Arc::new(ThreadPerBand)));
+        circle.set_parallel_bands(4);
+        assert_eq!(bits(&circle.rasterize_to_tri_list(0, 0, 200, 200)), bits(&coarse));
+        let stroker = Stroker::new(StrokeStyle { width: 4., ..Default::default() });
+        let coarse_stroke = stroker.stroke_builder(&circle).rasterize_to_tri_list(0, 0, 200, 200);
+        circle.set_flattening_tolerance(f32::NAN);
+        let stroke = stroker.stroke_builder(&circle).rasterize_to_tri_list(0, 0, 200, 200);
+        assert!(coarse_stroke.len() < stroke.len());
+    }
+
+    #[derive(Clone, Debug, Default, PartialEq)]
+    struct PackedVertex {
+        // 12.4 fixed point
+        x: u16,
+        y: u16,
+        coverage: u8,
+    }
+
+    impl Vertex for PackedVertex {
+        fn new(x: f32, y: f32, coverage: f32) -> Self {
+            PackedVertex {
+                x: (x * 16.).round() as u16,
+                y: (y * 16.).round() as u16,
+                coverage: (coverage * 255.).round() as u8,
+            }
+        }
+    }
+
+    #[test]
+    fn custom_vertex() {
+        let mut circle = PathBuilder::new();
+        circle.add_circle(50., 50., 30.);
+        let mut outside = PathBuilder::new();
+        outside.add_rounded_rect(20., 20., 40., 40., 0., 0.);
+        outside.set_outside_bounds(Some((0, 0, 100, 100)), true);
+        let mut far = PathBuilder::new();
+        far.add_rounded_rect(10., 10., 1e9, 1e9, 0., 0.);
+
+        for path in [&circle, &outside, &far] {
+            let expected: Vec<PackedVertex> = path.rasterize_to_tri_list(0, 0, 100, 100).iter()
+                .map(|v| PackedVertex::new(v.x, v.y, v.coverage)).collect();
+            assert!(!expected.is_empty());
+            assert_eq!(&*path.rasterize_to_vertices::<PackedVertex>(0, 0, 100, 100), &expected[..]);
+        }
+
+        let mut nan = PathBuilder::new();
+        nan.add_rounded_rect(f32::NAN, 10., 40., 40., 0., 0.);
+        assert!(nan.rasterize_to_vertices::<PackedVertex>(0, 0, 100, 100).is_empty());
+
+        // the vertices can be written into a caller provided buffer
+        let mut circle = PathBuilder::new();
+        circle.add_circle(50., 50., 30.);
+        let path = circle.get_path().unwrap();
+        let options = RasterizeOptions { fill_mode: path.fill_mode, ..RasterizeOptions::new(0, 0, 100, 100) };
+        let expected = try_rasterize_to_vertices::<PackedVertex>(&path.types, &path.points, &options, None).unwrap().flush_output();
+        let mut output = vec![PackedVertex::default(); expected.len()];
+        try_rasterize_to_vertices(&path.types, &path.points, &options, Some(&mut output)).unwrap();
+        assert_eq!(&output[..], &expected[..]);
+    }
+
+    #[test]
+    fn uv_transforms() {
+        let uv0 = Transform::new(0.01, 0., 0., 0.01, 0., 0.);
+        let uv1 = Transform::new(0., 2., -2., 0., 3., 5.);
+        let mut circle = PathBuilder::new();
+        circle.add_circle(50., 50., 30.);
+        circle.set_outside_bounds(Some((0, 0, 100, 100)), true);
+        let mut far = PathBuilder::new();
+        far.add_rounded_rect(10., 10., 1e9, 1e9, 0., 0.);
+
+        for path in [&mut circle, &mut far] {
+            let expected = path.rasterize_to_tri_list(0, 0, 100, 100);
+            let vertices = path.rasterize_to_vertices::<OutputVertexUV>(0, 0, 100, 100);
+            assert!(vertices.iter().all(|v| (v.u0, v.v0, v.u1, v.v1) == (0., 0., 0., 0.)));
+
+            path.set_uv_transforms(Some(uv0), Some(uv1));
+            // the positions don't change and the coordinates follow them
+            let vertices = path.rasterize_to_vertices::<OutputVertexUV>(0, 0, 100, 100);
+            assert_eq!(vertices.len(), expected.len());
+            for (v, e) in vertices.iter().zip(expected.iter()) {
+                assert_eq!((v.x, v.y, v.coverage), (e.x, e.y, e.coverage));
+                assert_eq!((v.u0, v.v0), uv0.transform_point(v.x, v.y));
+                assert_eq!((v.u1, v.v1), uv1.transform_point(v.x, v.y));
+            }
+            // vertices without texture coordinates are unaffected
+            assert_eq!(calculate_hash(&path.rasterize_to_tri_list(0, 0, 100, 100)), calculate_hash(&expected));
+
+            path.set_uv_transforms(None, Some(uv1));
+            let vertices = path.rasterize_to_vertices::<OutputVertexUV>(0, 0, 100, 100);
+            assert!(vertices.iter().all(|v| (v.u0, v.v0) == (0., 0.) && (v.u1, v.v1) == uv1.transform_point(v.x, v.y)));
+        }
+
+        let stroked = Stroker::new(StrokeStyle { width: 4., ..Default::default() }).stroke_builder(&circle);
+        let vertices = stroked.rasterize_to_vertices::<OutputVertexUV>(0, 0, 100, 100);
+        assert!(!vertices.is_empty());
+        assert!(vertices.iter().all(|v| (v.u1, v.v1) == uv1.transform_point(v.x, v.y)));
+    }
+
+    #[test]
+    fn mesh() {
+        let mut circle = PathBuilder::new();
+        circle.add_circle(50., 50., 30.);
+        let mut rect = PathBuilder::new();
+        rect.add_rounded_rect(10., 10., 40., 40., 0., 0.);
+        rect.set_outside_bounds(Some((0, 0, 100, 100)), true);
+
+        for path in [&circle, &rect] {
+            let tri_list = path.rasterize_to_tri_list(0, 0, 100, 100);
+            let mesh = path.rasterize_to_mesh(0, 0, 100, 100);
+            assert!(matches!(mesh.indices(), MeshIndices::U16(_)));
+            assert_eq!(mesh.indices().len(), tri_list.len());
+            assert!(mesh.vertices().len() < tri_list.len());
+            assert_eq!(calculate_hash(&mesh.to_tri_list()), calculate_hash(&tri_list));
+        }
+
+        // the triangles of a trapezoid and the falloff and interior trapezoids of a row share
+        // their edges, which covers nearly all of the repeated vertices
+        let mut circle = PathBuilder::new();
+        circle.add_circle(200., 200., 180.);
+        let tri_list = circle.rasterize_to_tri_list(0, 0, 400, 400);
+        let mesh = circle.rasterize_to_mesh(0, 0, 400, 400);
+        let unique: std::collections::HashSet<u64> = tri_list.iter().map(calculate_hash).collect();
+        assert!(mesh.vertices().len() <= unique.len() + unique.len() / 100);
+        assert_eq!(calculate_hash(&mesh.to_tri_list()), calculate_hash(&tri_list));
+        // and so do the bands
+        circle.set_band_pool(Some(Arc::new(ThreadPerBand)));
+        circle.set_parallel_bands(4);
+        let banded = circle.rasterize_to_mesh(0, 0, 400, 400);
+        assert_eq!(calculate_hash(&banded.to_tri_list()), calculate_hash(&tri_list));
+        assert_eq!(banded.vertices().len(), mesh.vertices().len());
+
+        let path = circle.get_path().unwrap();
+        let options = RasterizeOptions::new(0, 0, 400, 400);
+        let free = try_rasterize_to_indexed_vertices::<OutputVertex>(path.types(), path.points(), &options).unwrap();
+        assert_eq!(calculate_hash(&free.to_tri_list()), calculate_hash(&tri_list));
+
+        let mut nan = PathBuilder::new();
+        nan.add_rounded_rect(f32::NAN, 10., 40., 40., 0., 0.);
+        assert!(nan.try_rasterize_to_mesh(0, 0, 100, 100).is_err());
+        assert!(nan.rasterize_to_mesh(0, 0, 100, 100).indices().is_empty());
+
+        // 32 bit indices are used once there are too many vertices for 16 bit ones
+        let tri_list: Vec<OutputVertex> = (0..70000).map(|i| OutputVertex { x: i as f32, y: 0., coverage: 1. }).collect();
+        let mesh = Mesh::new(tri_list.clone(), (0..70000).collect());
+        assert!(matches!(mesh.indices(), MeshIndices::U32(_)));
+        assert_eq!(mesh.indices().get(69999), Some(69999));
+        assert_eq!(calculate_hash(&mesh.to_tri_list()), calculate_hash(&tri_list.into_boxed_slice()));
+    }
+
+    #[test]
+    fn opaque_and_fringe() {
+        let mut circle = PathBuilder::new();
+        circle.add_circle(50., 50., 30.);
+        let mut ellipse = PathBuilder::new();
+        ellipse.add_ellipse(50., 50., 40., 10.);
+        ellipse.set_transform(Some(Transform::new(0.8, 0.6, -0.6, 0.8, 0., 0.)));
+        ellipse.set_outside_bounds(Some((0, 0, 100, 100)), true);
+        let mut aliased = PathBuilder::new();
+        aliased.add_circle(50., 50., 30.);
+        aliased.set_antialias_mode(AntialiasMode::Aliased);
+
+        for path in [&circle, &ellipse, &aliased] {
+            let tri_list = path.rasterize_to_tri_list(0, 0, 100, 100);
+            let OpaqueAndFringe { opaque, fringe } = path.rasterize_to_opaque_and_fringe(0, 0, 100, 100);
+            assert!(!opaque.is_empty());
+            assert_eq!(opaque.len() + fringe.len(), tri_list.len());
+            assert!(opaque.iter().all(|v| v.coverage == 1.));
+            // the fringe only has fully covered vertices where a trapezoid's ramp meets its interior
+            assert!(fringe.chunks(3).all(|triangle| triangle.iter().any(|v| v.coverage < 1.)));
+            let mut combined = opaque.to_vec();
+            combined.extend_from_slice(&fringe);
+            assert_eq!(rasterize_to_mask(&combined, 100, 100), rasterize_to_mask(&tri_list, 100, 100));
+        }
+        assert!(aliased.rasterize_to_opaque_and_fringe(0, 0, 100, 100).fringe.is_empty());
+
+        let mut nan = PathBuilder::new();
+        nan.add_rounded_rect(f32::NAN, 10., 40., 40., 0., 0.);
+        assert!(nan.try_rasterize_to_opaque_and_fringe(0, 0, 100, 100).is_err());
+    }
 }
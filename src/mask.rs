@@ -0,0 +1,95 @@
+// Renders the rasterizer's trapezoids and complex scans into an 8-bit coverage mask on
+// the CPU. Trapezoids are sampled at pixel centers the same way that a GPU samples the
+// triangles built from them, so the mask can be used as a reference for GPU output.
+
+use crate::sink::{CoverageSpan, GeometrySink, Trapezoid};
+
+/// An A8 coverage mask that covers the pixels `x..x + width` and `y..y + height`.
+///
+/// Rows are stored top to bottom without padding, so the coverage of pixel (px, py) is at
+/// `data[((py - y) * width + (px - x)) as usize]`. Geometry outside of the mask is
+/// ignored. When geometry overlaps, the larger coverage is kept.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mask {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub data: Box<[u8]>,
+}
+
+fn coverage_to_u8(coverage: f32) -> u8 {
+    (coverage * 255. + 0.5) as u8
+}
+
+impl Mask {
+    /// Creates a mask with zero coverage.
+    pub fn new(x: i32, y: i32, width: u32, height: u32) -> Self {
+        Mask { x, y, width, height, data: vec![0; width as usize * height as usize].into_boxed_slice() }
+    }
+
+    // Returns the range of `data` that holds pixel row y
+    fn row(&self, y: i32) -> Option<std::ops::Range<usize>> {
+        let row = y as i64 - self.y as i64;
+        if row < 0 || row >= self.height as i64 {
+            return None;
+        }
+        let start = row as usize * self.width as usize;
+        Some(start..start + self.width as usize)
+    }
+
+    // Clamps the pixel range x0..x1 to the columns of the mask
+    fn columns(&self, x0: i32, x1: i32) -> std::ops::Range<usize> {
+        let clamp = |x: i32| (x as i64 - self.x as i64).clamp(0, self.width as i64) as usize;
+        clamp(x0)..clamp(x1)
+    }
+}
+
+impl GeometrySink for Mask {
+    fn add_trapezoid(&mut self, t: &Trapezoid) {
+        let height = t.y_bottom - t.y_top;
+        for y in t.y_top.floor() as i32..t.y_bottom.ceil() as i32 {
+            // Sample the edges at the pixel center
+            let center_y = y as f32 + 0.5;
+            if center_y < t.y_top || center_y >= t.y_bottom {
+                continue;
+            }
+            let s = (center_y - t.y_top) / height;
+            let left = t.x_top_left + (t.x_bottom_left - t.x_top_left) * s;
+            let right = t.x_top_right + (t.x_bottom_right - t.x_top_right) * s;
+            let x0 = (left - t.left_delta).floor() as i32;
+            let x1 = (right + t.right_delta).ceil() as i32 + 1;
+            let Some(row) = self.row(y) else {
+                continue;
+            };
+            let columns = self.columns(x0, x1);
+            let row = &mut self.data[row];
+            for column in columns {
+                let center_x = (self.x as i64 + column as i64) as f32 + 0.5;
+                // Coverage ramps from 0 to 1 across each edge. Without a ramp the edges
+                // follow the top-left rule.
+                let left_coverage = if t.left_delta > 0. {
+                    ((center_x - (left - t.left_delta)) / (2. * t.left_delta)).clamp(0., 1.)
+                } else if center_x >= left { 1. } else { 0. };
+                let right_coverage = if t.right_delta > 0. {
+                    ((right + t.right_delta - center_x) / (2. * t.right_delta)).clamp(0., 1.)
+                } else if center_x < right { 1. } else { 0. };
+                let coverage = coverage_to_u8(left_coverage.min(right_coverage));
+                row[column] = row[column].max(coverage);
+            }
+        }
+    }
+
+    fn add_complex_scan(&mut self, y: i32, spans: &[CoverageSpan]) {
+        let Some(row) = self.row(y) else {
+            return;
+        };
+        for span in spans {
+            let columns = self.columns(span.x0, span.x1);
+            let coverage = coverage_to_u8(span.coverage);
+            for pixel in &mut self.data[row.start + columns.start..row.start + columns.end] {
+                *pixel = (*pixel).max(coverage);
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+// Geometry queries on paths: bounds, area, perimeter and hit testing.
+//
+// The queries that depend on the shape of the curves work on the same polylines that the
+// rasterizer fills, see flatten.rs, so that they agree with what is drawn rather than with
+// the exact curves. Areas and hit tests are computed on the 28.4 points directly.
+//
+// contains() counts edge crossings with the rasterizer's fill convention: a sample is
+// inside of an edge's rows when it is at or below the top of the edge and above its bottom,
+// and a span covers the samples from its left edge up to, but not including, its right edge.
+// A point on the top or left side of a shape is inside and a point on the bottom or right
+// side is outside, so abutting shapes never both contain a point on their shared edge.
+
+use crate::flatten::{to_pixel, FlattenedSubpaths};
+use crate::types::POINT;
+use crate::FillMode;
+
+/// An axis aligned rectangle in pixels, see `OutputPath::bounds`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bounds {
+    pub min_x: f32,
+    pub min_y: f32,
+    pub max_x: f32,
+    pub max_y: f32,
+}
+
+impl Bounds {
+    pub fn width(&self) -> f32 {
+        self.max_x - self.min_x
+    }
+
+    pub fn height(&self) -> f32 {
+        self.max_y - self.min_y
+    }
+}
+
+// Grows the minimum and maximum corners of the points seen so far to include point
+fn include_point(extent: Option<(POINT, POINT)>, point: POINT) -> Option<(POINT, POINT)> {
+    let (mut min, mut max) = extent.unwrap_or((point, point));
+    min.x = min.x.min(point.x);
+    min.y = min.y.min(point.y);
+    max.x = max.x.max(point.x);
+    max.y = max.y.max(point.y);
+    Some((min, max))
+}
+
+fn extent_to_bounds((min, max): (POINT, POINT)) -> Bounds {
+    let (min_x, min_y) = to_pixel(min);
+    let (max_x, max_y) = to_pixel(max);
+    Bounds { min_x, min_y, max_x, max_y }
+}
+
+// Returns the bounds of the points in pixels, or None if there are no points
+pub(crate) fn bounds_of(points: impl IntoIterator<Item = POINT>) -> Option<Bounds> {
+    points.into_iter().fold(None, include_point).map(extent_to_bounds)
+}
+
+// Returns the bounds of the flattened path
+pub(crate) fn flattened_bounds(subpaths: FlattenedSubpaths) -> Option<Bounds> {
+    subpaths
+        .fold(None, |extent, subpath| subpath.points().iter().copied().fold(extent, include_point))
+        .map(extent_to_bounds)
+}
+
+// Returns the signed area of the flattened path in square pixels, treating every subpath
+// as closed. Areas that are enclosed clockwise on screen (with y pointing down) are positive.
+pub(crate) fn signed_area(subpaths: FlattenedSubpaths) -> f64 {
+    // Twice the area in square 28.4 units. The products of two 28.4 coordinates can
+    // overflow an i64 once they are summed up, so accumulate them in an i128.
+    let mut twice_area: i128 = 0;
+    for subpath in subpaths {
+        let points = subpath.points();
+        for (i, a) in points.iter().enumerate() {
+            let b = points[(i + 1) % points.len()];
+            twice_area += a.x as i128 * b.y as i128 - b.x as i128 * a.y as i128;
+        }
+    }
+    twice_area as f64 / (2. * 16. * 16.)
+}
+
+// Returns the length of each flattened subpath in pixels. Closed subpaths include the
+// segment back to their start point.
+pub(crate) fn perimeters(subpaths: FlattenedSubpaths) -> Vec<f32> {
+    subpaths.map(|subpath| {
+        let points = subpath.points();
+        let length = |a: &POINT, b: &POINT| ((b.x - a.x) as f64).hypot((b.y - a.y) as f64);
+        let mut perimeter: f64 = points.windows(2).map(|pair| length(&pair[0], &pair[1])).sum();
+        if subpath.is_closed() && points.len() > 1 {
+            perimeter += length(&points[points.len() - 1], &points[0]);
+        }
+        (perimeter / 16.) as f32
+    }).collect()
+}
+
+// Returns whether the pixel space point (x, y) is inside of the flattened path. Every
+// subpath is treated as closed, like when the path is filled.
+pub(crate) fn contains(subpaths: FlattenedSubpaths, x: f32, y: f32, fill_mode: FillMode) -> bool {
+    // Move the point into 28.4 units with the pixel center at 0, like the path's points
+    let x = (x as f64 - 0.5) * 16.;
+    let y = (y as f64 - 0.5) * 16.;
+    let mut winding = 0;
+    for subpath in subpaths {
+        let points = subpath.points();
+        for (i, &a) in points.iter().enumerate() {
+            let b = points[(i + 1) % points.len()];
+            // Orient the edge from top to bottom, horizontal edges don't cover any rows
+            let (top, bottom, direction) = match a.y.cmp(&b.y) {
+                std::cmp::Ordering::Less => (a, b, 1),
+                std::cmp::Ordering::Greater => (b, a, -1),
+                std::cmp::Ordering::Equal => continue,
+            };
+            if y < top.y as f64 || y >= bottom.y as f64 {
+                continue;
+            }
+            // The edge counts if it's strictly to the right of the point at the point's y,
+            // i.e. if the point is strictly to the left of the edge going down.
+            let dx = (bottom.x - top.x) as f64;
+            let dy = (bottom.y - top.y) as f64;
+            if dx * (y - top.y as f64) > (x - top.x as f64) * dy {
+                winding += direction;
+            }
+        }
+    }
+    match fill_mode {
+        FillMode::EvenOdd => winding % 2 != 0,
+        FillMode::Winding => winding != 0,
+    }
+}
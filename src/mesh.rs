@@ -0,0 +1,73 @@
+// An indexed triangle list. The rasterizer emits every trapezoid as two triangles that share
+// an edge, and the falloff and interior trapezoids of a row share their edges with each other.
+// Sharing these vertices through indices cuts down the vertex data, especially for large
+// fills that are mostly made of trapezoids.
+
+use crate::{OutputVertex, Vertex};
+
+/// The index buffer of a `Mesh`. 16 bit indices are used when they can address all of
+/// the vertices.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MeshIndices {
+    U16(Box<[u16]>),
+    U32(Box<[u32]>),
+}
+
+impl Default for MeshIndices {
+    fn default() -> Self {
+        MeshIndices::U16(Default::default())
+    }
+}
+
+impl MeshIndices {
+    pub fn len(&self) -> usize {
+        match self {
+            MeshIndices::U16(indices) => indices.len(),
+            MeshIndices::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<u32> {
+        match self {
+            MeshIndices::U16(indices) => indices.get(index).map(|&i| i as u32),
+            MeshIndices::U32(indices) => indices.get(index).copied(),
+        }
+    }
+}
+
+/// A triangle list with shared vertices. Every three indices form a triangle, in the same
+/// order as in the triangle list of the same path.
+#[derive(Clone, Debug, Default)]
+pub struct Mesh<V = OutputVertex> {
+    pub(crate) vertices: Box<[V]>,
+    pub(crate) indices: MeshIndices,
+}
+
+impl<V: Vertex> Mesh<V> {
+    // Uses 16 bit indices when they can address all of the vertices
+    pub(crate) fn new(vertices: Vec<V>, indices: Vec<u32>) -> Self {
+        let indices = if vertices.len() <= u16::MAX as usize + 1 {
+            MeshIndices::U16(indices.into_iter().map(|index| index as u16).collect())
+        } else {
+            MeshIndices::U32(indices.into_boxed_slice())
+        };
+        Mesh { vertices: vertices.into_boxed_slice(), indices }
+    }
+
+    pub fn vertices(&self) -> &[V] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &MeshIndices {
+        &self.indices
+    }
+
+    /// Expands the mesh back into a triangle list.
+    pub fn to_tri_list(&self) -> Box<[V]> {
+        (0..self.indices.len()).map(|i| self.vertices[self.indices.get(i).unwrap() as usize].clone()).collect()
+    }
+}
@@ -0,0 +1,246 @@
+// Clips paths whose coordinates don't fit into the rasterizer's 28.4 fixed point range.
+//
+// The path is clipped in floating point against a slightly enlarged clip rect and is
+// translated so that the origin of the clip rect becomes (0, 0). Each subpath is clipped as
+// a closed polygon, which keeps the winding numbers inside of the clip rect, so the visible
+// part of the fill doesn't change. Curves that lie within the clip rect are kept as curves.
+// Quadratic curves are elevated to cubics.
+
+use crate::aarasterizer::CheckValidRange28_4;
+use crate::real::CFloatFPU;
+use crate::types::*;
+use crate::Transform;
+
+// Clipping adds edges along the enlarged clip rect. Keep them far enough away from the
+// clip rect that antialiasing doesn't make them visible.
+const PADDING: f64 = 2.;
+
+// Curves that cross the clip rect are subdivided until the pieces are within this many
+// pixels of a straight line, or until MAX_CURVE_DEPTH.
+const CURVE_TOLERANCE: f64 = 1. / 16.;
+const MAX_CURVE_DEPTH: u32 = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Point {
+    x: f64,
+    y: f64,
+}
+
+impl Point {
+    fn lerp(self, other: Point, t: f64) -> Point {
+        Point { x: self.x + (other.x - self.x) * t, y: self.y + (other.y - self.y) * t }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Segment {
+    Line(Point),
+    Bezier(Point, Point, Point),
+}
+
+impl Segment {
+    fn end(&self) -> Point {
+        match *self {
+            Segment::Line(end) | Segment::Bezier(_, _, end) => end,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Rect {
+    left: f64,
+    top: f64,
+    right: f64,
+    bottom: f64,
+}
+
+// One of the half planes that make up the clip rect
+#[derive(Clone, Copy, Debug)]
+enum Side {
+    Left(f64),
+    Top(f64),
+    Right(f64),
+    Bottom(f64),
+}
+
+impl Side {
+    fn inside(self, p: Point) -> bool {
+        match self {
+            Side::Left(x) => p.x >= x,
+            Side::Top(y) => p.y >= y,
+            Side::Right(x) => p.x <= x,
+            Side::Bottom(y) => p.y <= y,
+        }
+    }
+
+    // Intersects the line from a to b, which crosses the side, with the side
+    fn intersect(self, a: Point, b: Point) -> Point {
+        match self {
+            Side::Left(x) | Side::Right(x) => Point { x, y: a.y + (b.y - a.y) * (x - a.x) / (b.x - a.x) },
+            Side::Top(y) | Side::Bottom(y) => Point { x: a.x + (b.x - a.x) * (y - a.y) / (b.y - a.y), y },
+        }
+    }
+}
+
+// Adds the curve from start to end. The parts of the curve that are outside of rect are
+// replaced by lines, which are clipped later. A curve whose control points are outside of
+// the rect encloses a region with its chord that is outside of the rect as well, so doing
+// that doesn't change the winding numbers inside of the rect.
+fn add_curve(start: Point, c1: Point, c2: Point, end: Point, rect: &Rect, depth: u32, segments: &mut Vec<Segment>) {
+    let points = [start, c1, c2, end];
+    let left = points.iter().map(|p| p.x).fold(f64::INFINITY, f64::min);
+    let right = points.iter().map(|p| p.x).fold(f64::NEG_INFINITY, f64::max);
+    let top = points.iter().map(|p| p.y).fold(f64::INFINITY, f64::min);
+    let bottom = points.iter().map(|p| p.y).fold(f64::NEG_INFINITY, f64::max);
+
+    if left >= rect.left && right <= rect.right && top >= rect.top && bottom <= rect.bottom {
+        segments.push(Segment::Bezier(c1, c2, end));
+    } else if left > rect.right || right < rect.left || top > rect.bottom || bottom < rect.top
+        || (right - left).max(bottom - top) <= CURVE_TOLERANCE
+        || depth == MAX_CURVE_DEPTH
+    {
+        segments.push(Segment::Line(end));
+    } else {
+        // Split the curve in half with de Casteljau's algorithm
+        let (p01, p12, p23) = (start.lerp(c1, 0.5), c1.lerp(c2, 0.5), c2.lerp(end, 0.5));
+        let (p012, p123) = (p01.lerp(p12, 0.5), p12.lerp(p23, 0.5));
+        let mid = p012.lerp(p123, 0.5);
+        add_curve(start, p01, p012, mid, rect, depth + 1, segments);
+        add_curve(mid, p123, p23, end, rect, depth + 1, segments);
+    }
+}
+
+// Clips the closed polygon that is made of segments against side with the
+// Sutherland-Hodgman algorithm. Each segment starts at the end of the previous one and the
+// first one starts at the end of the last one. Curves have to be inside of the side.
+fn clip_polygon(segments: &[Segment], side: Side, clipped: &mut Vec<Segment>) {
+    clipped.clear();
+    let Some(last) = segments.last() else {
+        return;
+    };
+    let mut start = last.end();
+    for &segment in segments {
+        let end = segment.end();
+        match segment {
+            Segment::Bezier(..) => clipped.push(segment),
+            Segment::Line(_) => match (side.inside(start), side.inside(end)) {
+                (true, true) => clipped.push(segment),
+                (true, false) => clipped.push(Segment::Line(side.intersect(start, end))),
+                (false, true) => {
+                    clipped.push(Segment::Line(side.intersect(start, end)));
+                    clipped.push(segment);
+                }
+                (false, false) => {}
+            },
+        }
+        start = end;
+    }
+}
+
+// Clips the path, given in pixels, against the clip rect and returns it in 28.4 with the
+// origin of the clip rect at (0, 0). The transform is applied before clipping. Returns None
+// if the clipped path still doesn't fit into the 28.4 range for a subpixel grid with
+// nShift, i.e. when the clip rect itself is too large.
+pub(crate) fn preclip(
+    types: &[BYTE],
+    points: &[MilPoint2F],
+    transform: Option<Transform>,
+    clip_x: i32,
+    clip_y: i32,
+    clip_width: i32,
+    clip_height: i32,
+    nShift: INT,
+) -> Option<(Vec<BYTE>, Vec<POINT>)> {
+    let transform = transform.unwrap_or_default();
+    let point = |i: usize| {
+        let (x, y) = transform.transform_point_f64(points[i].X, points[i].Y);
+        Point { x, y }
+    };
+    let rect = Rect {
+        left: clip_x as f64 - PADDING,
+        top: clip_y as f64 - PADDING,
+        right: clip_x as f64 + clip_width as f64 + PADDING,
+        bottom: clip_y as f64 + clip_height as f64 + PADDING,
+    };
+
+    let mut out_types = Vec::new();
+    let mut out_points = Vec::new();
+    let mut add_point = |p: Point| {
+        // Translate to the clip origin and convert to 28.4 like PathBuilder::add_point
+        let x = ((p.x - clip_x as f64 - 0.5) * 16.) as f32;
+        let y = ((p.y - clip_y as f64 - 0.5) * 16.) as f32;
+        out_points.push(POINT { x: CFloatFPU::Round(x), y: CFloatFPU::Round(y) });
+        CheckValidRange28_4(x, y, nShift)
+    };
+
+    let mut segments = Vec::new();
+    let mut clipped = Vec::new();
+    let mut subpath_start = Point { x: 0., y: 0. };
+    let mut i_point = 0;
+    let mut i_type = 0;
+    while i_type <= types.len() {
+        let ty = types.get(i_type).copied();
+        let ends_subpath = match ty {
+            Some(ty) => ty & PathPointTypePathTypeMask == PathPointTypeStart,
+            None => true,
+        };
+        if ends_subpath && !segments.is_empty() {
+            // Close and clip the previous subpath
+            segments.push(Segment::Line(subpath_start));
+            for side in [Side::Left(rect.left), Side::Top(rect.top), Side::Right(rect.right), Side::Bottom(rect.bottom)] {
+                clip_polygon(&segments, side, &mut clipped);
+                std::mem::swap(&mut segments, &mut clipped);
+            }
+            if let Some(last) = segments.last() {
+                out_types.push(PathPointTypeStart);
+                let mut valid = add_point(last.end());
+                for segment in &segments {
+                    match *segment {
+                        Segment::Line(end) => {
+                            out_types.push(PathPointTypeLine);
+                            valid &= add_point(end);
+                        }
+                        Segment::Bezier(c1, c2, end) => {
+                            out_types.push(PathPointTypeBezier);
+                            valid &= add_point(c1);
+                            valid &= add_point(c2);
+                            valid &= add_point(end);
+                        }
+                    }
+                }
+                if !valid {
+                    return None;
+                }
+                *out_types.last_mut().unwrap() |= PathPointTypeCloseSubpath;
+            }
+            segments.clear();
+        }
+        let Some(ty) = ty else {
+            break;
+        };
+        match ty & PathPointTypePathTypeMask {
+            PathPointTypeStart => {
+                subpath_start = point(i_point);
+                i_point += 1;
+            }
+            PathPointTypeLine => {
+                segments.push(Segment::Line(point(i_point)));
+                i_point += 1;
+            }
+            PathPointTypeBezier => {
+                let start = if i_point > 0 { point(i_point - 1) } else { subpath_start };
+                add_curve(start, point(i_point), point(i_point + 1), point(i_point + 2), &rect, 0, &mut segments);
+                i_point += 3;
+            }
+            PathPointTypeQuadratic => {
+                let start = if i_point > 0 { point(i_point - 1) } else { subpath_start };
+                let (control, end) = (point(i_point), point(i_point + 1));
+                add_curve(start, start.lerp(control, 2. / 3.), end.lerp(control, 2. / 3.), end, &rect, 0, &mut segments);
+                i_point += 2;
+            }
+            _ => unreachable!("unknown path point type"),
+        }
+        i_type += 1;
+    }
+    Some((out_types, out_points))
+}
@@ -0,0 +1,33 @@
+// A rasterizer context that keeps its buffers around between paths. Rasterizing a path
+// allocates the edge arena, the coverage intervals, the inactive edge array, the point
+// arrays and the output triangle list. When many paths are drawn every frame those
+// allocations add up, so Rasterizer holds on to them and hands them to the next path.
+
+use crate::hwrasterizer::CRasterizerStorage;
+use crate::{OutputVertex, PathBuilder, RasterError};
+
+#[derive(Default)]
+pub struct Rasterizer {
+    storage: CRasterizerStorage,
+    vertices: Vec<OutputVertex>,
+}
+
+impl Rasterizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Like `PathBuilder::rasterize_to_tri_list`, but the triangles are written into a
+    /// buffer owned by the rasterizer, which is reused by the next call.
+    pub fn rasterize_to_tri_list(&mut self, path: &PathBuilder, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> &[OutputVertex] {
+        // Failures leave the buffer empty
+        let _ = path.build_vertices(&mut self.storage, clip_x, clip_y, clip_width, clip_height, &mut self.vertices);
+        &self.vertices
+    }
+
+    /// Like `PathBuilder::try_rasterize_to_tri_list`, see `rasterize_to_tri_list`.
+    pub fn try_rasterize_to_tri_list(&mut self, path: &PathBuilder, clip_x: i32, clip_y: i32, clip_width: i32, clip_height: i32) -> Result<&[OutputVertex], RasterError> {
+        path.build_vertices(&mut self.storage, clip_x, clip_y, clip_width, clip_height, &mut self.vertices)?;
+        Ok(&self.vertices)
+    }
+}
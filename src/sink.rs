@@ -0,0 +1,157 @@
+// A public, safe version of the rasterizer's internal IGeometrySink. Instead of building
+// triangles, the rasterizer's trapezoids and complex scan spans are handed straight to a
+// GeometrySink implementation.
+
+use crate::aacoverage::{CCoverageInterval, CSubpixelGrid};
+use crate::geometry_sink::IGeometrySink;
+use crate::nullable_ref::Ref;
+use crate::types::*;
+
+/// A trapezoid with horizontal top and bottom edges.
+///
+/// Coverage is 1.0 between the left and right edges and falls off linearly to 0.0
+/// over `left_delta` and `right_delta` on either side of them. That is, coverage is 0.0
+/// at `x_top_left - left_delta` and 1.0 at `x_top_left + left_delta`. The deltas are
+/// zero for aliased rasterization.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Trapezoid {
+    pub y_top: f32,
+    pub y_bottom: f32,
+    pub x_top_left: f32,
+    pub x_top_right: f32,
+    pub x_bottom_left: f32,
+    pub x_bottom_right: f32,
+    pub left_delta: f32,
+    pub right_delta: f32,
+}
+
+/// A run of pixels in a single row, `x0..x1`, that all have the same coverage.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CoverageSpan {
+    pub x0: i32,
+    pub x1: i32,
+    pub coverage: f32,
+}
+
+/// Receives the geometry of a rasterized path.
+///
+/// Parts of the path with simple edges are output as trapezoids. The remaining pixel
+/// rows are output as complex scans, which list the spans with non-zero coverage.
+/// Trapezoids and scans don't overlap.
+pub trait GeometrySink {
+    fn add_trapezoid(&mut self, trapezoid: &Trapezoid);
+
+    /// Adds the spans of pixel row `y`, sorted by x. The spans don't overlap.
+    fn add_complex_scan(&mut self, y: i32, spans: &[CoverageSpan]);
+}
+
+// Adapts a GeometrySink to the interface that CHwRasterizer outputs to.
+pub(crate) struct SinkAdapter<'a> {
+    sink: &'a mut dyn GeometrySink,
+    spans: Vec<CoverageSpan>,
+    full_coverage: f32,
+    empty: bool,
+}
+
+impl<'a> SinkAdapter<'a> {
+    pub(crate) fn new(sink: &'a mut dyn GeometrySink) -> Self {
+        SinkAdapter {
+            sink,
+            spans: Vec::new(),
+            full_coverage: CSubpixelGrid::default().nShiftSizeSquared as f32,
+            empty: true,
+        }
+    }
+}
+
+impl IGeometrySink for SinkAdapter<'_> {
+    fn AddComplexScan(&mut self, nPixelY: INT, pIntervalSpanStart: Ref<CCoverageInterval>) -> HRESULT {
+        self.spans.clear();
+        let mut interval = pIntervalSpanStart;
+        while interval.m_nPixelX.get() != INT::MAX {
+            let next = interval.m_pNext.get();
+            let coverage = interval.m_nCoverage.get();
+            if coverage != 0 {
+                self.spans.push(CoverageSpan {
+                    x0: interval.m_nPixelX.get(),
+                    x1: next.m_nPixelX.get(),
+                    coverage: coverage as f32 / self.full_coverage,
+                });
+            }
+            interval = next;
+        }
+        if !self.spans.is_empty() {
+            self.empty = false;
+            self.sink.add_complex_scan(nPixelY, &self.spans);
+        }
+        S_OK
+    }
+
+    fn AddTrapezoid(
+        &mut self,
+        rYMin: f32,
+        rXLeftYMin: f32,
+        rXRightYMin: f32,
+        rYMax: f32,
+        rXLeftYMax: f32,
+        rXRightYMax: f32,
+        rXDeltaLeft: f32,
+        rXDeltaRight: f32,
+    ) -> HRESULT {
+        self.empty = false;
+        self.sink.add_trapezoid(&Trapezoid {
+            y_top: rYMin,
+            y_bottom: rYMax,
+            x_top_left: rXLeftYMin,
+            x_top_right: rXRightYMin,
+            x_bottom_left: rXLeftYMax,
+            x_bottom_right: rXRightYMax,
+            left_delta: rXDeltaLeft,
+            right_delta: rXDeltaRight,
+        });
+        S_OK
+    }
+
+    fn IsEmpty(&self) -> bool {
+        self.empty
+    }
+
+    fn SetSubpixelGrid(&mut self, grid: CSubpixelGrid) {
+        self.full_coverage = grid.nShiftSizeSquared as f32;
+    }
+}
+
+// Moves the geometry that is passed to sink by (dx, dy).
+pub(crate) struct OffsetSink<'a> {
+    sink: &'a mut dyn GeometrySink,
+    dx: i32,
+    dy: i32,
+    spans: Vec<CoverageSpan>,
+}
+
+impl<'a> OffsetSink<'a> {
+    pub(crate) fn new(sink: &'a mut dyn GeometrySink, dx: i32, dy: i32) -> Self {
+        OffsetSink { sink, dx, dy, spans: Vec::new() }
+    }
+}
+
+impl GeometrySink for OffsetSink<'_> {
+    fn add_trapezoid(&mut self, t: &Trapezoid) {
+        let (dx, dy) = (self.dx as f32, self.dy as f32);
+        self.sink.add_trapezoid(&Trapezoid {
+            y_top: t.y_top + dy,
+            y_bottom: t.y_bottom + dy,
+            x_top_left: t.x_top_left + dx,
+            x_top_right: t.x_top_right + dx,
+            x_bottom_left: t.x_bottom_left + dx,
+            x_bottom_right: t.x_bottom_right + dx,
+            ..*t
+        });
+    }
+
+    fn add_complex_scan(&mut self, y: i32, spans: &[CoverageSpan]) {
+        self.spans.clear();
+        self.spans.extend(spans.iter().map(|span| CoverageSpan { x0: span.x0 + self.dx, x1: span.x1 + self.dx, ..*span }));
+        self.sink.add_complex_scan(y + self.dy, &self.spans);
+    }
+}
@@ -0,0 +1,455 @@
+// Converts the outline of a path into a fill path that covers its stroke.
+//
+// Each subpath is flattened and then offset by half the stroke width on both sides. An open
+// subpath becomes a single contour made of one side, the end cap, the other side and the
+// start cap. A closed subpath becomes two contours, one for each side. Joins are only added
+// on the outer side of a turn; the inner side is routed through the vertex itself which
+// leaves some overlap but keeps every contour oriented the same way. The result is meant to
+// be filled with the nonzero winding rule so that all of this overlap is merged into a
+// single coverage computation instead of being drawn multiple times.
+//
+// Dashing happens on the flattened subpaths before they are outlined. Each dash is walked
+// out by arc length and then outlined as its own open subpath. On a closed subpath the dash
+// pattern continues through the start point, so the dashes on either side of it are one.
+
+use std::f32::consts::PI;
+use std::ops::{Add, Mul, Neg, Sub};
+
+use crate::error::RasterError;
+use crate::flatten::{for_each_flattened_float_subpath, for_each_flattened_subpath, to_pixel};
+use crate::types::{MilPoint2F, BYTE, POINT};
+use crate::{FillMode, Flattening, OutputPath, PathBuilder};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LineJoin {
+    Miter = 0,
+    Round = 1,
+    Bevel = 2,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LineCap {
+    Butt = 0,
+    Round = 1,
+    Square = 2,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StrokeStyle {
+    pub width: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    /// The maximum ratio of the miter length to the stroke width. Miter joins that
+    /// exceed this are drawn as bevel joins instead.
+    pub miter_limit: f32,
+    /// Alternating lengths of dashes and gaps, like SVG's `stroke-dasharray`. An odd number
+    /// of lengths is repeated to get an even number. An empty array, or one that has negative
+    /// lengths or sums to zero, draws a solid stroke.
+    pub dash_array: Vec<f32>,
+    /// The distance into the dash pattern at which each subpath starts, like SVG's
+    /// `stroke-dashoffset`.
+    pub dash_offset: f32,
+}
+
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        StrokeStyle {
+            width: 1.,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 10.,
+            dash_array: Vec::new(),
+            dash_offset: 0.,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) struct Vector {
+    x: f32,
+    y: f32,
+}
+
+impl Vector {
+    fn new(x: f32, y: f32) -> Self {
+        Vector { x, y }
+    }
+    fn length(self) -> f32 {
+        self.x.hypot(self.y)
+    }
+    fn normalize(self) -> Self {
+        self * (1. / self.length())
+    }
+    // Rotates by 90 degrees.
+    fn perp(self) -> Self {
+        Vector::new(-self.y, self.x)
+    }
+    fn dot(self, other: Vector) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+    fn cross(self, other: Vector) -> f32 {
+        self.x * other.y - self.y * other.x
+    }
+}
+
+impl Add for Vector {
+    type Output = Vector;
+    fn add(self, other: Vector) -> Vector {
+        Vector::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Vector {
+    type Output = Vector;
+    fn sub(self, other: Vector) -> Vector {
+        Vector::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Mul<f32> for Vector {
+    type Output = Vector;
+    fn mul(self, scale: f32) -> Vector {
+        Vector::new(self.x * scale, self.y * scale)
+    }
+}
+
+impl Neg for Vector {
+    type Output = Vector;
+    fn neg(self) -> Vector {
+        Vector::new(-self.x, -self.y)
+    }
+}
+
+// Patterns that would produce more dashes than this on a single subpath are drawn solid
+// instead, so that a tiny dash array can't make us generate an unbounded amount of geometry.
+const MAX_DASHES_PER_SUBPATH: f32 = 100000.;
+
+pub struct Stroker {
+    style: StrokeStyle,
+}
+
+impl Stroker {
+    pub fn new(style: StrokeStyle) -> Self {
+        Stroker { style }
+    }
+
+    pub fn style(&self) -> &StrokeStyle {
+        &self.style
+    }
+
+    /// Returns a path that covers the stroke of `path`. Curves in `path` are flattened like
+    /// `OutputPath::bounds` flattens them. The returned path uses the winding fill mode.
+    pub fn stroke_path(&self, path: &OutputPath) -> PathBuilder {
+        let mut out = PathBuilder::new();
+        out.set_fill_mode(FillMode::Winding);
+        self.stroke(&path.types, &path.points, path.flattening, &mut out);
+        out
+    }
+
+    /// Like `stroke_path` but the returned path also inherits the transform, clip path,
+    /// texture coordinate transforms, outside bounds and rasterization settings of `builder`.
+    /// Curves in `builder` are flattened with its flattening settings. Paths with points that
+    /// don't fit into the rasterizer's range are stroked in floating point, and the stroke is
+    /// clipped when it is rasterized like any other path with such points.
+    pub fn stroke_builder(&self, builder: &PathBuilder) -> PathBuilder {
+        let mut out = PathBuilder::new();
+        out.set_fill_mode(FillMode::Winding);
+        out.outside_bounds = builder.outside_bounds.clone();
+        out.need_inside = builder.need_inside;
+        out.rasterization_truncates = builder.rasterization_truncates;
+        out.transform = builder.transform;
+        out.clip_path = builder.clip_path.clone();
+        out.uv_transforms = builder.uv_transforms;
+        out.parallel_bands = builder.parallel_bands;
+        out.band_pool = builder.band_pool.clone();
+        out.flattening = builder.flattening;
+        // The transform is applied to the outline as it is added to `out`
+        let (points, range_error) = builder.untransformed_points();
+        match range_error {
+            None => self.stroke(&builder.types, &points, builder.flattening, &mut out),
+            Some(RasterError::OutOfRange { .. }) => {
+                self.stroke_float(&builder.types, &builder.source_points, builder.flattening.tolerance, &mut out)
+            }
+            Some(error) => out.range_error = Some(error),
+        }
+        out
+    }
+
+    fn stroke(&self, types: &[BYTE], points: &[POINT], flattening: Flattening, out: &mut PathBuilder) {
+        if self.style.width.is_nan() || self.style.width <= 0. {
+            return;
+        }
+        let dash_pattern = self.dash_pattern();
+        let mut polyline: Vec<Vector> = Vec::new();
+        for_each_flattened_subpath(types, points, flattening, |subpath, closed| {
+            let subpath = subpath.iter().map(|&point| {
+                let (x, y) = to_pixel(point);
+                Vector::new(x, y)
+            });
+            self.stroke_subpath(subpath, closed, dash_pattern.as_deref(), &mut polyline, out);
+        });
+    }
+
+    // Like stroke, for points in pixels that don't fit into the 28.4 range
+    fn stroke_float(&self, types: &[BYTE], points: &[MilPoint2F], tolerance: f32, out: &mut PathBuilder) {
+        if self.style.width.is_nan() || self.style.width <= 0. {
+            return;
+        }
+        let dash_pattern = self.dash_pattern();
+        let mut polyline: Vec<Vector> = Vec::new();
+        for_each_flattened_float_subpath(types, points, tolerance, |subpath, closed| {
+            let subpath = subpath.iter().map(|point| Vector::new(point.X, point.Y));
+            self.stroke_subpath(subpath, closed, dash_pattern.as_deref(), &mut polyline, out);
+        });
+    }
+
+    // Outlines one flattened subpath. `polyline` is scratch space that is reused between
+    // subpaths.
+    fn stroke_subpath(&self, subpath: impl Iterator<Item = Vector>, closed: bool, dash_pattern: Option<&[f32]>,
+                      polyline: &mut Vec<Vector>, out: &mut PathBuilder) {
+        polyline.clear();
+        for point in subpath {
+            if polyline.last() != Some(&point) {
+                polyline.push(point);
+            }
+        }
+        if closed && polyline.len() > 1 && polyline.first() == polyline.last() {
+            polyline.pop();
+        }
+        match dash_pattern {
+            Some(pattern) => self.dash_polyline(polyline, closed, pattern, out),
+            None => self.stroke_polyline(polyline, closed, out),
+        }
+    }
+
+    // Returns the dash pattern with an even number of entries or None if the stroke is solid.
+    fn dash_pattern(&self) -> Option<Vec<f32>> {
+        let dash_array = &self.style.dash_array;
+        if dash_array.is_empty() || dash_array.iter().any(|&length| !(length >= 0. && length.is_finite())) {
+            return None;
+        }
+        let mut pattern = dash_array.clone();
+        if pattern.len() % 2 == 1 {
+            pattern.extend_from_slice(dash_array);
+        }
+        let total: f32 = pattern.iter().sum();
+        if !(total > 0. && total.is_finite()) {
+            return None;
+        }
+        Some(pattern)
+    }
+
+    // Splits `polyline` into dashes following `pattern` and outlines each of them.
+    fn dash_polyline(&self, polyline: &[Vector], closed: bool, pattern: &[f32], out: &mut PathBuilder) {
+        let count = polyline.len();
+        let segments = if closed { count } else { count - 1 };
+        let segment = |i: usize| (polyline[i], polyline[(i + 1) % count]);
+
+        let total: f32 = pattern.iter().sum();
+        let length: f32 = (0..segments).map(|i| { let (a, b) = segment(i); (b - a).length() }).sum();
+        if length / total * pattern.len() as f32 > MAX_DASHES_PER_SUBPATH {
+            self.stroke_polyline(polyline, closed, out);
+            return;
+        }
+
+        // Find where in the pattern the subpath starts
+        let offset = if self.style.dash_offset.is_finite() { self.style.dash_offset } else { 0. };
+        let mut offset = offset % total;
+        if offset < 0. {
+            offset += total;
+        }
+        let mut index = 0;
+        while offset >= pattern[index] {
+            offset -= pattern[index];
+            index = (index + 1) % pattern.len();
+        }
+        let mut remaining = pattern[index] - offset;
+
+        let mut dash: Vec<Vector> = Vec::new();
+        if index % 2 == 0 {
+            dash.push(polyline[0]);
+        }
+        // On a closed subpath a dash that runs through the start point is a single dash.
+        // Its first part is held back until the last dash is known.
+        let crosses_start = closed && index % 2 == 0;
+        let mut first_dash: Option<Vec<Vector>> = None;
+        for i in 0..segments {
+            let (start, end) = segment(i);
+            let segment_length = (end - start).length();
+            let mut position = 0.;
+            while segment_length - position > remaining {
+                position += remaining;
+                let point = start + (end - start) * (position / segment_length);
+                dash.push(point);
+                if index % 2 == 0 {
+                    if crosses_start && first_dash.is_none() {
+                        first_dash = Some(std::mem::take(&mut dash));
+                    } else {
+                        self.stroke_dash(&mut dash, out);
+                    }
+                }
+                index = (index + 1) % pattern.len();
+                remaining = pattern[index];
+            }
+            remaining -= segment_length - position;
+            if index % 2 == 0 {
+                dash.push(end);
+            }
+        }
+        match first_dash {
+            Some(mut first_dash) if index % 2 == 0 => {
+                // The last dash ends on the start point, where the first dash begins
+                dash.append(&mut first_dash);
+                self.stroke_dash(&mut dash, out);
+            }
+            Some(mut first_dash) => self.stroke_dash(&mut first_dash, out),
+            // The pattern never turned off, so the whole subpath is one dash without caps
+            None if crosses_start => self.stroke_polyline(polyline, true, out),
+            None => {
+                if index % 2 == 0 {
+                    self.stroke_dash(&mut dash, out);
+                }
+            }
+        }
+    }
+
+    fn stroke_dash(&self, dash: &mut Vec<Vector>, out: &mut PathBuilder) {
+        dash.dedup();
+        if !dash.is_empty() {
+            self.stroke_polyline(dash, false, out);
+        }
+        dash.clear();
+    }
+
+    fn stroke_polyline(&self, polyline: &[Vector], closed: bool, out: &mut PathBuilder) {
+        let half_width = self.style.width / 2.;
+        if polyline.len() == 1 {
+            self.add_dot(polyline[0], half_width, out);
+            return;
+        }
+        if closed {
+            for side in [polyline.to_vec(), polyline.iter().rev().cloned().collect()] {
+                let start = side[0] + (side[1] - side[0]).normalize().perp() * half_width;
+                out.move_to(start.x, start.y);
+                self.add_side(&side, true, half_width, out);
+                out.close();
+            }
+        } else {
+            let reversed: Vec<Vector> = polyline.iter().rev().cloned().collect();
+            let start_direction = (polyline[1] - polyline[0]).normalize();
+            let end_direction = (reversed[0] - reversed[1]).normalize();
+            let start = polyline[0] + start_direction.perp() * half_width;
+            out.move_to(start.x, start.y);
+            self.add_side(polyline, false, half_width, out);
+            self.add_cap(reversed[0], end_direction, half_width, out);
+            self.add_side(&reversed, false, half_width, out);
+            self.add_cap(polyline[0], -start_direction, half_width, out);
+            out.close();
+        }
+    }
+
+    // Emits the offset of one side of `points` after its first point. Open sides end with
+    // the offset of the last point and closed sides end back at their first point.
+    fn add_side(&self, points: &[Vector], closed: bool, half_width: f32, out: &mut PathBuilder) {
+        let count = points.len();
+        let segments = if closed { count } else { count - 1 };
+        let direction = |i: usize| (points[(i + 1) % count] - points[i]).normalize();
+        let mut previous = direction(0);
+        for i in 1..=segments {
+            if i == count - 1 && !closed {
+                let end = points[i] + previous.perp() * half_width;
+                out.line_to(end.x, end.y);
+                break;
+            }
+            let next = direction(i % count);
+            self.add_join(points[i % count], previous, next, half_width, out);
+            previous = next;
+        }
+    }
+
+    // Connects the offset of the segment ending at `point` going in `incoming` with the
+    // offset of the segment starting at `point` going in `outgoing`.
+    fn add_join(&self, point: Vector, incoming: Vector, outgoing: Vector, half_width: f32, out: &mut PathBuilder) {
+        let normal_in = incoming.perp();
+        let normal_out = outgoing.perp();
+        let cross = incoming.cross(outgoing);
+        let dot = incoming.dot(outgoing);
+        let start = point + normal_in * half_width;
+        let end = point + normal_out * half_width;
+        out.line_to(start.x, start.y);
+
+        if cross == 0. && dot > 0. {
+            // Collinear segments don't need a join
+            return;
+        }
+        if cross > 0. {
+            // This is the inner side of the turn
+            out.line_to(point.x, point.y);
+            out.line_to(end.x, end.y);
+            return;
+        }
+
+        match self.style.join {
+            LineJoin::Miter => {
+                // The ratio of the miter length to the stroke width is 1/sin(theta/2) where
+                // theta is the angle between the segments, which works out to sqrt(2/(1 + dot)).
+                let limit = self.style.miter_limit;
+                if 1. + dot > 0. && 2. <= limit * limit * (1. + dot) {
+                    let miter = point + (normal_in + normal_out) * (half_width / (1. + dot));
+                    out.line_to(miter.x, miter.y);
+                }
+                out.line_to(end.x, end.y);
+            }
+            LineJoin::Round => {
+                let start_angle = normal_in.y.atan2(normal_in.x);
+                out.append_arc(point.x, point.y, half_width, half_width, 0., start_angle, -cross.abs().atan2(dot));
+            }
+            LineJoin::Bevel => {
+                out.line_to(end.x, end.y);
+            }
+        }
+    }
+
+    // Connects the offset on the left of `direction` at `point` to the offset on the right.
+    fn add_cap(&self, point: Vector, direction: Vector, half_width: f32, out: &mut PathBuilder) {
+        let normal = direction.perp() * half_width;
+        let end = point - normal;
+        match self.style.cap {
+            LineCap::Butt => {}
+            LineCap::Round => {
+                out.append_arc(point.x, point.y, half_width, half_width, 0., normal.y.atan2(normal.x), -PI);
+                return;
+            }
+            LineCap::Square => {
+                let extension = direction * half_width;
+                let corner0 = point + normal + extension;
+                let corner1 = point - normal + extension;
+                out.line_to(corner0.x, corner0.y);
+                out.line_to(corner1.x, corner1.y);
+            }
+        }
+        out.line_to(end.x, end.y);
+    }
+
+    // Zero length subpaths get a dot for round and square caps. These are wound the same
+    // way as the rest of the outlines so that they don't cancel out overlapping strokes.
+    fn add_dot(&self, point: Vector, half_width: f32, out: &mut PathBuilder) {
+        match self.style.cap {
+            LineCap::Butt => {}
+            LineCap::Round => {
+                out.move_to(point.x + half_width, point.y);
+                out.append_arc(point.x, point.y, half_width, half_width, 0., 0., -2. * PI);
+                out.close();
+            }
+            LineCap::Square => {
+                out.move_to(point.x - half_width, point.y + half_width);
+                out.line_to(point.x + half_width, point.y + half_width);
+                out.line_to(point.x + half_width, point.y - half_width);
+                out.line_to(point.x - half_width, point.y - half_width);
+                out.close();
+            }
+        }
+    }
+}
@@ -0,0 +1,258 @@
+// Parses SVG path data (the `d` attribute) into a PathBuilder.
+//
+// This follows the grammar from the "Path data" chapter of the SVG spec, including implicit
+// repeated commands, numbers that aren't separated by whitespace ("1-2.5.5") and flags that
+// aren't separated from the following number ("a1 1 0 00.5.5").
+
+use std::fmt;
+
+use crate::PathBuilder;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SvgPathErrorKind {
+    /// Path data has to start with a moveto command.
+    ExpectedMoveTo,
+    /// Found something that is neither a command nor the start of a number.
+    ExpectedCommand,
+    /// A command was missing one of its arguments.
+    ExpectedNumber,
+    /// An arc's large-arc or sweep flag wasn't a `0` or a `1`.
+    ExpectedFlag,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SvgPathError {
+    pub kind: SvgPathErrorKind,
+    /// The byte offset into the path data where the error was found.
+    pub offset: usize,
+}
+
+impl fmt::Display for SvgPathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self.kind {
+            SvgPathErrorKind::ExpectedMoveTo => "expected a moveto command",
+            SvgPathErrorKind::ExpectedCommand => "expected a path command",
+            SvgPathErrorKind::ExpectedNumber => "expected a number",
+            SvgPathErrorKind::ExpectedFlag => "expected a flag",
+        };
+        write!(f, "{} at offset {}", description, self.offset)
+    }
+}
+
+impl std::error::Error for SvgPathError {}
+
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, kind: SvgPathErrorKind) -> SvgPathError {
+        SvgPathError { kind, offset: self.pos }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r' | b'\x0c') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comma_whitespace(&mut self) {
+        self.skip_whitespace();
+        if self.peek() == Some(b',') {
+            self.pos += 1;
+            self.skip_whitespace();
+        }
+    }
+
+    fn at_number(&mut self) -> bool {
+        self.skip_whitespace();
+        matches!(self.peek(), Some(b'0'..=b'9' | b'.' | b'-' | b'+'))
+    }
+
+    fn skip_digits(&mut self) -> usize {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn number(&mut self) -> Result<f32, SvgPathError> {
+        self.skip_whitespace();
+        let start = self.pos;
+        if let Some(b'-' | b'+') = self.peek() {
+            self.pos += 1;
+        }
+        let mut digits = self.skip_digits();
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            digits += self.skip_digits();
+        }
+        if digits == 0 {
+            self.pos = start;
+            return Err(self.error(SvgPathErrorKind::ExpectedNumber));
+        }
+        // Only treat an 'e' as an exponent if it is followed by digits
+        if let Some(b'e' | b'E') = self.peek() {
+            let mantissa_end = self.pos;
+            self.pos += 1;
+            if let Some(b'-' | b'+') = self.peek() {
+                self.pos += 1;
+            }
+            if self.skip_digits() == 0 {
+                self.pos = mantissa_end;
+            }
+        }
+        // The scanned bytes are all ASCII so this can't fail
+        let text = std::str::from_utf8(&self.data[start..self.pos]).unwrap();
+        let value = text.parse().map_err(|_| SvgPathError { kind: SvgPathErrorKind::ExpectedNumber, offset: start })?;
+        self.skip_comma_whitespace();
+        Ok(value)
+    }
+
+    fn flag(&mut self) -> Result<bool, SvgPathError> {
+        self.skip_whitespace();
+        let value = match self.peek() {
+            Some(b'0') => false,
+            Some(b'1') => true,
+            _ => return Err(self.error(SvgPathErrorKind::ExpectedFlag)),
+        };
+        self.pos += 1;
+        self.skip_comma_whitespace();
+        Ok(value)
+    }
+
+    fn point(&mut self) -> Result<(f32, f32), SvgPathError> {
+        Ok((self.number()?, self.number()?))
+    }
+}
+
+pub(crate) fn parse(data: &str) -> Result<PathBuilder, SvgPathError> {
+    let mut builder = PathBuilder::new();
+    let mut parser = Parser { data: data.as_bytes(), pos: 0 };
+
+    // The current point, the start of the current subpath and the last control point of
+    // the previous command, which is reflected by the smooth curve commands.
+    let (mut x, mut y) = (0f32, 0f32);
+    let (mut start_x, mut start_y) = (0f32, 0f32);
+    let mut last_cubic_control: Option<(f32, f32)> = None;
+    let mut last_quad_control: Option<(f32, f32)> = None;
+    let mut command: Option<u8> = None;
+
+    loop {
+        parser.skip_whitespace();
+        let Some(next) = parser.peek() else {
+            break;
+        };
+        let offset = parser.pos;
+        let current = if next.is_ascii_alphabetic() {
+            parser.pos += 1;
+            next
+        } else {
+            match command {
+                None => return Err(parser.error(SvgPathErrorKind::ExpectedMoveTo)),
+                // Additional coordinate pairs after a moveto are implicit linetos
+                Some(b'M') if parser.at_number() => b'L',
+                Some(b'm') if parser.at_number() => b'l',
+                Some(c) if !c.eq_ignore_ascii_case(&b'Z') && parser.at_number() => c,
+                _ => return Err(parser.error(SvgPathErrorKind::ExpectedCommand)),
+            }
+        };
+        if command.is_none() && !current.eq_ignore_ascii_case(&b'M') {
+            return Err(SvgPathError { kind: SvgPathErrorKind::ExpectedMoveTo, offset });
+        }
+        parser.skip_whitespace();
+
+        let relative = current.is_ascii_lowercase();
+        let (base_x, base_y) = if relative { (x, y) } else { (0., 0.) };
+        let mut cubic_control = None;
+        let mut quad_control = None;
+        let absolute_command = current.to_ascii_uppercase();
+        match absolute_command {
+            b'M' => {
+                let (px, py) = parser.point()?;
+                x = base_x + px;
+                y = base_y + py;
+                start_x = x;
+                start_y = y;
+                builder.move_to(x, y);
+            }
+            b'L' => {
+                let (px, py) = parser.point()?;
+                x = base_x + px;
+                y = base_y + py;
+                builder.line_to(x, y);
+            }
+            b'H' => {
+                x = base_x + parser.number()?;
+                builder.line_to(x, y);
+            }
+            b'V' => {
+                y = base_y + parser.number()?;
+                builder.line_to(x, y);
+            }
+            b'C' | b'S' => {
+                let (c1x, c1y) = if absolute_command == b'C' {
+                    let (px, py) = parser.point()?;
+                    (base_x + px, base_y + py)
+                } else {
+                    match last_cubic_control {
+                        Some((cx, cy)) => (2. * x - cx, 2. * y - cy),
+                        None => (x, y),
+                    }
+                };
+                let (c2x, c2y) = parser.point()?;
+                let (px, py) = parser.point()?;
+                let (c2x, c2y) = (base_x + c2x, base_y + c2y);
+                x = base_x + px;
+                y = base_y + py;
+                builder.curve_to(c1x, c1y, c2x, c2y, x, y);
+                cubic_control = Some((c2x, c2y));
+            }
+            b'Q' | b'T' => {
+                let (cx, cy) = if absolute_command == b'Q' {
+                    let (px, py) = parser.point()?;
+                    (base_x + px, base_y + py)
+                } else {
+                    match last_quad_control {
+                        Some((cx, cy)) => (2. * x - cx, 2. * y - cy),
+                        None => (x, y),
+                    }
+                };
+                let (px, py) = parser.point()?;
+                x = base_x + px;
+                y = base_y + py;
+                builder.quad_to(cx, cy, x, y);
+                quad_control = Some((cx, cy));
+            }
+            b'A' => {
+                let rx = parser.number()?;
+                let ry = parser.number()?;
+                let x_axis_rotation = parser.number()?;
+                let large_arc = parser.flag()?;
+                let sweep = parser.flag()?;
+                let (px, py) = parser.point()?;
+                x = base_x + px;
+                y = base_y + py;
+                builder.arc_to(rx, ry, x_axis_rotation, large_arc, sweep, x, y);
+            }
+            b'Z' => {
+                builder.close();
+                x = start_x;
+                y = start_y;
+            }
+            _ => {
+                return Err(SvgPathError { kind: SvgPathErrorKind::ExpectedCommand, offset });
+            }
+        }
+        last_cubic_control = cubic_control;
+        last_quad_control = quad_control;
+        command = Some(current);
+    }
+    Ok(builder)
+}
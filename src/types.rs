@@ -90,10 +90,12 @@ pub struct MilPointAndSizeL
 
 pub type CMILSurfaceRect = RECT;
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum MilAntiAliasMode {
     None = 0,
     EightByEight = 1,
+    FourByFour = 2,
+    SixteenBySixteen = 3,
 }
 #[derive(PartialEq, Clone, Copy)]
 pub enum MilFillMode {
@@ -103,6 +105,7 @@ pub enum MilFillMode {
 
 pub const    PathPointTypeStart: u8           = 0;    // move, 1 point
 pub const    PathPointTypeLine: u8            = 1;    // line, 1 point
+pub const    PathPointTypeQuadratic: u8       = 2;    // quadratic Bezier, 2 points
 pub const    PathPointTypeBezier: u8          = 3;    // default Bezier (= cubic Bezier), 3 points
 pub const    PathPointTypePathTypeMask: u8    = 0x07; // type mask (lowest 3 bits).
 pub const    PathPointTypeCloseSubpath: u8    = 0x80; // closed flag